@@ -0,0 +1,9 @@
+CREATE TABLE sessions
+(
+    id BIGSERIAL PRIMARY KEY,
+    token text NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    CONSTRAINT session_token UNIQUE (token)
+);
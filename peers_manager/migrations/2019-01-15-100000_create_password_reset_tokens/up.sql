@@ -0,0 +1,9 @@
+CREATE TABLE password_reset_tokens
+(
+    id BIGSERIAL PRIMARY KEY,
+    token text NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    CONSTRAINT password_reset_token UNIQUE (token)
+);
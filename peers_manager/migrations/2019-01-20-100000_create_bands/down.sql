@@ -0,0 +1,3 @@
+DROP TABLE band_members;
+
+DROP TABLE bands;
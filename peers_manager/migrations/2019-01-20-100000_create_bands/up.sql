@@ -0,0 +1,21 @@
+CREATE TABLE bands
+(
+    id BIGSERIAL PRIMARY KEY,
+    name text NOT NULL,
+    description text NOT NULL,
+    genre text NOT NULL,
+    created_by BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE band_members
+(
+    band_id BIGINT NOT NULL REFERENCES bands (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role text NOT NULL,
+    joined_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (band_id, user_id),
+    CONSTRAINT band_member_role CHECK (role IN ('owner', 'admin', 'member'))
+);
+
+CREATE INDEX band_members_user_id ON band_members (user_id);
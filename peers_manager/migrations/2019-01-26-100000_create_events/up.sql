@@ -0,0 +1,33 @@
+CREATE TABLE events
+(
+    id BIGSERIAL PRIMARY KEY,
+    title text NOT NULL,
+    description text NOT NULL,
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP NOT NULL,
+    timezone text NOT NULL,
+    venue text NOT NULL,
+    organizer_user_id BIGINT REFERENCES users (id) ON DELETE CASCADE,
+    organizer_band_id BIGINT REFERENCES bands (id) ON DELETE CASCADE,
+    created_by BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    cancelled_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT event_schedule CHECK (ends_at >= starts_at),
+    CONSTRAINT event_organizer CHECK ((organizer_user_id IS NULL) <> (organizer_band_id IS NULL))
+);
+
+CREATE INDEX events_starts_at ON events (starts_at);
+CREATE INDEX events_organizer_user_id ON events (organizer_user_id);
+CREATE INDEX events_organizer_band_id ON events (organizer_band_id);
+
+CREATE TABLE event_attendees
+(
+    event_id BIGINT NOT NULL REFERENCES events (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    status text NOT NULL,
+    responded_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (event_id, user_id),
+    CONSTRAINT event_attendee_status CHECK (status IN ('going', 'maybe', 'declined'))
+);
+
+CREATE INDEX event_attendees_user_id ON event_attendees (user_id);
@@ -0,0 +1,21 @@
+CREATE TABLE invitations
+(
+    id BIGSERIAL PRIMARY KEY,
+    band_id BIGINT REFERENCES bands (id) ON DELETE CASCADE,
+    event_id BIGINT REFERENCES events (id) ON DELETE CASCADE,
+    invited_by BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    invitee_user_id BIGINT REFERENCES users (id) ON DELETE CASCADE,
+    invitee_email text,
+    status text NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    responded_at TIMESTAMP,
+    CONSTRAINT invitation_target CHECK ((band_id IS NULL) <> (event_id IS NULL)),
+    CONSTRAINT invitation_invitee CHECK (invitee_user_id IS NOT NULL OR invitee_email IS NOT NULL),
+    CONSTRAINT invitation_status CHECK (status IN ('pending', 'accepted', 'declined', 'expired', 'revoked'))
+);
+
+CREATE INDEX invitations_invitee_user_id ON invitations (invitee_user_id);
+CREATE INDEX invitations_invitee_email ON invitations (invitee_email) WHERE invitee_user_id IS NULL;
+CREATE INDEX invitations_band_id ON invitations (band_id);
+CREATE INDEX invitations_event_id ON invitations (event_id);
@@ -0,0 +1,7 @@
+DROP INDEX users_search_vector;
+
+DROP TRIGGER users_search_vector_update ON users;
+
+DROP FUNCTION users_search_vector_update();
+
+ALTER TABLE users DROP COLUMN search_vector;
@@ -0,0 +1,22 @@
+-- Kept up to date by the trigger below, not declared in src/schema.rs since
+-- diesel has no type for tsvector; queries refer to it through `sql`.
+ALTER TABLE users ADD COLUMN search_vector tsvector;
+
+CREATE FUNCTION users_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector :=
+        setweight(to_tsvector('simple', coalesce(NEW.name, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(NEW.about, '')), 'B');
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_search_vector_update
+    BEFORE INSERT OR UPDATE OF name, about ON users
+    FOR EACH ROW EXECUTE PROCEDURE users_search_vector_update();
+
+UPDATE users SET search_vector =
+    setweight(to_tsvector('simple', coalesce(name, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(about, '')), 'B');
+
+CREATE INDEX users_search_vector ON users USING GIN (search_vector);
@@ -0,0 +1,6 @@
+-- Site wide role, see models::UserRole
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user'
+    CONSTRAINT user_role CHECK (role IN ('user', 'moderator', 'admin'));
+
+-- Set while an admin keeps the account suspended
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP;
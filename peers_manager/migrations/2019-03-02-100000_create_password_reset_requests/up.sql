@@ -0,0 +1,10 @@
+-- Password resets asked for each email, registered or not, counted against
+-- the hourly limit. Redeeming a token doesn't remove them.
+CREATE TABLE password_reset_requests
+(
+    id BIGSERIAL PRIMARY KEY,
+    email TEXT NOT NULL,
+    requested_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX password_reset_requests_email ON password_reset_requests (email, requested_at);
@@ -0,0 +1,12 @@
+-- Bands and events of deleted users can't be kept without a creator
+DELETE FROM events WHERE created_by IS NULL;
+ALTER TABLE events DROP CONSTRAINT events_created_by_fkey;
+ALTER TABLE events ADD CONSTRAINT events_created_by_fkey
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE events ALTER COLUMN created_by SET NOT NULL;
+
+DELETE FROM bands WHERE created_by IS NULL;
+ALTER TABLE bands DROP CONSTRAINT bands_created_by_fkey;
+ALTER TABLE bands ADD CONSTRAINT bands_created_by_fkey
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE bands ALTER COLUMN created_by SET NOT NULL;
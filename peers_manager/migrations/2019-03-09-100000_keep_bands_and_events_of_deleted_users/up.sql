@@ -0,0 +1,12 @@
+-- Bands and events outlive the account which created them, their members
+-- and attendees are still there. Events organized by the user in person, not
+-- by a band, are still deleted with it: an event has exactly one organizer.
+ALTER TABLE bands ALTER COLUMN created_by DROP NOT NULL;
+ALTER TABLE bands DROP CONSTRAINT bands_created_by_fkey;
+ALTER TABLE bands ADD CONSTRAINT bands_created_by_fkey
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL;
+
+ALTER TABLE events ALTER COLUMN created_by DROP NOT NULL;
+ALTER TABLE events DROP CONSTRAINT events_created_by_fkey;
+ALTER TABLE events ADD CONSTRAINT events_created_by_fkey
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL;
@@ -0,0 +1,12 @@
+-- Same columns as the users of the Postgres migrations, without the
+-- search_vector: SQLite searches the loaded users, see src/db/search.rs.
+CREATE TABLE users
+(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    about TEXT NOT NULL,
+    email TEXT NOT NULL,
+    password_hash TEXT NOT NULL DEFAULT '',
+    email_verified BOOLEAN NOT NULL DEFAULT 0,
+    CONSTRAINT email UNIQUE (email)
+);
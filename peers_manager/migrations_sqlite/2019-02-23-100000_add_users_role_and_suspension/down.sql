@@ -0,0 +1,20 @@
+-- SQLite can't drop columns, the users are copied to a table without them.
+-- Foreign keys are off on the connections of `peers_manager migrate`, the
+-- sessions and tokens are kept.
+CREATE TABLE users_without_roles
+(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    about TEXT NOT NULL,
+    email TEXT NOT NULL,
+    password_hash TEXT NOT NULL DEFAULT '',
+    email_verified BOOLEAN NOT NULL DEFAULT 0,
+    CONSTRAINT email UNIQUE (email)
+);
+
+INSERT INTO users_without_roles (id, name, about, email, password_hash, email_verified)
+    SELECT id, name, about, email, password_hash, email_verified FROM users;
+
+DROP TABLE users;
+
+ALTER TABLE users_without_roles RENAME TO users;
@@ -0,0 +1,8 @@
+CREATE TABLE password_reset_requests
+(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email TEXT NOT NULL,
+    requested_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX password_reset_requests_email ON password_reset_requests (email, requested_at);
@@ -0,0 +1,29 @@
+//! Roles and suspension of accounts
+//!
+//! Suspending an account closes its sessions, it can't log in again until it
+//! is restored. Its profile, bands and events are kept.
+use actix::prelude::*;
+
+use super::DbError;
+use models::{self, UserRole};
+
+/// Suspends or restores the account of user `id`, `None` when there is no
+/// such user
+pub struct SetSuspended {
+    pub id: i64,
+    pub suspended: bool,
+}
+
+impl Message for SetSuspended {
+    type Result = Result<Option<models::User>, DbError>;
+}
+
+/// Gives user `id` the `role`, `None` when there is no such user
+pub struct SetRole {
+    pub id: i64,
+    pub role: UserRole,
+}
+
+impl Message for SetRole {
+    type Result = Result<Option<models::User>, DbError>;
+}
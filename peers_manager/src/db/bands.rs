@@ -0,0 +1,430 @@
+//! Bands and their members
+use actix::prelude::*;
+use diesel;
+use diesel::prelude::*;
+
+use super::users::DbExecutor;
+use super::DbError;
+use models;
+use models::BandRole;
+use schema;
+
+#[derive(Debug)]
+pub enum BandError {
+    NotFound,
+    MemberNotFound,
+    NotAllowed,
+    /// A band always keeps at least one owner
+    LastOwner,
+    DbError(DbError),
+}
+
+db_error_conversions!(BandError);
+
+/// Role of the user in the band, `None` when not a member
+pub fn member_role(
+    conn: &PgConnection,
+    of_band_id: i64,
+    of_user_id: i64,
+) -> Result<Option<BandRole>, diesel::result::Error> {
+    use self::schema::band_members::dsl::*;
+
+    let member_role = band_members
+        .find((of_band_id, of_user_id))
+        .select(role)
+        .first::<String>(conn)
+        .optional()?;
+
+    Ok(member_role.and_then(|member_role| BandRole::parse(&member_role)))
+}
+
+/// Checks that the band exists and the user's role in it passes `allowed`
+fn require_role<F>(
+    conn: &PgConnection,
+    band_id: i64,
+    user_id: i64,
+    allowed: F,
+) -> Result<BandRole, BandError>
+where
+    F: Fn(BandRole) -> bool,
+{
+    let band_exists = schema::bands::table
+        .find(band_id)
+        .select(schema::bands::id)
+        .first::<i64>(conn)
+        .optional()?
+        .is_some();
+
+    if !band_exists {
+        return Err(BandError::NotFound);
+    }
+
+    match member_role(conn, band_id, user_id)? {
+        Some(role) if allowed(role) => Ok(role),
+        _ => Err(BandError::NotAllowed),
+    }
+}
+
+/// Number of owners of the band, their rows stay locked until the transaction
+/// ends so that two owners can't both step down as the last but one
+fn owners_count(conn: &PgConnection, of_band_id: i64) -> Result<i64, diesel::result::Error> {
+    use self::schema::band_members::dsl::*;
+
+    let owners = band_members
+        .filter(band_id.eq(of_band_id))
+        .filter(role.eq(BandRole::Owner.as_str()))
+        .select(user_id)
+        .for_update()
+        .load::<i64>(conn)?;
+
+    Ok(owners.len() as i64)
+}
+
+/// Whether the user is the only owner of one of its bands, the owners of these
+/// bands stay locked until the transaction ends
+pub fn owns_band_alone(conn: &PgConnection, owner_id: i64) -> Result<bool, diesel::result::Error> {
+    use self::schema::band_members::dsl::*;
+
+    let owned_bands = band_members
+        .filter(user_id.eq(owner_id))
+        .filter(role.eq(BandRole::Owner.as_str()))
+        .select(band_id)
+        .for_update()
+        .load::<i64>(conn)?;
+    // one row per owner of each band
+    let owners_of = band_members
+        .filter(band_id.eq_any(owned_bands))
+        .filter(role.eq(BandRole::Owner.as_str()))
+        .select(band_id)
+        .for_update()
+        .load::<i64>(conn)?;
+
+    Ok(owners_of
+        .iter()
+        .any(|owned| owners_of.iter().filter(|band| *band == owned).count() == 1))
+}
+
+/// Adds the user to the band or changes its role when already a member
+pub fn upsert_band_member(
+    conn: &PgConnection,
+    to_band_id: i64,
+    new_user_id: i64,
+    new_role: BandRole,
+) -> Result<models::BandMember, diesel::result::Error> {
+    use self::schema::band_members::dsl::*;
+
+    let new_member = models::NewBandMember {
+        band_id: to_band_id,
+        user_id: new_user_id,
+        role: new_role.as_str(),
+    };
+
+    diesel::insert_into(band_members)
+        .values(&new_member)
+        .on_conflict((band_id, user_id))
+        .do_update()
+        .set(role.eq(new_role.as_str()))
+        .get_result::<models::BandMember>(conn)
+}
+
+/// Creates a band owned by its creator
+pub struct CreateBand {
+    pub created_by: i64,
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+}
+
+impl Message for CreateBand {
+    type Result = Result<models::Band, BandError>;
+}
+
+impl Handler<CreateBand> for DbExecutor {
+    type Result = Result<models::Band, BandError>;
+
+    fn handle(&mut self, msg: CreateBand, _: &mut Self::Context) -> Self::Result {
+        use self::schema::bands::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        let new_band = models::NewBand {
+            name: &msg.name,
+            description: &msg.description,
+            genre: &msg.genre,
+            created_by: msg.created_by,
+        };
+
+        conn.transaction(|| {
+            let band = diesel::insert_into(bands)
+                .values(&new_band)
+                .get_result::<models::Band>(conn)?;
+
+            upsert_band_member(conn, band.id, msg.created_by, BandRole::Owner)?;
+
+            Ok(band)
+        })
+    }
+}
+
+pub struct GetBand {
+    pub id: i64,
+}
+
+impl Message for GetBand {
+    type Result = Result<models::Band, BandError>;
+}
+
+impl Handler<GetBand> for DbExecutor {
+    type Result = Result<models::Band, BandError>;
+
+    fn handle(&mut self, msg: GetBand, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        schema::bands::table
+            .find(msg.id)
+            .first::<models::Band>(conn)
+            .optional()?
+            .ok_or(BandError::NotFound)
+    }
+}
+
+/// Lists all bands, or only those the given user is a member of
+#[derive(Deserialize)]
+pub struct ListBands {
+    pub member: Option<i64>,
+}
+
+impl Message for ListBands {
+    type Result = Result<Vec<models::Band>, BandError>;
+}
+
+impl Handler<ListBands> for DbExecutor {
+    type Result = Result<Vec<models::Band>, BandError>;
+
+    fn handle(&mut self, msg: ListBands, _: &mut Self::Context) -> Self::Result {
+        use self::schema::bands::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        let mut query = bands.order(name.asc()).into_boxed();
+
+        if let Some(member) = msg.member {
+            let member_band_ids = schema::band_members::table
+                .filter(schema::band_members::user_id.eq(member))
+                .select(schema::band_members::band_id);
+            query = query.filter(id.eq_any(member_band_ids));
+        }
+
+        Ok(query.load::<models::Band>(conn)?)
+    }
+}
+
+/// Changes the given fields of a band, allowed to owners and admins
+pub struct UpdateBand {
+    pub id: i64,
+    pub acting_user_id: i64,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub genre: Option<String>,
+}
+
+impl Message for UpdateBand {
+    type Result = Result<models::Band, BandError>;
+}
+
+impl Handler<UpdateBand> for DbExecutor {
+    type Result = Result<models::Band, BandError>;
+
+    fn handle(&mut self, msg: UpdateBand, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        require_role(conn, msg.id, msg.acting_user_id, |role| role.can_manage())?;
+
+        let changes = models::BandChanges {
+            name: msg.name.as_ref().map(|new_name| new_name.as_str()),
+            description: msg
+                .description
+                .as_ref()
+                .map(|new_description| new_description.as_str()),
+            genre: msg.genre.as_ref().map(|new_genre| new_genre.as_str()),
+        };
+
+        let band = if changes.is_empty() {
+            schema::bands::table
+                .find(msg.id)
+                .first::<models::Band>(conn)?
+        } else {
+            diesel::update(schema::bands::table.find(msg.id))
+                .set(&changes)
+                .get_result::<models::Band>(conn)?
+        };
+
+        Ok(band)
+    }
+}
+
+/// Deletes a band with its memberships, allowed to owners only
+pub struct DeleteBand {
+    pub id: i64,
+    pub acting_user_id: i64,
+}
+
+impl Message for DeleteBand {
+    type Result = Result<(), BandError>;
+}
+
+impl Handler<DeleteBand> for DbExecutor {
+    type Result = Result<(), BandError>;
+
+    fn handle(&mut self, msg: DeleteBand, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        require_role(conn, msg.id, msg.acting_user_id, |role| {
+            role == BandRole::Owner
+        })?;
+
+        diesel::delete(schema::bands::table.find(msg.id)).execute(conn)?;
+
+        Ok(())
+    }
+}
+
+pub struct ListBandMembers {
+    pub band_id: i64,
+}
+
+impl Message for ListBandMembers {
+    type Result = Result<Vec<models::BandMember>, BandError>;
+}
+
+impl Handler<ListBandMembers> for DbExecutor {
+    type Result = Result<Vec<models::BandMember>, BandError>;
+
+    fn handle(&mut self, msg: ListBandMembers, _: &mut Self::Context) -> Self::Result {
+        use self::schema::band_members::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        let band_exists = schema::bands::table
+            .find(msg.band_id)
+            .select(schema::bands::id)
+            .first::<i64>(conn)
+            .optional()?
+            .is_some();
+
+        if !band_exists {
+            return Err(BandError::NotFound);
+        }
+
+        Ok(band_members
+            .filter(band_id.eq(msg.band_id))
+            .order(joined_at.asc())
+            .load::<models::BandMember>(conn)?)
+    }
+}
+
+/// Adds a member or changes its role. Owners and admins manage members, only
+/// owners may grant or take away the owner role.
+pub struct SetBandMember {
+    pub band_id: i64,
+    pub acting_user_id: i64,
+    pub user_id: i64,
+    pub role: BandRole,
+}
+
+impl Message for SetBandMember {
+    type Result = Result<models::BandMember, BandError>;
+}
+
+impl Handler<SetBandMember> for DbExecutor {
+    type Result = Result<models::BandMember, BandError>;
+
+    fn handle(&mut self, msg: SetBandMember, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        conn.transaction(|| {
+            let acting_role = require_role(conn, msg.band_id, msg.acting_user_id, |role| {
+                role.can_manage()
+            })?;
+
+            let user_exists = schema::users::table
+                .find(msg.user_id)
+                .select(schema::users::id)
+                .first::<i64>(conn)
+                .optional()?
+                .is_some();
+
+            if !user_exists {
+                return Err(BandError::MemberNotFound);
+            }
+
+            let current_role = member_role(conn, msg.band_id, msg.user_id)?;
+            let touches_owner =
+                msg.role == BandRole::Owner || current_role == Some(BandRole::Owner);
+
+            if touches_owner && acting_role != BandRole::Owner {
+                return Err(BandError::NotAllowed);
+            }
+
+            if current_role == Some(BandRole::Owner)
+                && msg.role != BandRole::Owner
+                && owners_count(conn, msg.band_id)? <= 1
+            {
+                return Err(BandError::LastOwner);
+            }
+
+            Ok(upsert_band_member(
+                conn,
+                msg.band_id,
+                msg.user_id,
+                msg.role,
+            )?)
+        })
+    }
+}
+
+/// Removes a member, members may also leave on their own
+pub struct RemoveBandMember {
+    pub band_id: i64,
+    pub acting_user_id: i64,
+    pub user_id: i64,
+}
+
+impl Message for RemoveBandMember {
+    type Result = Result<(), BandError>;
+}
+
+impl Handler<RemoveBandMember> for DbExecutor {
+    type Result = Result<(), BandError>;
+
+    fn handle(&mut self, msg: RemoveBandMember, _: &mut Self::Context) -> Self::Result {
+        use self::schema::band_members::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        conn.transaction(|| {
+            let leaving = msg.acting_user_id == msg.user_id;
+            let acting_role = require_role(conn, msg.band_id, msg.acting_user_id, |acting_role| {
+                leaving || acting_role.can_manage()
+            })?;
+
+            let removed_role = match member_role(conn, msg.band_id, msg.user_id)? {
+                Some(removed_role) => removed_role,
+                None => return Err(BandError::MemberNotFound),
+            };
+
+            if removed_role == BandRole::Owner {
+                if !leaving && acting_role != BandRole::Owner {
+                    return Err(BandError::NotAllowed);
+                }
+                if owners_count(conn, msg.band_id)? <= 1 {
+                    return Err(BandError::LastOwner);
+                }
+            }
+
+            diesel::delete(band_members.find((msg.band_id, msg.user_id))).execute(conn)?;
+
+            Ok(())
+        })
+    }
+}
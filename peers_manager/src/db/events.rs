@@ -0,0 +1,377 @@
+//! Events and their attendees
+use actix::prelude::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+
+use super::bands::member_role;
+use super::users::DbExecutor;
+use super::DbError;
+use models;
+use models::RsvpStatus;
+use schema;
+
+#[derive(Debug)]
+pub enum EventError {
+    NotFound,
+    BandNotFound,
+    NotAllowed,
+    /// The event ends before it starts
+    InvalidSchedule,
+    Cancelled,
+    DbError(DbError),
+}
+
+db_error_conversions!(EventError);
+
+fn find_event(conn: &PgConnection, event_id: i64) -> Result<models::Event, EventError> {
+    schema::events::table
+        .find(event_id)
+        .first::<models::Event>(conn)
+        .optional()?
+        .ok_or(EventError::NotFound)
+}
+
+/// Events organized by a user are managed by that user, events organized by a
+/// band by the band's owners and admins.
+pub fn can_manage_event(
+    conn: &PgConnection,
+    event: &models::Event,
+    user_id: i64,
+) -> Result<bool, diesel::result::Error> {
+    match (event.organizer_user_id, event.organizer_band_id) {
+        (Some(organizer_user_id), _) => Ok(organizer_user_id == user_id),
+        (None, Some(organizer_band_id)) => {
+            Ok(member_role(conn, organizer_band_id, user_id)?
+                .map_or(false, |role| role.can_manage()))
+        }
+        (None, None) => Ok(false),
+    }
+}
+
+/// Event which can still be changed by the user
+fn find_managed_event(
+    conn: &PgConnection,
+    event_id: i64,
+    user_id: i64,
+) -> Result<models::Event, EventError> {
+    let event = find_event(conn, event_id)?;
+
+    if !can_manage_event(conn, &event, user_id)? {
+        return Err(EventError::NotAllowed);
+    }
+
+    if event.cancelled_at.is_some() {
+        return Err(EventError::Cancelled);
+    }
+
+    Ok(event)
+}
+
+/// Creates an event organized by its creator, or by a band the creator manages
+pub struct CreateEvent {
+    pub created_by: i64,
+    pub title: String,
+    pub description: String,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub timezone: String,
+    pub venue: String,
+    pub band_id: Option<i64>,
+}
+
+impl Message for CreateEvent {
+    type Result = Result<models::Event, EventError>;
+}
+
+impl Handler<CreateEvent> for DbExecutor {
+    type Result = Result<models::Event, EventError>;
+
+    fn handle(&mut self, msg: CreateEvent, _: &mut Self::Context) -> Self::Result {
+        use self::schema::events::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        if msg.ends_at < msg.starts_at {
+            return Err(EventError::InvalidSchedule);
+        }
+
+        if let Some(band_id) = msg.band_id {
+            let band_exists = schema::bands::table
+                .find(band_id)
+                .select(schema::bands::id)
+                .first::<i64>(conn)
+                .optional()?
+                .is_some();
+
+            if !band_exists {
+                return Err(EventError::BandNotFound);
+            }
+
+            let role = member_role(conn, band_id, msg.created_by)?;
+            if !role.map_or(false, |role| role.can_manage()) {
+                return Err(EventError::NotAllowed);
+            }
+        }
+
+        let new_event = models::NewEvent {
+            title: &msg.title,
+            description: &msg.description,
+            starts_at: msg.starts_at,
+            ends_at: msg.ends_at,
+            timezone: &msg.timezone,
+            venue: &msg.venue,
+            organizer_user_id: match msg.band_id {
+                Some(_) => None,
+                None => Some(msg.created_by),
+            },
+            organizer_band_id: msg.band_id,
+            created_by: msg.created_by,
+        };
+
+        Ok(diesel::insert_into(events)
+            .values(&new_event)
+            .get_result::<models::Event>(conn)?)
+    }
+}
+
+pub struct GetEvent {
+    pub id: i64,
+}
+
+impl Message for GetEvent {
+    type Result = Result<models::Event, EventError>;
+}
+
+impl Handler<GetEvent> for DbExecutor {
+    type Result = Result<models::Event, EventError>;
+
+    fn handle(&mut self, msg: GetEvent, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        find_event(conn, msg.id)
+    }
+}
+
+/// Changes the given fields of an event which is not cancelled
+pub struct UpdateEvent {
+    pub id: i64,
+    pub acting_user_id: i64,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub timezone: Option<String>,
+    pub venue: Option<String>,
+}
+
+impl Message for UpdateEvent {
+    type Result = Result<models::Event, EventError>;
+}
+
+impl Handler<UpdateEvent> for DbExecutor {
+    type Result = Result<models::Event, EventError>;
+
+    fn handle(&mut self, msg: UpdateEvent, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        conn.transaction(|| {
+            let event = find_managed_event(conn, msg.id, msg.acting_user_id)?;
+
+            let new_starts_at = msg.starts_at.unwrap_or(event.starts_at);
+            let new_ends_at = msg.ends_at.unwrap_or(event.ends_at);
+            if new_ends_at < new_starts_at {
+                return Err(EventError::InvalidSchedule);
+            }
+
+            let changes = models::EventChanges {
+                title: msg.title.as_ref().map(|new_title| new_title.as_str()),
+                description: msg
+                    .description
+                    .as_ref()
+                    .map(|new_description| new_description.as_str()),
+                starts_at: msg.starts_at,
+                ends_at: msg.ends_at,
+                timezone: msg
+                    .timezone
+                    .as_ref()
+                    .map(|new_timezone| new_timezone.as_str()),
+                venue: msg.venue.as_ref().map(|new_venue| new_venue.as_str()),
+            };
+
+            if changes.is_empty() {
+                return Ok(event);
+            }
+
+            Ok(diesel::update(schema::events::table.find(msg.id))
+                .set(&changes)
+                .get_result::<models::Event>(conn)?)
+        })
+    }
+}
+
+/// Marks an event as cancelled, it is kept for its attendees to see
+pub struct CancelEvent {
+    pub id: i64,
+    pub acting_user_id: i64,
+}
+
+impl Message for CancelEvent {
+    type Result = Result<models::Event, EventError>;
+}
+
+impl Handler<CancelEvent> for DbExecutor {
+    type Result = Result<models::Event, EventError>;
+
+    fn handle(&mut self, msg: CancelEvent, _: &mut Self::Context) -> Self::Result {
+        use self::schema::events::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        conn.transaction(|| {
+            find_managed_event(conn, msg.id, msg.acting_user_id)?;
+
+            Ok(diesel::update(events.find(msg.id))
+                .set(cancelled_at.eq(Utc::now().naive_utc()))
+                .get_result::<models::Event>(conn)?)
+        })
+    }
+}
+
+/// Lists events which did not end yet and are not cancelled, by start time.
+///
+/// The events of a user are the ones it organizes, attends (going or maybe)
+/// or which are organized by its bands.
+#[derive(Deserialize)]
+pub struct ListUpcomingEvents {
+    pub user: Option<i64>,
+    pub band: Option<i64>,
+}
+
+impl Message for ListUpcomingEvents {
+    type Result = Result<Vec<models::Event>, EventError>;
+}
+
+impl Handler<ListUpcomingEvents> for DbExecutor {
+    type Result = Result<Vec<models::Event>, EventError>;
+
+    fn handle(&mut self, msg: ListUpcomingEvents, _: &mut Self::Context) -> Self::Result {
+        use self::schema::events::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        let mut query = events
+            .filter(ends_at.ge(Utc::now().naive_utc()))
+            .filter(cancelled_at.is_null())
+            .order(starts_at.asc())
+            .into_boxed();
+
+        if let Some(user) = msg.user {
+            let attending = schema::event_attendees::table
+                .filter(schema::event_attendees::user_id.eq(user))
+                .filter(
+                    schema::event_attendees::status
+                        .eq_any(vec![RsvpStatus::Going.as_str(), RsvpStatus::Maybe.as_str()]),
+                )
+                .select(schema::event_attendees::event_id);
+            let member_of = schema::band_members::table
+                .filter(schema::band_members::user_id.eq(user))
+                .select(schema::band_members::band_id.nullable());
+
+            query = query.filter(
+                organizer_user_id
+                    .eq(user)
+                    .or(id.eq_any(attending))
+                    .or(organizer_band_id.eq_any(member_of)),
+            );
+        }
+
+        if let Some(band) = msg.band {
+            query = query.filter(organizer_band_id.eq(band));
+        }
+
+        Ok(query.load::<models::Event>(conn)?)
+    }
+}
+
+/// Sets the answer of a user to an event which is not cancelled
+pub struct SetRsvp {
+    pub event_id: i64,
+    pub user_id: i64,
+    pub status: RsvpStatus,
+}
+
+impl Message for SetRsvp {
+    type Result = Result<models::EventAttendee, EventError>;
+}
+
+/// Records the answer of the user, replacing a previous one
+pub fn upsert_attendee(
+    conn: &PgConnection,
+    to_event_id: i64,
+    new_user_id: i64,
+    new_status: RsvpStatus,
+) -> Result<models::EventAttendee, diesel::result::Error> {
+    use self::schema::event_attendees::dsl::*;
+
+    let new_attendee = models::NewEventAttendee {
+        event_id: to_event_id,
+        user_id: new_user_id,
+        status: new_status.as_str(),
+    };
+
+    diesel::insert_into(event_attendees)
+        .values(&new_attendee)
+        .on_conflict((event_id, user_id))
+        .do_update()
+        .set((
+            status.eq(new_status.as_str()),
+            responded_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result::<models::EventAttendee>(conn)
+}
+
+impl Handler<SetRsvp> for DbExecutor {
+    type Result = Result<models::EventAttendee, EventError>;
+
+    fn handle(&mut self, msg: SetRsvp, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        let event = find_event(conn, msg.event_id)?;
+        if event.cancelled_at.is_some() {
+            return Err(EventError::Cancelled);
+        }
+
+        Ok(upsert_attendee(
+            conn,
+            msg.event_id,
+            msg.user_id,
+            msg.status,
+        )?)
+    }
+}
+
+pub struct ListAttendees {
+    pub event_id: i64,
+}
+
+impl Message for ListAttendees {
+    type Result = Result<Vec<models::EventAttendee>, EventError>;
+}
+
+impl Handler<ListAttendees> for DbExecutor {
+    type Result = Result<Vec<models::EventAttendee>, EventError>;
+
+    fn handle(&mut self, msg: ListAttendees, _: &mut Self::Context) -> Self::Result {
+        use self::schema::event_attendees::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        find_event(conn, msg.event_id)?;
+
+        Ok(event_attendees
+            .filter(event_id.eq(msg.event_id))
+            .order(responded_at.asc())
+            .load::<models::EventAttendee>(conn)?)
+    }
+}
@@ -0,0 +1,396 @@
+//! Invitations to join a band or to attend an event
+use actix::prelude::*;
+use chrono::{Duration, Utc};
+use diesel;
+use diesel::prelude::*;
+
+use super::bands::{member_role, upsert_band_member};
+use super::events::{can_manage_event, upsert_attendee};
+use super::lower;
+use super::users::DbExecutor;
+use super::DbError;
+use models;
+use models::{BandRole, InvitationStatus, RsvpStatus};
+use schema;
+
+/// How long an invitation can be answered before it expires.
+pub const INVITATION_TTL_DAYS: i64 = 14;
+
+#[derive(Debug)]
+pub enum InvitationError {
+    NotFound,
+    /// The band or event of the invitation doesn't exist
+    TargetNotFound,
+    InviteeNotFound,
+    NotAllowed,
+    /// The invitation has to name either a band or an event
+    InvalidTarget,
+    AlreadyInvited,
+    AlreadyMember,
+    /// The invitation was already answered or revoked
+    NotPending,
+    Expired,
+    EventCancelled,
+    DbError(DbError),
+}
+
+db_error_conversions!(InvitationError);
+
+/// Marks pending invitations past their expiry date as expired
+fn expire_invitations(conn: &PgConnection) -> Result<usize, diesel::result::Error> {
+    use self::schema::invitations::dsl::*;
+
+    diesel::update(
+        invitations
+            .filter(status.eq(InvitationStatus::Pending.as_str()))
+            .filter(expires_at.le(Utc::now().naive_utc())),
+    )
+    .set(status.eq(InvitationStatus::Expired.as_str()))
+    .execute(conn)
+}
+
+/// Whether the user may invite others to the band or event and revoke
+/// invitations to it
+fn can_invite(
+    conn: &PgConnection,
+    invitation_band_id: Option<i64>,
+    invitation_event_id: Option<i64>,
+    user_id: i64,
+) -> Result<bool, InvitationError> {
+    match (invitation_band_id, invitation_event_id) {
+        (Some(to_band_id), None) => {
+            let band_exists = schema::bands::table
+                .find(to_band_id)
+                .select(schema::bands::id)
+                .first::<i64>(conn)
+                .optional()?
+                .is_some();
+
+            if !band_exists {
+                return Err(InvitationError::TargetNotFound);
+            }
+
+            Ok(member_role(conn, to_band_id, user_id)?.map_or(false, |role| role.can_manage()))
+        }
+        (None, Some(to_event_id)) => {
+            let event = schema::events::table
+                .find(to_event_id)
+                .first::<models::Event>(conn)
+                .optional()?
+                .ok_or(InvitationError::TargetNotFound)?;
+
+            if event.cancelled_at.is_some() {
+                return Err(InvitationError::EventCancelled);
+            }
+
+            Ok(can_manage_event(conn, &event, user_id)?)
+        }
+        _ => Err(InvitationError::InvalidTarget),
+    }
+}
+
+/// Invitation addressed to the user which can still be answered, locked
+/// until the end of the transaction
+fn find_pending_invitation(
+    conn: &PgConnection,
+    invitation_id: i64,
+    user_id: i64,
+) -> Result<models::Invitation, InvitationError> {
+    let invitation = schema::invitations::table
+        .find(invitation_id)
+        .for_update()
+        .first::<models::Invitation>(conn)
+        .optional()?
+        .ok_or(InvitationError::NotFound)?;
+
+    // invitations of other users are none of the user's business
+    if invitation.invitee_user_id != Some(user_id) {
+        return Err(InvitationError::NotFound);
+    }
+
+    if invitation.status == InvitationStatus::Expired.as_str() {
+        return Err(InvitationError::Expired);
+    }
+
+    if invitation.status != InvitationStatus::Pending.as_str() {
+        return Err(InvitationError::NotPending);
+    }
+
+    Ok(invitation)
+}
+
+fn set_status(
+    conn: &PgConnection,
+    invitation_id: i64,
+    new_status: InvitationStatus,
+) -> Result<models::Invitation, diesel::result::Error> {
+    use self::schema::invitations::dsl::*;
+
+    diesel::update(invitations.find(invitation_id))
+        .set((
+            status.eq(new_status.as_str()),
+            responded_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result::<models::Invitation>(conn)
+}
+
+/// Attaches the pending invitations sent to the email to the user who just
+/// verified it, they show up in its inbox from then on. Anyone can register
+/// an email, so it is not enough to claim them. `email` is canonical, as
+/// the emails of the invitations are.
+pub fn claim_email_invitations(
+    conn: &PgConnection,
+    verified_user_id: i64,
+    email: &str,
+) -> Result<usize, diesel::result::Error> {
+    use self::schema::invitations::dsl::*;
+
+    diesel::update(
+        invitations
+            .filter(invitee_user_id.is_null())
+            .filter(invitee_email.eq(email.to_lowercase()))
+            .filter(status.eq(InvitationStatus::Pending.as_str())),
+    )
+    .set(invitee_user_id.eq(verified_user_id))
+    .execute(conn)
+}
+
+/// Invites a user, by id or by email, to a band or an event. Emails which are
+/// not registered and verified yet are invited as is, see
+/// `claim_email_invitations`.
+pub struct CreateInvitation {
+    pub invited_by: i64,
+    pub band_id: Option<i64>,
+    pub event_id: Option<i64>,
+    pub user_id: Option<i64>,
+    /// Canonical, folded by the `Canonicalization` of the email validator as
+    /// the emails of the users are
+    pub email: Option<String>,
+}
+
+impl Message for CreateInvitation {
+    type Result = Result<models::Invitation, InvitationError>;
+}
+
+impl Handler<CreateInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, InvitationError>;
+
+    fn handle(&mut self, msg: CreateInvitation, _: &mut Self::Context) -> Self::Result {
+        use self::schema::invitations::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        conn.transaction(|| {
+            if !can_invite(conn, msg.band_id, msg.event_id, msg.invited_by)? {
+                return Err(InvitationError::NotAllowed);
+            }
+
+            let new_invitee_email = msg.email.as_ref().map(|email| email.to_lowercase());
+
+            let new_invitee_user_id = match (msg.user_id, &new_invitee_email) {
+                (Some(user_id), _) => schema::users::table
+                    .find(user_id)
+                    .select(schema::users::id)
+                    .first::<i64>(conn)
+                    .optional()?
+                    .ok_or(InvitationError::InviteeNotFound)
+                    .map(Some)?,
+                (None, Some(email)) => schema::users::table
+                    .filter(lower(schema::users::email).eq(email))
+                    .filter(schema::users::email_verified.eq(true))
+                    .select(schema::users::id)
+                    .first::<i64>(conn)
+                    .optional()?,
+                (None, None) => return Err(InvitationError::InviteeNotFound),
+            };
+
+            if let (Some(to_band_id), Some(user_id)) = (msg.band_id, new_invitee_user_id) {
+                if member_role(conn, to_band_id, user_id)?.is_some() {
+                    return Err(InvitationError::AlreadyMember);
+                }
+            }
+
+            expire_invitations(conn)?;
+
+            let mut pending = invitations
+                .filter(status.eq(InvitationStatus::Pending.as_str()))
+                .select(id)
+                .into_boxed();
+            pending = match (msg.band_id, msg.event_id) {
+                (Some(to_band_id), _) => pending.filter(band_id.eq(to_band_id)),
+                (_, Some(to_event_id)) => pending.filter(event_id.eq(to_event_id)),
+                (None, None) => return Err(InvitationError::InvalidTarget),
+            };
+            pending = match (new_invitee_user_id, &new_invitee_email) {
+                (Some(user_id), _) => pending.filter(invitee_user_id.eq(user_id)),
+                (None, Some(email)) => pending.filter(invitee_email.eq(email)),
+                (None, None) => return Err(InvitationError::InviteeNotFound),
+            };
+
+            if pending.first::<i64>(conn).optional()?.is_some() {
+                return Err(InvitationError::AlreadyInvited);
+            }
+
+            let new_invitation = models::NewInvitation {
+                band_id: msg.band_id,
+                event_id: msg.event_id,
+                invited_by: msg.invited_by,
+                invitee_user_id: new_invitee_user_id,
+                invitee_email: new_invitee_email.as_ref().map(|email| email.as_str()),
+                expires_at: (Utc::now() + Duration::days(INVITATION_TTL_DAYS)).naive_utc(),
+            };
+
+            Ok(diesel::insert_into(invitations)
+                .values(&new_invitation)
+                .get_result::<models::Invitation>(conn)?)
+        })
+    }
+}
+
+/// Pending invitations of the user, newest first
+pub struct ListPendingInvitations {
+    pub user_id: i64,
+}
+
+impl Message for ListPendingInvitations {
+    type Result = Result<Vec<models::Invitation>, InvitationError>;
+}
+
+impl Handler<ListPendingInvitations> for DbExecutor {
+    type Result = Result<Vec<models::Invitation>, InvitationError>;
+
+    fn handle(&mut self, msg: ListPendingInvitations, _: &mut Self::Context) -> Self::Result {
+        use self::schema::invitations::dsl::*;
+
+        let conn = &self.0.get()?;
+
+        expire_invitations(conn)?;
+
+        Ok(invitations
+            .filter(invitee_user_id.eq(msg.user_id))
+            .filter(status.eq(InvitationStatus::Pending.as_str()))
+            .order(created_at.desc())
+            .load::<models::Invitation>(conn)?)
+    }
+}
+
+/// Accepts an invitation of the user, joining the band as a member or going
+/// to the event in the same transaction.
+pub struct AcceptInvitation {
+    pub id: i64,
+    pub acting_user_id: i64,
+}
+
+impl Message for AcceptInvitation {
+    type Result = Result<models::Invitation, InvitationError>;
+}
+
+impl Handler<AcceptInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, InvitationError>;
+
+    fn handle(&mut self, msg: AcceptInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        expire_invitations(conn)?;
+
+        conn.transaction(|| {
+            let invitation = find_pending_invitation(conn, msg.id, msg.acting_user_id)?;
+
+            match (invitation.band_id, invitation.event_id) {
+                (Some(band_id), None) => {
+                    // members keep the role they may have been given meanwhile
+                    if member_role(conn, band_id, msg.acting_user_id)?.is_none() {
+                        upsert_band_member(conn, band_id, msg.acting_user_id, BandRole::Member)?;
+                    }
+                }
+                (None, Some(event_id)) => {
+                    let event = schema::events::table
+                        .find(event_id)
+                        .first::<models::Event>(conn)?;
+
+                    if event.cancelled_at.is_some() {
+                        return Err(InvitationError::EventCancelled);
+                    }
+
+                    upsert_attendee(conn, event_id, msg.acting_user_id, RsvpStatus::Going)?;
+                }
+                _ => return Err(InvitationError::InvalidTarget),
+            }
+
+            Ok(set_status(conn, msg.id, InvitationStatus::Accepted)?)
+        })
+    }
+}
+
+pub struct DeclineInvitation {
+    pub id: i64,
+    pub acting_user_id: i64,
+}
+
+impl Message for DeclineInvitation {
+    type Result = Result<models::Invitation, InvitationError>;
+}
+
+impl Handler<DeclineInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, InvitationError>;
+
+    fn handle(&mut self, msg: DeclineInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        expire_invitations(conn)?;
+
+        conn.transaction(|| {
+            find_pending_invitation(conn, msg.id, msg.acting_user_id)?;
+
+            Ok(set_status(conn, msg.id, InvitationStatus::Declined)?)
+        })
+    }
+}
+
+/// Withdraws a pending invitation, allowed to its sender and to whoever may
+/// invite to the same band or event.
+pub struct RevokeInvitation {
+    pub id: i64,
+    pub acting_user_id: i64,
+}
+
+impl Message for RevokeInvitation {
+    type Result = Result<models::Invitation, InvitationError>;
+}
+
+impl Handler<RevokeInvitation> for DbExecutor {
+    type Result = Result<models::Invitation, InvitationError>;
+
+    fn handle(&mut self, msg: RevokeInvitation, _: &mut Self::Context) -> Self::Result {
+        let conn = &self.0.get()?;
+
+        expire_invitations(conn)?;
+
+        conn.transaction(|| {
+            let invitation = schema::invitations::table
+                .find(msg.id)
+                .for_update()
+                .first::<models::Invitation>(conn)
+                .optional()?
+                .ok_or(InvitationError::NotFound)?;
+
+            let allowed = invitation.invited_by == msg.acting_user_id
+                || can_invite(
+                    conn,
+                    invitation.band_id,
+                    invitation.event_id,
+                    msg.acting_user_id,
+                )?;
+
+            if !allowed {
+                return Err(InvitationError::NotAllowed);
+            }
+
+            if invitation.status != InvitationStatus::Pending.as_str() {
+                return Err(InvitationError::NotPending);
+            }
+
+            Ok(set_status(conn, msg.id, InvitationStatus::Revoked)?)
+        })
+    }
+}
@@ -0,0 +1,597 @@
+//! Users kept in memory
+//!
+//! Same semantics as the Postgres repository, so the HTTP tests of the user
+//! endpoints run without a database. Invitations live in Postgres only, none
+//! are claimed by the users verified here.
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+
+use super::accounts::{SetRole, SetSuspended};
+use super::password_reset::{
+    PasswordReset, RequestPasswordReset, RequestPasswordResetError, ResetPassword,
+    ResetPasswordError, MAX_PASSWORD_RESETS_PER_HOUR, PASSWORD_RESET_TTL_HOURS,
+};
+use super::passwords::{hash_password, verify_password};
+use super::repository::UserRepository;
+use super::search::{search_loaded_users, SearchError, SearchResults, SearchUsers};
+use super::sessions::{CreateSession, ResolveSession, SESSION_TTL_DAYS};
+use super::tokens::generate_token;
+use super::users::{
+    lookup_emails, CheckConnection, CreateUser, CreateUserError, DeleteUser, DeleteUserError,
+    GetUser, LoginError, LoginResponse, LoginWithEmail, UpdateUser, UpdateUserError,
+};
+use super::verification::{
+    IssueVerificationToken, VerifyEmail, VerifyEmailError, VERIFICATION_TTL_DAYS,
+};
+use super::DbError;
+use models::{self, UserRole};
+
+#[derive(Default)]
+struct MemoryStore {
+    users: Vec<models::User>,
+    sessions: Vec<models::Session>,
+    verification_tokens: Vec<models::VerificationToken>,
+    password_reset_tokens: Vec<models::PasswordResetToken>,
+    /// Email and time of the password resets asked for
+    password_reset_requests: Vec<(String, NaiveDateTime)>,
+    /// Last id handed out, shared by all the records
+    last_id: i64,
+}
+
+impl MemoryStore {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn user_mut(&mut self, id: i64) -> Option<&mut models::User> {
+        self.users.iter_mut().find(|user| user.id == id)
+    }
+
+    /// User with the first of `emails` registered
+    fn find_by_email(&self, emails: &[&str]) -> Option<&models::User> {
+        emails
+            .iter()
+            .find_map(|email| self.users.iter().find(|user| user.email == *email))
+    }
+
+    fn email_taken(&self, email: &str, except_id: Option<i64>) -> bool {
+        self.users
+            .iter()
+            .any(|user| user.email == email && Some(user.id) != except_id)
+    }
+
+    fn create_session(&mut self, user_id: i64) -> models::Session {
+        let now = Utc::now().naive_utc();
+        let session = models::Session {
+            id: self.next_id(),
+            token: generate_token(),
+            user_id,
+            created_at: now,
+            expires_at: now + Duration::days(SESSION_TTL_DAYS),
+        };
+
+        self.sessions.push(session.clone());
+        session
+    }
+}
+
+/// Repository sharing one `MemoryStore` between all its clones, give one clone
+/// to every `UsersExecutor` worker. Passwords are hashed and verified without
+/// holding the store, the other workers would wait for argon2 otherwise.
+#[derive(Clone, Default)]
+pub struct MemoryUserRepository {
+    store: Arc<Mutex<MemoryStore>>,
+}
+
+impl MemoryUserRepository {
+    pub fn new() -> Self {
+        MemoryUserRepository::default()
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+impl UserRepository for MemoryUserRepository {
+    fn create_user(&mut self, msg: CreateUser) -> Result<models::User, CreateUserError> {
+        let new_password_hash = hash_password(&msg.password);
+        let mut store = self.store.lock().unwrap();
+
+        if store.email_taken(&msg.email, None) {
+            return Err(CreateUserError::UserAlreadyExists);
+        }
+
+        let user = models::User {
+            id: store.next_id(),
+            name: msg.name,
+            about: msg.about,
+            email: msg.email,
+            password_hash: new_password_hash,
+            email_verified: false,
+            role: UserRole::User.as_str().to_string(),
+            suspended_at: None,
+        };
+
+        store.users.push(user.clone());
+        Ok(user)
+    }
+
+    fn get_user(&mut self, msg: GetUser) -> Result<Option<models::User>, DbError> {
+        let store = self.store.lock().unwrap();
+
+        Ok(store.users.iter().find(|user| user.id == msg.id).cloned())
+    }
+
+    fn update_user(&mut self, msg: UpdateUser) -> Result<models::User, UpdateUserError> {
+        let mut store = self.store.lock().unwrap();
+
+        let email_changes = msg.email.is_some();
+        if let Some(ref new_email) = msg.email {
+            if store.email_taken(new_email, Some(msg.id)) {
+                return Err(UpdateUserError::UserAlreadyExists);
+            }
+        }
+
+        let user = store.user_mut(msg.id).ok_or(UpdateUserError::NotFound)?;
+        if let Some(new_name) = msg.name {
+            user.name = new_name;
+        }
+        if let Some(new_about) = msg.about {
+            user.about = new_about;
+        }
+        if let Some(new_email) = msg.email {
+            user.email = new_email;
+            user.email_verified = false;
+        }
+        let user = user.clone();
+
+        // links sent to the previous email don't verify the new one
+        if email_changes {
+            store
+                .verification_tokens
+                .retain(|token| token.user_id != user.id);
+        }
+
+        Ok(user)
+    }
+
+    fn delete_user(&mut self, msg: DeleteUser) -> Result<(), DeleteUserError> {
+        let mut store = self.store.lock().unwrap();
+
+        store.users.retain(|user| user.id != msg.id);
+        store.sessions.retain(|session| session.user_id != msg.id);
+        store
+            .verification_tokens
+            .retain(|token| token.user_id != msg.id);
+        store
+            .password_reset_tokens
+            .retain(|token| token.user_id != msg.id);
+
+        Ok(())
+    }
+
+    fn login_with_email(&mut self, msg: LoginWithEmail) -> Result<LoginResponse, LoginError> {
+        let user = self
+            .store
+            .lock()
+            .unwrap()
+            .find_by_email(&lookup_emails(&msg.email, &msg.typed_email))
+            .map(|user| {
+                (
+                    user.id,
+                    user.password_hash.clone(),
+                    user.email_verified,
+                    user.is_suspended(),
+                )
+            });
+
+        let password_matches = verify_password(
+            user.as_ref().map(|(_, hash, _, _)| hash.as_str()),
+            &msg.password,
+        );
+
+        match user {
+            Some((_, _, _, true)) if password_matches => Err(LoginError::AccountSuspended),
+            Some((_, _, false, _)) if password_matches && msg.require_verified_email => {
+                Err(LoginError::EmailNotVerified)
+            }
+            Some((user_id, _, _, _)) if password_matches => Ok(LoginResponse {
+                token: self.store.lock().unwrap().create_session(user_id).token,
+            }),
+            _ => Err(LoginError::InvalidCredentials),
+        }
+    }
+
+    fn search_users(&mut self, msg: SearchUsers) -> Result<SearchResults, SearchError> {
+        let store = self.store.lock().unwrap();
+
+        search_loaded_users(&store.users, &msg)
+    }
+
+    fn create_session(&mut self, msg: CreateSession) -> Result<models::Session, DbError> {
+        let mut store = self.store.lock().unwrap();
+
+        Ok(store.create_session(msg.user_id))
+    }
+
+    fn resolve_session(&mut self, msg: ResolveSession) -> Result<Option<models::User>, DbError> {
+        let store = self.store.lock().unwrap();
+
+        let now = now();
+        let owner_id = store
+            .sessions
+            .iter()
+            .find(|session| session.token == msg.token && session.expires_at > now)
+            .map(|session| session.user_id);
+
+        Ok(owner_id.and_then(|owner_id| {
+            store
+                .users
+                .iter()
+                .find(|user| user.id == owner_id && !user.is_suspended())
+                .cloned()
+        }))
+    }
+
+    fn issue_verification_token(
+        &mut self,
+        msg: IssueVerificationToken,
+    ) -> Result<models::VerificationToken, DbError> {
+        let mut store = self.store.lock().unwrap();
+
+        let now = now();
+        let verification_token = models::VerificationToken {
+            id: store.next_id(),
+            token: generate_token(),
+            user_id: msg.user_id,
+            created_at: now,
+            expires_at: now + Duration::days(VERIFICATION_TTL_DAYS),
+        };
+
+        store.verification_tokens.push(verification_token.clone());
+        Ok(verification_token)
+    }
+
+    fn verify_email(&mut self, msg: VerifyEmail) -> Result<models::User, VerifyEmailError> {
+        let mut store = self.store.lock().unwrap();
+
+        let now = now();
+        let owner_id = store
+            .verification_tokens
+            .iter()
+            .find(|token| token.token == msg.token && token.expires_at > now)
+            .map(|token| token.user_id)
+            .ok_or(VerifyEmailError::InvalidToken)?;
+
+        store
+            .verification_tokens
+            .retain(|token| token.user_id != owner_id);
+
+        let user = store
+            .user_mut(owner_id)
+            .ok_or(VerifyEmailError::InvalidToken)?;
+        user.email_verified = true;
+
+        Ok(user.clone())
+    }
+
+    fn request_password_reset(
+        &mut self,
+        msg: RequestPasswordReset,
+    ) -> Result<Option<PasswordReset>, RequestPasswordResetError> {
+        let mut store = self.store.lock().unwrap();
+
+        let now = now();
+        let an_hour_ago = now - Duration::hours(1);
+        store
+            .password_reset_requests
+            .retain(|(_, requested_at)| *requested_at > an_hour_ago);
+        let recent_requests = store
+            .password_reset_requests
+            .iter()
+            .filter(|(email, _)| *email == msg.email)
+            .count() as i64;
+
+        if recent_requests >= MAX_PASSWORD_RESETS_PER_HOUR {
+            return Err(RequestPasswordResetError::TooManyRequests);
+        }
+        store.password_reset_requests.push((msg.email.clone(), now));
+
+        let (owner_id, owner_email) =
+            match store.find_by_email(&lookup_emails(&msg.email, &msg.typed_email)) {
+                Some(user) => (user.id, user.email.clone()),
+                None => return Ok(None),
+            };
+
+        let reset_token = models::PasswordResetToken {
+            id: store.next_id(),
+            token: generate_token(),
+            user_id: owner_id,
+            created_at: now,
+            expires_at: now + Duration::hours(PASSWORD_RESET_TTL_HOURS),
+        };
+
+        store.password_reset_tokens.push(reset_token.clone());
+        Ok(Some(PasswordReset {
+            token: reset_token,
+            email: owner_email,
+        }))
+    }
+
+    fn reset_password(&mut self, msg: ResetPassword) -> Result<(), ResetPasswordError> {
+        let new_password_hash = hash_password(&msg.password);
+        let mut store = self.store.lock().unwrap();
+
+        let now = now();
+        let owner_id = store
+            .password_reset_tokens
+            .iter()
+            .find(|token| token.token == msg.token && token.expires_at > now)
+            .map(|token| token.user_id)
+            .ok_or(ResetPasswordError::InvalidToken)?;
+
+        if let Some(user) = store.user_mut(owner_id) {
+            user.password_hash = new_password_hash;
+        }
+        store
+            .password_reset_tokens
+            .retain(|token| token.user_id != owner_id);
+        store.sessions.retain(|session| session.user_id != owner_id);
+
+        Ok(())
+    }
+
+    fn set_suspended(&mut self, msg: SetSuspended) -> Result<Option<models::User>, DbError> {
+        let mut store = self.store.lock().unwrap();
+
+        if msg.suspended {
+            store.sessions.retain(|session| session.user_id != msg.id);
+        }
+
+        Ok(store.user_mut(msg.id).map(|user| {
+            if msg.suspended != user.is_suspended() {
+                user.suspended_at = if msg.suspended { Some(now()) } else { None };
+            }
+            user.clone()
+        }))
+    }
+
+    fn set_role(&mut self, msg: SetRole) -> Result<Option<models::User>, DbError> {
+        let mut store = self.store.lock().unwrap();
+
+        Ok(store.user_mut(msg.id).map(|user| {
+            user.role = msg.role.as_str().to_string();
+            user.clone()
+        }))
+    }
+
+    fn check_connection(&mut self, _: CheckConnection) -> Result<(), DbError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+
+    use super::*;
+
+    fn create_user(repository: &mut MemoryUserRepository, name: &str, about: &str) -> i64 {
+        repository
+            .create_user(CreateUser {
+                name: name.to_string(),
+                email: format!("{}@gmail.com", name.to_lowercase().replace(' ', "_")),
+                about: about.to_string(),
+                password: "password 1".to_string(),
+            })
+            .unwrap()
+            .id
+    }
+
+    fn login(
+        repository: &mut MemoryUserRepository,
+        email: &str,
+    ) -> Result<LoginResponse, LoginError> {
+        repository.login_with_email(LoginWithEmail {
+            email: email.to_string(),
+            password: "password 1".to_string(),
+            require_verified_email: false,
+            typed_email: None,
+        })
+    }
+
+    #[test]
+    fn test_unique_email_and_ids() {
+        let mut repository = MemoryUserRepository::new();
+
+        let first = create_user(&mut repository, "Alice", "");
+        let second = create_user(&mut repository, "Bob", "");
+        assert!(second > first);
+
+        let duplicate = repository.create_user(CreateUser {
+            name: "Other Alice".to_string(),
+            email: "alice@gmail.com".to_string(),
+            about: "".to_string(),
+            password: "password 1".to_string(),
+        });
+        match duplicate {
+            Err(CreateUserError::UserAlreadyExists) => (),
+            _ => panic!("duplicate email was accepted"),
+        }
+
+        let update = repository.update_user(UpdateUser {
+            id: second,
+            name: None,
+            about: None,
+            email: Some("alice@gmail.com".to_string()),
+        });
+        match update {
+            Err(UpdateUserError::UserAlreadyExists) => (),
+            _ => panic!("duplicate email was accepted"),
+        }
+    }
+
+    #[test]
+    fn test_search_matching() {
+        let mut repository = MemoryUserRepository::new();
+
+        create_user(&mut repository, "Bob", "plays the guitar");
+        create_user(&mut repository, "Alice Guitar", "jazz");
+        create_user(&mut repository, "Carol", "bass and guitar, jazz");
+
+        let results = repository
+            .search_users(SearchUsers {
+                query: Some("Guitar".to_string()),
+                with_total: true,
+                ..SearchUsers::default()
+            })
+            .unwrap();
+        let names = results
+            .users
+            .iter()
+            .map(|user| user.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Alice Guitar", "Bob", "Carol"]);
+        assert_eq!(results.total, Some(3));
+
+        let results = repository
+            .search_users(SearchUsers {
+                query: Some("guitar jazz".to_string()),
+                name: Some("o".to_string()),
+                ..SearchUsers::default()
+            })
+            .unwrap();
+        assert_eq!(results.users.len(), 1);
+        assert_eq!(results.users[0].name, "Carol");
+
+        let results = repository
+            .search_users(SearchUsers {
+                query: Some("bob@gmail.com".to_string()),
+                match_email: true,
+                ..SearchUsers::default()
+            })
+            .unwrap();
+        assert_eq!(results.users.len(), 1);
+        assert_eq!(results.users[0].name, "Bob");
+
+        let results = repository
+            .search_users(SearchUsers {
+                query: Some("bob@gmail.com".to_string()),
+                ..SearchUsers::default()
+            })
+            .unwrap();
+        assert!(results.users.is_empty());
+    }
+
+    #[test]
+    fn test_suspension() {
+        let mut repository = MemoryUserRepository::new();
+
+        let id = create_user(&mut repository, "Dave", "drums");
+        let session = repository
+            .create_session(CreateSession { user_id: id })
+            .unwrap();
+
+        let suspended = repository
+            .set_suspended(SetSuspended {
+                id,
+                suspended: true,
+            })
+            .unwrap()
+            .unwrap();
+        assert!(suspended.is_suspended());
+
+        // sessions are closed and new ones refused
+        let resolved = repository
+            .resolve_session(ResolveSession {
+                token: session.token,
+            })
+            .unwrap();
+        assert!(resolved.is_none());
+        match login(&mut repository, "dave@gmail.com") {
+            Err(LoginError::AccountSuspended) => (),
+            _ => panic!("a suspended account logged in"),
+        }
+
+        // suspended users are only found on demand
+        let search = |include_suspended| SearchUsers {
+            query: Some("drums".to_string()),
+            include_suspended,
+            ..SearchUsers::default()
+        };
+        assert!(repository
+            .search_users(search(false))
+            .unwrap()
+            .users
+            .is_empty());
+        assert_eq!(
+            repository.search_users(search(true)).unwrap().users.len(),
+            1
+        );
+
+        // suspending again keeps the first time
+        let again = repository
+            .set_suspended(SetSuspended {
+                id,
+                suspended: true,
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.suspended_at, suspended.suspended_at);
+
+        let restored = repository
+            .set_suspended(SetSuspended {
+                id,
+                suspended: false,
+            })
+            .unwrap()
+            .unwrap();
+        assert!(!restored.is_suspended());
+        assert!(login(&mut repository, "dave@gmail.com").is_ok());
+
+        let missing = repository
+            .set_suspended(SetSuspended {
+                id: id + 1_000,
+                suspended: true,
+            })
+            .unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn test_lookup_by_typed_email() {
+        let mut repository = MemoryUserRepository::new();
+
+        let id = repository
+            .create_user(CreateUser {
+                name: "Erin".to_string(),
+                email: "Erin@Example.COM".to_string(),
+                about: String::new(),
+                password: "password 1".to_string(),
+            })
+            .unwrap()
+            .id;
+
+        let reset = repository
+            .request_password_reset(RequestPasswordReset {
+                email: "Erin@example.com".to_string(),
+                typed_email: Some("Erin@Example.COM".to_string()),
+            })
+            .unwrap();
+        let reset = reset.unwrap();
+        assert_eq!(reset.token.user_id, id);
+        // the link goes to the email as registered
+        assert_eq!(reset.email, "Erin@Example.COM");
+
+        // only the canonical email is looked up when it was typed as is
+        assert!(repository
+            .request_password_reset(RequestPasswordReset {
+                email: "Erin@example.com".to_string(),
+                typed_email: None,
+            })
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,257 @@
+//! Migrations embedded into the binary
+//!
+//! The sql of every directory in `migrations/` is compiled in, so the server
+//! and `peers_manager migrate` don't need the diesel CLI nor the sources.
+//! Applied versions are tracked in diesel's `__diesel_schema_migrations`, the
+//! diesel CLI keeps working on the same database.
+//!
+//! SQLite has its own migrations in `migrations_sqlite/`, they create the
+//! tables of the users only.
+use std::io::Write;
+
+use diesel::connection::SimpleConnection;
+use diesel::migration::{Migration, MigrationError, RunMigrationsError};
+use diesel::pg::PgConnection;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::SqliteConnection;
+use diesel_migrations::{self, MigrationConnection};
+
+pub struct EmbeddedMigration {
+    /// Name of the migration directory
+    pub name: &'static str,
+    version: String,
+    up_sql: &'static str,
+    down_sql: &'static str,
+}
+
+impl EmbeddedMigration {
+    fn new(name: &'static str, up_sql: &'static str, down_sql: &'static str) -> Self {
+        // diesel's version of `2019-01-05-120000_create_sessions` is
+        // `20190105120000`
+        let version = name.split('_').next().unwrap_or(name).replace('-', "");
+
+        EmbeddedMigration {
+            name,
+            version,
+            up_sql,
+            down_sql,
+        }
+    }
+}
+
+impl Migration for EmbeddedMigration {
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn run(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.up_sql)
+            .map_err(RunMigrationsError::QueryError)
+    }
+
+    fn revert(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.down_sql)
+            .map_err(RunMigrationsError::QueryError)
+    }
+}
+
+macro_rules! embed_migration {
+    ($dir:expr, $name:expr) => {
+        EmbeddedMigration::new(
+            $name,
+            include_str!(concat!("../../", $dir, "/", $name, "/up.sql")),
+            include_str!(concat!("../../", $dir, "/", $name, "/down.sql")),
+        )
+    };
+}
+
+/// All migrations, oldest first. New migration directories have to be added
+/// here as well.
+pub fn migrations() -> Vec<EmbeddedMigration> {
+    vec![
+        embed_migration!("migrations", "00000000000000_diesel_initial_setup"),
+        embed_migration!("migrations", "2018-12-29-011101_create_users_table"),
+        embed_migration!("migrations", "2019-01-05-120000_create_sessions_table"),
+        embed_migration!("migrations", "2019-01-08-090000_add_users_password_hash"),
+        embed_migration!("migrations", "2019-01-12-100000_create_verification_tokens"),
+        embed_migration!(
+            "migrations",
+            "2019-01-15-100000_create_password_reset_tokens"
+        ),
+        embed_migration!("migrations", "2019-01-20-100000_create_bands"),
+        embed_migration!("migrations", "2019-01-26-100000_create_events"),
+        embed_migration!("migrations", "2019-02-02-100000_create_invitations"),
+        embed_migration!("migrations", "2019-02-09-100000_add_users_search_vector"),
+        embed_migration!(
+            "migrations",
+            "2019-02-23-100000_add_users_role_and_suspension"
+        ),
+        embed_migration!(
+            "migrations",
+            "2019-03-02-100000_create_password_reset_requests"
+        ),
+        embed_migration!(
+            "migrations",
+            "2019-03-09-100000_keep_bands_and_events_of_deleted_users"
+        ),
+    ]
+}
+
+/// SQLite migrations, oldest first
+#[cfg(feature = "sqlite")]
+pub fn sqlite_migrations() -> Vec<EmbeddedMigration> {
+    vec![
+        embed_migration!("migrations_sqlite", "2019-02-16-100000_create_users"),
+        embed_migration!("migrations_sqlite", "2019-02-16-100100_create_sessions"),
+        embed_migration!(
+            "migrations_sqlite",
+            "2019-02-16-100200_create_verification_tokens"
+        ),
+        embed_migration!(
+            "migrations_sqlite",
+            "2019-02-16-100300_create_password_reset_tokens"
+        ),
+        embed_migration!(
+            "migrations_sqlite",
+            "2019-02-23-100000_add_users_role_and_suspension"
+        ),
+        embed_migration!(
+            "migrations_sqlite",
+            "2019-03-02-100000_create_password_reset_requests"
+        ),
+    ]
+}
+
+/// Connection of a backend with embedded migrations
+pub trait EmbeddedMigrations: MigrationConnection {
+    fn embedded_migrations() -> Vec<EmbeddedMigration>;
+}
+
+impl EmbeddedMigrations for PgConnection {
+    fn embedded_migrations() -> Vec<EmbeddedMigration> {
+        migrations()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl EmbeddedMigrations for SqliteConnection {
+    fn embedded_migrations() -> Vec<EmbeddedMigration> {
+        sqlite_migrations()
+    }
+}
+
+/// Applies the migrations which did not run yet, each in its own transaction
+pub fn run_pending_migrations<Conn: EmbeddedMigrations>(
+    conn: &Conn,
+    output: &mut dyn Write,
+) -> Result<(), RunMigrationsError> {
+    diesel_migrations::run_migrations(conn, Conn::embedded_migrations(), output)
+}
+
+/// Reverts the latest applied migration, returns its name. `None` when no
+/// migration was applied.
+pub fn revert_latest_migration<Conn: EmbeddedMigrations>(
+    conn: &Conn,
+) -> Result<Option<&'static str>, RunMigrationsError> {
+    diesel_migrations::setup_database(conn)?;
+
+    let latest = match conn.latest_run_migration_version()? {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+
+    let migration = Conn::embedded_migrations()
+        .into_iter()
+        .find(|migration| migration.version() == latest)
+        .ok_or_else(|| MigrationError::UnknownMigrationVersion(latest.clone()))?;
+
+    conn.transaction(|| {
+        migration.revert(conn)?;
+
+        // the version is made of digits only, see `EmbeddedMigration::new`
+        conn.execute(&format!(
+            "DELETE FROM __diesel_schema_migrations WHERE version = '{}'",
+            migration.version()
+        ))?;
+
+        Ok(Some(migration.name))
+    })
+}
+
+/// Every migration with whether it was applied
+pub fn migrations_status<Conn: EmbeddedMigrations>(
+    conn: &Conn,
+) -> Result<Vec<(&'static str, bool)>, RunMigrationsError> {
+    diesel_migrations::setup_database(conn)?;
+
+    let applied = conn.previously_run_migration_versions()?;
+
+    Ok(Conn::embedded_migrations()
+        .iter()
+        .map(|migration| (migration.name, applied.contains(migration.version())))
+        .collect())
+}
+
+#[cfg(test)]
+mod migrations_tests {
+
+    use super::*;
+    use std::fs;
+
+    fn directories(dir: &str) -> Vec<String> {
+        let mut directories = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        directories.sort();
+        directories
+    }
+
+    fn names(migrations: Vec<EmbeddedMigration>) -> Vec<String> {
+        migrations
+            .iter()
+            .map(|migration| migration.name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_all_migrations_embedded() {
+        assert_eq!(names(migrations()), directories("migrations"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_migrations() {
+        use diesel::Connection;
+
+        assert_eq!(names(sqlite_migrations()), directories("migrations_sqlite"));
+
+        let conn = SqliteConnection::establish(":memory:").unwrap();
+        run_pending_migrations(&conn, &mut ::std::io::sink()).unwrap();
+        assert!(migrations_status(&conn)
+            .unwrap()
+            .iter()
+            .all(|&(_, applied)| applied));
+
+        while revert_latest_migration(&conn).unwrap().is_some() {}
+        assert!(migrations_status(&conn)
+            .unwrap()
+            .iter()
+            .all(|&(_, applied)| !applied));
+    }
+
+    #[test]
+    fn test_versions() {
+        let versions = migrations()
+            .iter()
+            .map(|migration| migration.version().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(versions[2], "20190105120000");
+
+        let mut sorted = versions.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(versions, sorted);
+    }
+}
@@ -1 +1,120 @@
+//! Storage
+//!
+//! Failures of the database itself are `DbError`s: a query error or no pooled
+//! connection available in time. Every error type of the messages has a
+//! `DbError` variant, `db_error_conversions!` lets `?` fill it.
+use std::fmt;
+
+use diesel;
+use r2d2;
+
+/// `From` conversions of query and pool errors into the `DbError` variant of
+/// `$error`
+macro_rules! db_error_conversions {
+    ($error:ident) => {
+        impl From<::diesel::result::Error> for $error {
+            fn from(db_error: ::diesel::result::Error) -> Self {
+                $error::DbError(db_error.into())
+            }
+        }
+
+        impl From<::r2d2::Error> for $error {
+            fn from(pool_error: ::r2d2::Error) -> Self {
+                $error::DbError(pool_error.into())
+            }
+        }
+
+        impl From<::db::DbError> for $error {
+            fn from(db_error: ::db::DbError) -> Self {
+                $error::DbError(db_error)
+            }
+        }
+    };
+}
+
+/// `Handler<Traced<M>>` of `$actor` for every message `M` listed: handles the
+/// message with the records of the worker tagged with its request id and logs
+/// it when slower than `SLOW_MESSAGE`. Listing the messages keeps the compiler
+/// from recursing through `Traced<Traced<..>>` as a generic impl would.
+macro_rules! traced_handlers {
+    ($actor:ty, $kind:expr, $($message:ty),+ $(,)*) => {
+        $(
+            impl ::actix::Handler<::logging::Traced<$message>> for $actor {
+                type Result = <$message as ::actix::Message>::Result;
+
+                fn handle(
+                    &mut self,
+                    traced: ::logging::Traced<$message>,
+                    ctx: &mut Self::Context,
+                ) -> Self::Result {
+                    let ::logging::Traced { request_id, msg } = traced;
+                    let started = ::std::time::Instant::now();
+
+                    ::logging::with_request_id(request_id.as_ref().map(|id| id.as_str()), || {
+                        let result = ::actix::Handler::<$message>::handle(self, msg, ctx);
+
+                        let elapsed = started.elapsed();
+                        if elapsed >= ::db::users::SLOW_MESSAGE {
+                            warn!(
+                                "slow {} message: {}ms",
+                                $kind,
+                                elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+                            );
+                        }
+                        result
+                    })
+                }
+            }
+        )+
+    };
+}
+
+pub mod accounts;
+pub mod bands;
+pub mod events;
+pub mod invitations;
+#[cfg(test)]
+pub mod memory;
+pub mod migrations;
+pub mod password_reset;
+pub mod passwords;
+pub mod repository;
+pub mod search;
+pub mod sessions;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tokens;
 pub mod users;
+pub mod verification;
+
+use diesel::sql_types::Text;
+
+#[derive(Debug)]
+pub enum DbError {
+    Query(diesel::result::Error),
+    /// No connection of the pool got free before the pool's timeout
+    Pool(r2d2::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Query(error) => write!(f, "query failed: {}", error),
+            DbError::Pool(error) => write!(f, "no database connection: {}", error),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for DbError {
+    fn from(error: diesel::result::Error) -> Self {
+        DbError::Query(error)
+    }
+}
+
+impl From<r2d2::Error> for DbError {
+    fn from(error: r2d2::Error) -> Self {
+        DbError::Pool(error)
+    }
+}
+
+sql_function!(fn lower(x: Text) -> Text);
@@ -0,0 +1,59 @@
+//! Password reset tokens
+use actix::prelude::*;
+
+use super::DbError;
+use models;
+
+/// How long a password reset link sent by email stays valid.
+pub const PASSWORD_RESET_TTL_HOURS: i64 = 1;
+
+/// How many resets may be asked for one email per hour, whether a user owns
+/// it or not. Redeemed tokens still count.
+pub const MAX_PASSWORD_RESETS_PER_HOUR: i64 = 3;
+
+#[derive(Deserialize, Serialize)]
+pub struct RequestPasswordReset {
+    pub email: String,
+    /// The email as typed when `email` is its canonical form, see
+    /// `db::users::lookup_emails`
+    #[serde(skip)]
+    pub typed_email: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum RequestPasswordResetError {
+    TooManyRequests,
+    DbError(DbError),
+}
+
+db_error_conversions!(RequestPasswordResetError);
+
+/// Token issued by `RequestPasswordReset`
+pub struct PasswordReset {
+    pub token: models::PasswordResetToken,
+    /// Email of the user as stored, the link is mailed there
+    pub email: String,
+}
+
+impl Message for RequestPasswordReset {
+    /// `None` when no user is registered with the email
+    type Result = Result<Option<PasswordReset>, RequestPasswordResetError>;
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug)]
+pub enum ResetPasswordError {
+    InvalidToken,
+    DbError(DbError),
+}
+
+db_error_conversions!(ResetPasswordError);
+
+impl Message for ResetPassword {
+    type Result = Result<(), ResetPasswordError>;
+}
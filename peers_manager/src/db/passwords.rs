@@ -0,0 +1,31 @@
+//! Password hashing
+use argon2;
+use rand::Rng;
+
+lazy_static! {
+    /// Hash verified against when no user matches the login email, so that a
+    /// missing account costs as much time as a wrong password.
+    static ref DUMMY_HASH: String = hash_password("dummy password");
+}
+
+/// Hashes the password with Argon2 and a random salt, the returned string
+/// encodes the parameters and the salt.
+pub fn hash_password(password: &str) -> String {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+        .expect("Failed to hash password")
+}
+
+/// Checks the password against an encoded hash, a missing (empty) hash never
+/// matches but takes the same time to check.
+pub fn verify_password(hash: Option<&str>, password: &str) -> bool {
+    match hash {
+        Some(hash) if !hash.is_empty() => {
+            argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+        }
+        _ => {
+            let _ = argon2::verify_encoded(&DUMMY_HASH, password.as_bytes());
+            false
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! User storage
+//!
+//! Handlers send the user messages to a `UsersExecutor`, which hands them to
+//! its `UserRepository`: `DbExecutor` on Postgres, or on SQLite with the
+//! `sqlite` feature, for deployments, `MemoryUserRepository` for hermetic
+//! tests.
+//!
+//! Diesel 1.4 can't name the bounds of a query generic over its backend,
+//! SQLite builds its insert statements its own way, so the queries of
+//! `DbExecutor` are written once in `diesel_user_repository!` and the macro is
+//! instantiated for the connection of each backend. What the backends do
+//! differently is behind `UsersBackend`.
+
+use actix::prelude::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+use super::accounts::{SetRole, SetSuspended};
+use super::bands::owns_band_alone;
+use super::invitations::claim_email_invitations;
+use super::password_reset::{
+    PasswordReset, RequestPasswordReset, RequestPasswordResetError, ResetPassword,
+    ResetPasswordError, MAX_PASSWORD_RESETS_PER_HOUR, PASSWORD_RESET_TTL_HOURS,
+};
+use super::passwords::{hash_password, verify_password};
+use super::search::{self, SearchError, SearchResults, SearchUsers};
+use super::sessions::{CreateSession, ResolveSession, SESSION_TTL_DAYS};
+use super::tokens::generate_token;
+use super::users::{
+    is_email_taken, lookup_emails, CheckConnection, CreateUser, CreateUserError, DbExecutor,
+    DeleteUser, DeleteUserError, GetUser, LoginError, LoginResponse, LoginWithEmail, UpdateUser,
+    UpdateUserError,
+};
+use super::verification::{
+    IssueVerificationToken, VerifyEmail, VerifyEmailError, VERIFICATION_TTL_DAYS,
+};
+use super::DbError;
+use models;
+use schema;
+
+/// Storage of users, their sessions and their email and password tokens.
+///
+/// Implementations keep the same rules: emails are unique, ids come from an
+/// increasing sequence, expired tokens and sessions are ignored, suspended
+/// users have no sessions and are left out of searches unless asked for.
+pub trait UserRepository: Send {
+    fn create_user(&mut self, msg: CreateUser) -> Result<models::User, CreateUserError>;
+    fn get_user(&mut self, msg: GetUser) -> Result<Option<models::User>, DbError>;
+    fn update_user(&mut self, msg: UpdateUser) -> Result<models::User, UpdateUserError>;
+    fn delete_user(&mut self, msg: DeleteUser) -> Result<(), DeleteUserError>;
+    fn login_with_email(&mut self, msg: LoginWithEmail) -> Result<LoginResponse, LoginError>;
+    fn search_users(&mut self, msg: SearchUsers) -> Result<SearchResults, SearchError>;
+    fn create_session(&mut self, msg: CreateSession) -> Result<models::Session, DbError>;
+    fn resolve_session(&mut self, msg: ResolveSession) -> Result<Option<models::User>, DbError>;
+    fn issue_verification_token(
+        &mut self,
+        msg: IssueVerificationToken,
+    ) -> Result<models::VerificationToken, DbError>;
+    fn verify_email(&mut self, msg: VerifyEmail) -> Result<models::User, VerifyEmailError>;
+    fn request_password_reset(
+        &mut self,
+        msg: RequestPasswordReset,
+    ) -> Result<Option<PasswordReset>, RequestPasswordResetError>;
+    fn reset_password(&mut self, msg: ResetPassword) -> Result<(), ResetPasswordError>;
+    fn set_suspended(&mut self, msg: SetSuspended) -> Result<Option<models::User>, DbError>;
+    fn set_role(&mut self, msg: SetRole) -> Result<Option<models::User>, DbError>;
+    /// Whether the storage answers, for the readiness probe
+    fn check_connection(&mut self, msg: CheckConnection) -> Result<(), DbError>;
+}
+
+/// What the diesel backends keeping users do their own way
+pub trait UsersBackend {
+    /// Searches users with the text search of the backend
+    fn search_users(&self, msg: SearchUsers) -> Result<SearchResults, SearchError>;
+    /// Hands the invitations sent to `email` over to user `user_id`, who
+    /// just proved owning it
+    fn claim_email_invitations(&self, user_id: i64, email: &str) -> QueryResult<()>;
+    /// Makes the other transactions asking a password reset for `email` wait
+    /// until the current one ends
+    fn lock_password_resets(&self, email: &str) -> QueryResult<()>;
+    /// Whether user `user_id` is the only owner of a band, the owners of its
+    /// bands stay locked until the transaction ends
+    fn owns_band_alone(&self, user_id: i64) -> QueryResult<bool>;
+}
+
+impl UsersBackend for PgConnection {
+    fn search_users(&self, msg: SearchUsers) -> Result<SearchResults, SearchError> {
+        search::search_users(self, msg)
+    }
+
+    fn claim_email_invitations(&self, user_id: i64, email: &str) -> QueryResult<()> {
+        claim_email_invitations(self, user_id, email).map(|_| ())
+    }
+
+    fn lock_password_resets(&self, email: &str) -> QueryResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind::<Text, _>(email)
+            .execute(self)
+            .map(|_| ())
+    }
+
+    fn owns_band_alone(&self, user_id: i64) -> QueryResult<bool> {
+        owns_band_alone(self, user_id)
+    }
+}
+
+/// Timestamps are stored as naive UTC
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Implements `UserRepository` for `DbExecutor` on `$conn`, which has to be a
+/// `UsersBackend`. Rows are read back after they are written, SQLite has no
+/// `RETURNING`.
+macro_rules! diesel_user_repository {
+    ($conn:ty) => {
+        impl DbExecutor<$conn> {
+            fn find_user(conn: &$conn, user_id: i64) -> QueryResult<Option<models::User>> {
+                schema::users::table
+                    .find(user_id)
+                    .first::<models::User>(conn)
+                    .optional()
+            }
+
+            fn insert_session(conn: &$conn, for_user_id: i64) -> QueryResult<models::Session> {
+                use self::schema::sessions::dsl::*;
+
+                let new_token = generate_token();
+                let new_session = models::NewSession {
+                    token: &new_token,
+                    user_id: for_user_id,
+                    expires_at: now() + Duration::days(SESSION_TTL_DAYS),
+                };
+
+                diesel::insert_into(sessions)
+                    .values(&new_session)
+                    .execute(conn)?;
+
+                sessions
+                    .filter(token.eq(&new_token))
+                    .first::<models::Session>(conn)
+            }
+        }
+
+        impl UserRepository for DbExecutor<$conn> {
+            fn create_user(&mut self, msg: CreateUser) -> Result<models::User, CreateUserError> {
+                use self::schema::users::dsl::*;
+
+                let new_password_hash = hash_password(&msg.password);
+                let new_user = models::NewUser {
+                    name: &msg.name,
+                    email: &msg.email,
+                    about: &msg.about,
+                    password_hash: &new_password_hash,
+                };
+
+                let conn = &self.0.get()?;
+
+                conn.transaction::<_, diesel::result::Error, _>(|| {
+                    diesel::insert_into(users).values(&new_user).execute(conn)?;
+
+                    let user = users
+                        .filter(email.eq(&msg.email))
+                        .first::<models::User>(conn)?;
+
+                    Ok(user)
+                })
+                .map_err(|db_error| {
+                    if is_email_taken(&db_error) {
+                        return CreateUserError::UserAlreadyExists;
+                    }
+
+                    CreateUserError::DbError(db_error.into())
+                })
+            }
+
+            fn get_user(&mut self, msg: GetUser) -> Result<Option<models::User>, DbError> {
+                let conn = &self.0.get()?;
+
+                Ok(Self::find_user(conn, msg.id)?)
+            }
+
+            fn update_user(&mut self, msg: UpdateUser) -> Result<models::User, UpdateUserError> {
+                let conn = &self.0.get()?;
+
+                let changes = models::UserChanges {
+                    name: msg.name.as_ref().map(|new_name| new_name.as_str()),
+                    about: msg.about.as_ref().map(|new_about| new_about.as_str()),
+                    email: msg.email.as_ref().map(|new_email| new_email.as_str()),
+                    email_verified: msg.email.as_ref().map(|_| false),
+                };
+
+                let updated = conn.transaction::<_, diesel::result::Error, _>(|| {
+                    if !changes.is_empty() {
+                        diesel::update(schema::users::table.find(msg.id))
+                            .set(&changes)
+                            .execute(conn)?;
+                    }
+
+                    // links sent to the previous email don't verify the new one
+                    if msg.email.is_some() {
+                        diesel::delete(
+                            schema::verification_tokens::table
+                                .filter(schema::verification_tokens::user_id.eq(msg.id)),
+                        )
+                        .execute(conn)?;
+                    }
+
+                    Self::find_user(conn, msg.id)
+                });
+
+                match updated {
+                    Ok(Some(user)) => Ok(user),
+                    Ok(None) => Err(UpdateUserError::NotFound),
+                    Err(ref db_error) if is_email_taken(db_error) => {
+                        Err(UpdateUserError::UserAlreadyExists)
+                    }
+                    Err(db_error) => Err(UpdateUserError::DbError(db_error.into())),
+                }
+            }
+
+            /// Sessions and pending tokens of the user are removed by the
+            /// cascade, bands are never left without an owner
+            fn delete_user(&mut self, msg: DeleteUser) -> Result<(), DeleteUserError> {
+                let conn = &self.0.get()?;
+
+                conn.transaction(|| {
+                    if conn.owns_band_alone(msg.id)? {
+                        return Err(DeleteUserError::LastOwner);
+                    }
+
+                    diesel::delete(schema::users::table.find(msg.id)).execute(conn)?;
+
+                    Ok(())
+                })
+            }
+
+            fn login_with_email(
+                &mut self,
+                msg: LoginWithEmail,
+            ) -> Result<LoginResponse, LoginError> {
+                use self::schema::users::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                let mut user = None;
+                for address in lookup_emails(&msg.email, &msg.typed_email) {
+                    user = users
+                        .filter(email.eq(address))
+                        .select((
+                            id,
+                            password_hash,
+                            email_verified,
+                            suspended_at.is_not_null(),
+                        ))
+                        .first::<(i64, String, bool, bool)>(conn)
+                        .optional()
+                        .map_err(LoginError::from)?;
+                    if user.is_some() {
+                        break;
+                    }
+                }
+
+                // Always verify a hash, so a missing email can't be told apart
+                // from a wrong password by the response time.
+                let password_matches = verify_password(
+                    user.as_ref().map(|(_, hash, _, _)| hash.as_str()),
+                    &msg.password,
+                );
+
+                match user {
+                    Some((_, _, _, true)) if password_matches => Err(LoginError::AccountSuspended),
+                    Some((_, _, false, _)) if password_matches && msg.require_verified_email => {
+                        Err(LoginError::EmailNotVerified)
+                    }
+                    Some((user_id, _, _, _)) if password_matches => {
+                        Self::insert_session(conn, user_id)
+                            .map(|session| LoginResponse {
+                                token: session.token,
+                            })
+                            .map_err(LoginError::from)
+                    }
+                    _ => Err(LoginError::InvalidCredentials),
+                }
+            }
+
+            fn search_users(&mut self, msg: SearchUsers) -> Result<SearchResults, SearchError> {
+                self.0.get()?.search_users(msg)
+            }
+
+            fn create_session(&mut self, msg: CreateSession) -> Result<models::Session, DbError> {
+                let conn = &self.0.get()?;
+
+                Ok(Self::insert_session(conn, msg.user_id)?)
+            }
+
+            /// The active user owning a non expired session token
+            fn resolve_session(
+                &mut self,
+                msg: ResolveSession,
+            ) -> Result<Option<models::User>, DbError> {
+                use self::schema::sessions::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                let owner = sessions
+                    .inner_join(schema::users::table)
+                    .filter(token.eq(msg.token))
+                    .filter(expires_at.gt(now()))
+                    .filter(schema::users::suspended_at.is_null())
+                    .select(schema::users::all_columns)
+                    .first::<models::User>(conn)
+                    .optional()?;
+
+                Ok(owner)
+            }
+
+            fn issue_verification_token(
+                &mut self,
+                msg: IssueVerificationToken,
+            ) -> Result<models::VerificationToken, DbError> {
+                use self::schema::verification_tokens::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                let new_token = generate_token();
+                let new_verification_token = models::NewVerificationToken {
+                    token: &new_token,
+                    user_id: msg.user_id,
+                    expires_at: now() + Duration::days(VERIFICATION_TTL_DAYS),
+                };
+
+                diesel::insert_into(verification_tokens)
+                    .values(&new_verification_token)
+                    .execute(conn)?;
+
+                let verification_token = verification_tokens
+                    .filter(token.eq(&new_token))
+                    .first::<models::VerificationToken>(conn)?;
+
+                Ok(verification_token)
+            }
+
+            /// Marks the email verified, the invitations sent to it are
+            /// handed over to its owner only then
+            fn verify_email(&mut self, msg: VerifyEmail) -> Result<models::User, VerifyEmailError> {
+                use self::schema::verification_tokens::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                let verified_user = conn
+                    .transaction::<_, diesel::result::Error, _>(|| {
+                        let owner_id = verification_tokens
+                            .filter(token.eq(&msg.token))
+                            .filter(expires_at.gt(now()))
+                            .select(user_id)
+                            .first::<i64>(conn)
+                            .optional()?;
+
+                        match owner_id {
+                            Some(owner_id) => {
+                                diesel::delete(verification_tokens.filter(user_id.eq(owner_id)))
+                                    .execute(conn)?;
+
+                                diesel::update(schema::users::table.find(owner_id))
+                                    .set(schema::users::email_verified.eq(true))
+                                    .execute(conn)?;
+
+                                let user = Self::find_user(conn, owner_id)?;
+                                if let Some(ref user) = user {
+                                    let email = msg.canonicalization.canonicalize(&user.email);
+                                    conn.claim_email_invitations(user.id, &email)?;
+                                }
+
+                                Ok(user)
+                            }
+                            None => Ok(None),
+                        }
+                    })
+                    .map_err(VerifyEmailError::from)?;
+
+                verified_user.ok_or(VerifyEmailError::InvalidToken)
+            }
+
+            /// Counts the request against the limit of its email, registered
+            /// or not, then issues a token when a user owns the email
+            fn request_password_reset(
+                &mut self,
+                msg: RequestPasswordReset,
+            ) -> Result<Option<PasswordReset>, RequestPasswordResetError> {
+                use self::schema::password_reset_requests::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                conn.transaction::<_, RequestPasswordResetError, _>(|| {
+                    conn.lock_password_resets(&msg.email)?;
+
+                    let an_hour_ago = now() - Duration::hours(1);
+                    diesel::delete(password_reset_requests.filter(requested_at.le(an_hour_ago)))
+                        .execute(conn)?;
+
+                    let recent_requests = password_reset_requests
+                        .filter(email.eq(&msg.email))
+                        .count()
+                        .get_result::<i64>(conn)?;
+
+                    if recent_requests >= MAX_PASSWORD_RESETS_PER_HOUR {
+                        return Err(RequestPasswordResetError::TooManyRequests);
+                    }
+
+                    diesel::insert_into(password_reset_requests)
+                        .values(&models::NewPasswordResetRequest {
+                            email: &msg.email,
+                            requested_at: now(),
+                        })
+                        .execute(conn)?;
+
+                    let mut owner = None;
+                    for address in lookup_emails(&msg.email, &msg.typed_email) {
+                        owner = schema::users::table
+                            .filter(schema::users::email.eq(address))
+                            .select((schema::users::id, schema::users::email))
+                            .first::<(i64, String)>(conn)
+                            .optional()?;
+                        if owner.is_some() {
+                            break;
+                        }
+                    }
+
+                    let (owner_id, owner_email) = match owner {
+                        Some(owner) => owner,
+                        None => return Ok(None),
+                    };
+
+                    let new_token = generate_token();
+                    let new_reset_token = models::NewPasswordResetToken {
+                        token: &new_token,
+                        user_id: owner_id,
+                        expires_at: now() + Duration::hours(PASSWORD_RESET_TTL_HOURS),
+                    };
+
+                    diesel::insert_into(schema::password_reset_tokens::table)
+                        .values(&new_reset_token)
+                        .execute(conn)?;
+
+                    let reset_token = schema::password_reset_tokens::table
+                        .filter(schema::password_reset_tokens::token.eq(&new_token))
+                        .first::<models::PasswordResetToken>(conn)?;
+
+                    Ok(Some(PasswordReset {
+                        token: reset_token,
+                        email: owner_email,
+                    }))
+                })
+            }
+
+            /// Redeems the token, sets the new password and logs the user out
+            /// everywhere
+            fn reset_password(&mut self, msg: ResetPassword) -> Result<(), ResetPasswordError> {
+                use self::schema::password_reset_tokens::dsl::*;
+
+                let conn = &self.0.get()?;
+
+                let new_password_hash = hash_password(&msg.password);
+
+                let reset = conn
+                    .transaction::<_, diesel::result::Error, _>(|| {
+                        let owner_id = password_reset_tokens
+                            .filter(token.eq(&msg.token))
+                            .filter(expires_at.gt(now()))
+                            .select(user_id)
+                            .first::<i64>(conn)
+                            .optional()?;
+
+                        let owner_id = match owner_id {
+                            Some(owner_id) => owner_id,
+                            None => return Ok(false),
+                        };
+
+                        diesel::update(schema::users::table.find(owner_id))
+                            .set(schema::users::password_hash.eq(&new_password_hash))
+                            .execute(conn)?;
+
+                        diesel::delete(password_reset_tokens.filter(user_id.eq(owner_id)))
+                            .execute(conn)?;
+
+                        diesel::delete(
+                            schema::sessions::table.filter(schema::sessions::user_id.eq(owner_id)),
+                        )
+                        .execute(conn)?;
+
+                        Ok(true)
+                    })
+                    .map_err(ResetPasswordError::from)?;
+
+                if reset {
+                    Ok(())
+                } else {
+                    Err(ResetPasswordError::InvalidToken)
+                }
+            }
+
+            fn set_suspended(
+                &mut self,
+                msg: SetSuspended,
+            ) -> Result<Option<models::User>, DbError> {
+                let conn = &self.0.get()?;
+
+                let user = conn.transaction::<_, diesel::result::Error, _>(|| {
+                    let user = match Self::find_user(conn, msg.id)? {
+                        Some(user) => user,
+                        None => return Ok(None),
+                    };
+
+                    // suspending again keeps the time of the first suspension
+                    if msg.suspended == user.is_suspended() {
+                        return Ok(Some(user));
+                    }
+
+                    if msg.suspended {
+                        diesel::delete(
+                            schema::sessions::table.filter(schema::sessions::user_id.eq(msg.id)),
+                        )
+                        .execute(conn)?;
+                    }
+
+                    let suspended_at = if msg.suspended { Some(now()) } else { None };
+                    diesel::update(schema::users::table.find(msg.id))
+                        .set(schema::users::suspended_at.eq(suspended_at))
+                        .execute(conn)?;
+
+                    Self::find_user(conn, msg.id)
+                })?;
+
+                Ok(user)
+            }
+
+            fn set_role(&mut self, msg: SetRole) -> Result<Option<models::User>, DbError> {
+                let conn = &self.0.get()?;
+
+                diesel::update(schema::users::table.find(msg.id))
+                    .set(schema::users::role.eq(msg.role.as_str()))
+                    .execute(conn)?;
+
+                Ok(Self::find_user(conn, msg.id)?)
+            }
+
+            fn check_connection(&mut self, _: CheckConnection) -> Result<(), DbError> {
+                self.0.get()?;
+                Ok(())
+            }
+        }
+    };
+}
+
+diesel_user_repository!(PgConnection);
+#[cfg(feature = "sqlite")]
+diesel_user_repository!(::diesel::sqlite::SqliteConnection);
+
+/// This is users executor actor, it owns one repository per worker.
+pub struct UsersExecutor(pub Box<dyn UserRepository>);
+
+impl Actor for UsersExecutor {
+    type Context = SyncContext<Self>;
+}
+
+/// Forwards a message to the method of the repository handling it
+macro_rules! delegate_to_repository {
+    ($message:ty, $method:ident) => {
+        impl Handler<$message> for UsersExecutor {
+            type Result = <$message as Message>::Result;
+
+            fn handle(&mut self, msg: $message, _: &mut Self::Context) -> Self::Result {
+                self.0.$method(msg)
+            }
+        }
+    };
+}
+
+delegate_to_repository!(CreateUser, create_user);
+delegate_to_repository!(GetUser, get_user);
+delegate_to_repository!(UpdateUser, update_user);
+delegate_to_repository!(DeleteUser, delete_user);
+delegate_to_repository!(LoginWithEmail, login_with_email);
+delegate_to_repository!(SearchUsers, search_users);
+delegate_to_repository!(CreateSession, create_session);
+delegate_to_repository!(ResolveSession, resolve_session);
+delegate_to_repository!(IssueVerificationToken, issue_verification_token);
+delegate_to_repository!(VerifyEmail, verify_email);
+delegate_to_repository!(RequestPasswordReset, request_password_reset);
+delegate_to_repository!(ResetPassword, reset_password);
+delegate_to_repository!(SetSuspended, set_suspended);
+delegate_to_repository!(SetRole, set_role);
+delegate_to_repository!(CheckConnection, check_connection);
+
+// every message of the workers comes tagged with the request it serves
+traced_handlers!(
+    UsersExecutor,
+    "users",
+    CreateUser,
+    GetUser,
+    UpdateUser,
+    DeleteUser,
+    LoginWithEmail,
+    SearchUsers,
+    CreateSession,
+    ResolveSession,
+    IssueVerificationToken,
+    VerifyEmail,
+    RequestPasswordReset,
+    ResetPassword,
+    SetSuspended,
+    SetRole,
+    CheckConnection,
+);
@@ -0,0 +1,411 @@
+//! User search
+use actix::prelude::*;
+use diesel::dsl::sql;
+use diesel::expression::bound::Bound;
+use diesel::expression::{SqlLiteral, UncheckedBind};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Float, Text};
+
+use super::lower;
+use super::DbError;
+use models::{self, UserRole};
+use schema;
+
+/// Weights of the name and about words in the relevance, as `setweight` 'A'
+/// and 'B' rank them in Postgres
+#[cfg(any(test, feature = "sqlite"))]
+const NAME_WEIGHT: f32 = 1.0;
+#[cfg(any(test, feature = "sqlite"))]
+const ABOUT_WEIGHT: f32 = 0.4;
+
+/// Page size of searches which don't ask for one
+pub const DEFAULT_SEARCH_LIMIT: i64 = 20;
+/// Largest page size a search may ask for
+pub const MAX_SEARCH_LIMIT: i64 = 100;
+
+/// Order of search results
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    /// Best matches of `query` first, by id without a `query`
+    Relevance,
+    Name,
+    Id,
+}
+
+impl Default for SearchSort {
+    fn default() -> Self {
+        SearchSort::Relevance
+    }
+}
+
+/// Searches users, all the given filters have to match.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SearchUsers {
+    /// Words of the name or about, or an exact email with `match_email`
+    pub query: Option<String>,
+    /// Part of the name, case insensitive
+    pub name: Option<String>,
+    /// Email, case insensitive, matches nobody without `match_email`
+    pub email: Option<String>,
+    pub limit: Option<i64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub sort: SearchSort,
+    /// Count all the matches, not only the returned page
+    #[serde(default)]
+    pub with_total: bool,
+    /// Leave out users which did not verify their email yet
+    #[serde(skip)]
+    pub only_verified: bool,
+    /// Keep suspended users, left out otherwise
+    #[serde(skip)]
+    pub include_suspended: bool,
+    /// Keep only suspended users
+    #[serde(skip)]
+    pub only_suspended: bool,
+    /// Keep only users of this role
+    #[serde(skip)]
+    pub role: Option<UserRole>,
+    /// Find users by their email, which only admins see
+    #[serde(skip)]
+    pub match_email: bool,
+}
+
+/// Body of the deprecated `POST /users/search`, same as `query` of
+/// `SearchUsers`.
+#[derive(Deserialize, Serialize)]
+pub struct SearchWithKeyword {
+    pub keyword: String,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub with_total: bool,
+}
+
+impl From<SearchWithKeyword> for SearchUsers {
+    fn from(search: SearchWithKeyword) -> Self {
+        SearchUsers {
+            query: Some(search.keyword),
+            limit: search.limit,
+            cursor: search.cursor,
+            with_total: search.with_total,
+            ..SearchUsers::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchResults {
+    pub users: Vec<models::User>,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+    pub total: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum SearchError {
+    InvalidCursor,
+    DbError(DbError),
+}
+
+db_error_conversions!(SearchError);
+
+impl Message for SearchUsers {
+    type Result = Result<SearchResults, SearchError>;
+}
+
+/// `users.search_vector` is maintained by a trigger and left out of the
+/// schema, it is matched against the query with raw sql.
+fn text_query(
+    query: &str,
+) -> SqlLiteral<Bool, UncheckedBind<SqlLiteral<Bool>, Bound<Text, String>>> {
+    sql::<Bool>("users.search_vector @@ plainto_tsquery('simple', ")
+        .bind::<Text, _>(query.to_string())
+        .sql(")")
+}
+
+fn text_rank(
+    query: &str,
+) -> SqlLiteral<Float, UncheckedBind<SqlLiteral<Float>, Bound<Text, String>>> {
+    sql::<Float>("ts_rank(users.search_vector, plainto_tsquery('simple', ")
+        .bind::<Text, _>(query.to_string())
+        .sql("))")
+}
+
+/// `LIKE` pattern matching the text anywhere, its wildcards taken literally
+fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+/// Non blank query string parameter
+fn filter_value(value: &Option<String>) -> Option<&str> {
+    value
+        .as_ref()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+/// Users matching the search, unordered and unpaginated
+fn search_matches(msg: &SearchUsers) -> schema::users::BoxedQuery<'static, Pg> {
+    use self::schema::users::dsl::*;
+
+    let mut found = users.into_boxed();
+
+    if let Some(text) = msg.query_text() {
+        found = if msg.match_email {
+            found.filter(text_query(text).or(email.eq(text.to_string())))
+        } else {
+            found.filter(text_query(text))
+        };
+    }
+
+    if let Some(part) = msg.name_part() {
+        found = found.filter(name.ilike(contains_pattern(part)));
+    }
+
+    if let Some(address) = msg.email_address() {
+        found = if msg.match_email {
+            found.filter(lower(email).eq(address.to_lowercase()))
+        } else {
+            found.filter(sql::<Bool>("FALSE"))
+        };
+    }
+
+    if msg.only_verified {
+        found = found.filter(email_verified.eq(true));
+    }
+
+    if msg.only_suspended {
+        found = found.filter(suspended_at.is_not_null());
+    } else if !msg.include_suspended {
+        found = found.filter(suspended_at.is_null());
+    }
+
+    if let Some(wanted) = msg.role {
+        found = found.filter(role.eq(wanted.as_str()));
+    }
+
+    found
+}
+
+impl SearchUsers {
+    /// Page size asked for, within the allowed bounds
+    pub fn page_limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .max(1)
+            .min(MAX_SEARCH_LIMIT)
+    }
+
+    /// Number of matches to skip, the cursor is the offset of the page. The
+    /// offset of the next page has to fit too.
+    pub fn page_offset(&self) -> Result<i64, SearchError> {
+        match self.cursor {
+            Some(ref cursor) => match cursor.parse::<i64>() {
+                Ok(offset) if offset >= 0 => offset
+                    .checked_add(self.page_limit())
+                    .map(|_| offset)
+                    .ok_or(SearchError::InvalidCursor),
+                _ => Err(SearchError::InvalidCursor),
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// Non blank words or email to match
+    pub fn query_text(&self) -> Option<&str> {
+        filter_value(&self.query)
+    }
+
+    /// Non blank part of the name to match
+    pub fn name_part(&self) -> Option<&str> {
+        filter_value(&self.name)
+    }
+
+    /// Non blank email to match
+    pub fn email_address(&self) -> Option<&str> {
+        filter_value(&self.email)
+    }
+}
+
+pub fn search_users(conn: &PgConnection, msg: SearchUsers) -> Result<SearchResults, SearchError> {
+    use self::schema::users::dsl::*;
+
+    let limit = msg.page_limit();
+    let offset = msg.page_offset()?;
+
+    let mut found = search_matches(&msg);
+    found = match (msg.sort, msg.query_text()) {
+        (SearchSort::Relevance, Some(text)) => {
+            found.order(text_rank(text).desc()).then_order_by(id.asc())
+        }
+        (SearchSort::Name, _) => found.order(name.asc()).then_order_by(id.asc()),
+        (SearchSort::Relevance, None) | (SearchSort::Id, _) => found.order(id.asc()),
+    };
+
+    // one more than asked tells whether there is a next page
+    let mut page = found
+        .limit(limit + 1)
+        .offset(offset)
+        .load::<models::User>(conn)?;
+
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        Some((offset + limit).to_string())
+    } else {
+        None
+    };
+
+    let total = if msg.with_total {
+        Some(search_matches(&msg).count().get_result::<i64>(conn)?)
+    } else {
+        None
+    };
+
+    Ok(SearchResults {
+        users: page,
+        next_cursor,
+        total,
+    })
+}
+
+/// Lowercase words as the 'simple' text search configuration splits them
+#[cfg(any(test, feature = "sqlite"))]
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Relevance of the user for the query words, `None` unless every word is
+/// in the name or the about
+#[cfg(any(test, feature = "sqlite"))]
+fn words_rank(user: &models::User, query_words: &[String]) -> Option<f32> {
+    let name_words = words(&user.name);
+    let about_words = words(&user.about);
+
+    let mut rank = 0.0;
+    for query_word in query_words {
+        let in_name = name_words.iter().filter(|word| *word == query_word).count();
+        let in_about = about_words
+            .iter()
+            .filter(|word| *word == query_word)
+            .count();
+        if in_name + in_about == 0 {
+            return None;
+        }
+        rank += in_name as f32 * NAME_WEIGHT + in_about as f32 * ABOUT_WEIGHT;
+    }
+
+    Some(rank)
+}
+
+/// Relevance of the user when it matches all the filters of the search
+#[cfg(any(test, feature = "sqlite"))]
+fn search_rank(user: &models::User, msg: &SearchUsers) -> Option<f32> {
+    let mut rank = 0.0;
+
+    if let Some(text) = msg.query_text() {
+        let query_words = words(text);
+        rank = match words_rank(user, &query_words) {
+            Some(words_rank) if !query_words.is_empty() => words_rank,
+            _ if msg.match_email && user.email == text => 0.0,
+            _ => return None,
+        };
+    }
+
+    if let Some(part) = msg.name_part() {
+        if !user.name.to_lowercase().contains(&part.to_lowercase()) {
+            return None;
+        }
+    }
+
+    if let Some(address) = msg.email_address() {
+        if !msg.match_email || user.email.to_lowercase() != address.to_lowercase() {
+            return None;
+        }
+    }
+
+    if msg.only_verified && !user.email_verified {
+        return None;
+    }
+
+    let suspended_matches = if msg.only_suspended {
+        user.is_suspended()
+    } else {
+        msg.include_suspended || !user.is_suspended()
+    };
+    if !suspended_matches {
+        return None;
+    }
+
+    if msg.role.map_or(false, |wanted| user.role() != wanted) {
+        return None;
+    }
+
+    Some(rank)
+}
+
+/// Search among users loaded in memory, for the backends without Postgres'
+/// full text search. Words are matched and ranked like the `simple` text
+/// search configuration of the `users.search_vector` trigger does.
+#[cfg(any(test, feature = "sqlite"))]
+pub fn search_loaded_users(
+    users: &[models::User],
+    msg: &SearchUsers,
+) -> Result<SearchResults, SearchError> {
+    let limit = msg.page_limit();
+    let offset = msg.page_offset()?;
+
+    let mut found = users
+        .iter()
+        .filter_map(|user| search_rank(user, msg).map(|rank| (rank, user)))
+        .collect::<Vec<_>>();
+
+    match (msg.sort, msg.query_text()) {
+        (SearchSort::Relevance, Some(_)) => found.sort_by(|(rank_a, user_a), (rank_b, user_b)| {
+            rank_b
+                .partial_cmp(rank_a)
+                .unwrap()
+                .then(user_a.id.cmp(&user_b.id))
+        }),
+        (SearchSort::Name, _) => found.sort_by(|(_, user_a), (_, user_b)| {
+            user_a
+                .name
+                .cmp(&user_b.name)
+                .then(user_a.id.cmp(&user_b.id))
+        }),
+        (SearchSort::Relevance, None) | (SearchSort::Id, _) => {
+            found.sort_by_key(|(_, user)| user.id)
+        }
+    }
+
+    let total = found.len() as i64;
+    let page = found
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .map(|(_, user)| user.clone())
+        .collect::<Vec<_>>();
+
+    let next_cursor = if offset + limit < total {
+        Some((offset + limit).to_string())
+    } else {
+        None
+    };
+
+    Ok(SearchResults {
+        users: page,
+        next_cursor,
+        total: if msg.with_total { Some(total) } else { None },
+    })
+}
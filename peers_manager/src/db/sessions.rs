@@ -0,0 +1,25 @@
+//! Session token storage
+use actix::prelude::*;
+
+use super::DbError;
+use models;
+
+/// How long a freshly issued session token stays valid.
+pub const SESSION_TTL_DAYS: i64 = 30;
+
+pub struct CreateSession {
+    pub user_id: i64,
+}
+
+impl Message for CreateSession {
+    type Result = Result<models::Session, DbError>;
+}
+
+/// Looks up the active user owning a non expired session token.
+pub struct ResolveSession {
+    pub token: String,
+}
+
+impl Message for ResolveSession {
+    type Result = Result<Option<models::User>, DbError>;
+}
@@ -0,0 +1,150 @@
+//! Users in a SQLite file
+//!
+//! For single node deployments without Postgres, built with the `sqlite`
+//! feature. Only users, their sessions and tokens are kept here, the routes of
+//! bands, events and invitations are left out in this mode. The queries are
+//! the ones of `DbExecutor`, see `db::repository`.
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool};
+use diesel::sqlite::SqliteConnection;
+
+use super::repository::UsersBackend;
+use super::search::{search_loaded_users, SearchError, SearchResults, SearchUsers};
+use models;
+use schema;
+
+pub type SqlitePool = Pool<ConnectionManager<SqliteConnection>>;
+
+/// Settings SQLite only keeps per connection
+#[derive(Debug)]
+struct ConnectionSettings;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionSettings {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        // cascade the deletion of users to their sessions and tokens, and
+        // wait for the other workers instead of failing on a locked database
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Pool of connections to the database file at `path`
+pub fn create_pool(path: &str, max_size: u32) -> Result<SqlitePool, ::r2d2::Error> {
+    Pool::builder()
+        .max_size(max_size)
+        .connection_customizer(Box::new(ConnectionSettings))
+        .build(ConnectionManager::<SqliteConnection>::new(path))
+}
+
+impl UsersBackend for SqliteConnection {
+    /// Loads the whole users table on every search and matches them in
+    /// memory, fine for the few users of one node only
+    fn search_users(&self, msg: SearchUsers) -> Result<SearchResults, SearchError> {
+        let users = schema::users::table.load::<models::User>(self)?;
+
+        search_loaded_users(&users, &msg)
+    }
+
+    /// No invitations are kept in SQLite
+    fn claim_email_invitations(&self, _: i64, _: &str) -> QueryResult<()> {
+        Ok(())
+    }
+
+    /// SQLite writes one transaction at a time, the first statement of a
+    /// request deletes the expired requests and waits for the write lock
+    fn lock_password_resets(&self, _: &str) -> QueryResult<()> {
+        Ok(())
+    }
+
+    /// No bands are kept in SQLite
+    fn owns_band_alone(&self, _: i64) -> QueryResult<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod sqlite_tests {
+
+    use super::*;
+    use db::migrations::run_pending_migrations;
+    use db::repository::UserRepository;
+    use db::sessions::ResolveSession;
+    use db::users::{CreateUser, CreateUserError, DbExecutor, DeleteUser, LoginWithEmail};
+
+    /// Repository on a fresh in-memory database, one connection keeps it alive
+    fn create_repository() -> DbExecutor<SqliteConnection> {
+        let pool = create_pool(":memory:", 1).unwrap();
+        run_pending_migrations(&*pool.get().unwrap(), &mut ::std::io::sink()).unwrap();
+
+        DbExecutor(pool)
+    }
+
+    fn new_user(name: &str, about: &str) -> CreateUser {
+        CreateUser {
+            name: name.to_string(),
+            email: format!("{}@gmail.com", name.to_lowercase()),
+            about: about.to_string(),
+            password: "password 1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_create_and_login() {
+        let mut repository = create_repository();
+
+        let user = repository.create_user(new_user("Alice", "")).unwrap();
+        assert_eq!(user.email, "alice@gmail.com");
+
+        match repository.create_user(new_user("Alice", "")) {
+            Err(CreateUserError::UserAlreadyExists) => (),
+            _ => panic!("duplicate email was accepted"),
+        }
+
+        let login = repository
+            .login_with_email(LoginWithEmail {
+                email: "alice@gmail.com".to_string(),
+                password: "password 1".to_string(),
+                require_verified_email: false,
+                typed_email: None,
+            })
+            .unwrap();
+        let owner = repository
+            .resolve_session(ResolveSession { token: login.token })
+            .unwrap();
+        assert_eq!(owner.map(|owner| owner.id), Some(user.id));
+
+        // sessions go with their user
+        repository.delete_user(DeleteUser { id: user.id }).unwrap();
+        let sessions = schema::sessions::table
+            .count()
+            .get_result::<i64>(&*repository.0.get().unwrap())
+            .unwrap();
+        assert_eq!(sessions, 0);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut repository = create_repository();
+
+        repository
+            .create_user(new_user("Bob", "plays guitar sometimes"))
+            .unwrap();
+        repository.create_user(new_user("Guitarist", "")).unwrap();
+        repository
+            .create_user(new_user("Carol", "guitar teacher"))
+            .unwrap();
+
+        let results = repository
+            .search_users(SearchUsers {
+                query: Some("guitar".to_string()),
+                limit: Some(1),
+                with_total: true,
+                ..SearchUsers::default()
+            })
+            .unwrap();
+        assert_eq!(results.total, Some(2));
+        assert_eq!(results.users[0].name, "Bob");
+        assert_eq!(results.next_cursor, Some("1".to_string()));
+    }
+}
@@ -0,0 +1,8 @@
+//! Random tokens handed out to users
+use rand::Rng;
+
+/// Opaque random token, 32 bytes hex encoded.
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
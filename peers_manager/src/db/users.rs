@@ -1,15 +1,22 @@
 //! Db executor actor
+use std::time::Duration;
+
 use actix::prelude::*;
-use actix_web::*;
 use diesel;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 
+use super::DbError;
 use models;
-use schema;
 
 /// This is db executor actor. We are going to run 3 of them in parallel.
-pub struct DbExecutor(pub Pool<ConnectionManager<PgConnection>>);
+///
+/// Bands, events and invitations are kept in Postgres only. The users are
+/// kept by the `UserRepository` of the executor on any connection of a
+/// diesel backend, see `db::repository`.
+pub struct DbExecutor<C = PgConnection>(pub Pool<ConnectionManager<C>>)
+where
+    C: Connection + 'static;
 
 /// This is only message that this actor can handle, but it is easy to extend
 /// number of messages.
@@ -17,14 +24,17 @@ pub struct CreateUser {
     pub name: String,
     pub email: String,
     pub about: String,
+    pub password: String,
 }
 
 #[derive(Debug)]
 pub enum CreateUserError {
     UserAlreadyExists,
-    DbError(diesel::result::Error),
+    DbError(DbError),
 }
 
+db_error_conversions!(CreateUserError);
+
 impl Message for CreateUser {
     type Result = Result<models::User, CreateUserError>;
 }
@@ -33,110 +43,158 @@ impl Actor for DbExecutor {
     type Context = SyncContext<Self>;
 }
 
-impl Handler<CreateUser> for DbExecutor {
-    type Result = Result<models::User, CreateUserError>;
+/// Messages taking longer are logged, with the request which sent them
+pub const SLOW_MESSAGE: Duration = Duration::from_millis(500);
 
-    fn handle(&mut self, msg: CreateUser, _: &mut Self::Context) -> Self::Result {
-        use self::schema::users::dsl::*;
-
-        let new_user = models::NewUser {
-            name: &msg.name,
-            email: &msg.email,
-            about: &msg.about,
-        };
-
-        let conn = &self.0.get().unwrap();
-
-        diesel::insert_into(users)
-            .values(&new_user)
-            .get_result::<models::User>(conn)
-            .map_err(|db_error| {
-                match &db_error {
-                    diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::UniqueViolation,
-                        db_error,
-                    ) => match db_error.constraint_name() {
-                        Some("email") => return CreateUserError::UserAlreadyExists,
-                        _ => {}
-                    },
-                    _ => {}
-                };
-
-                CreateUserError::DbError(db_error)
-            })
-    }
+/// Checks a connection out of the pool, answered once one is free
+pub struct CheckConnection;
+
+impl Message for CheckConnection {
+    type Result = Result<(), DbError>;
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct LoginWithEmail {
-    pub email: String,
+impl Handler<CheckConnection> for DbExecutor {
+    type Result = Result<(), DbError>;
+
+    fn handle(&mut self, _: CheckConnection, _: &mut Self::Context) -> Self::Result {
+        self.0.get()?;
+        Ok(())
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct LoginResponse {
-    pub token: i64,
+// every message of the workers comes tagged with the request it serves
+traced_handlers!(
+    DbExecutor,
+    "database",
+    CheckConnection,
+    super::bands::CreateBand,
+    super::bands::GetBand,
+    super::bands::ListBands,
+    super::bands::UpdateBand,
+    super::bands::DeleteBand,
+    super::bands::ListBandMembers,
+    super::bands::SetBandMember,
+    super::bands::RemoveBandMember,
+    super::events::CreateEvent,
+    super::events::GetEvent,
+    super::events::UpdateEvent,
+    super::events::CancelEvent,
+    super::events::ListUpcomingEvents,
+    super::events::SetRsvp,
+    super::events::ListAttendees,
+    super::invitations::CreateInvitation,
+    super::invitations::ListPendingInvitations,
+    super::invitations::AcceptInvitation,
+    super::invitations::DeclineInvitation,
+    super::invitations::RevokeInvitation,
+);
+
+/// Emails a user is looked up by when logging in or asking a password reset:
+/// the canonical one, then the one typed when it differs. Users registered
+/// before emails were canonicalized are only found by the latter.
+pub fn lookup_emails<'a>(email: &'a str, typed_email: &'a Option<String>) -> Vec<&'a str> {
+    let mut emails = vec![email];
+    if let Some(ref typed_email) = *typed_email {
+        if typed_email != email {
+            emails.push(typed_email);
+        }
+    }
+    emails
 }
 
-#[derive(Debug)]
-pub enum LoginError {
-    InvalidCredentials,
-    DbError(diesel::result::Error),
+/// Whether the error is a violation of the unique `email` constraint. SQLite
+/// doesn't name the constraint, only the column in the message.
+pub fn is_email_taken(db_error: &diesel::result::Error) -> bool {
+    match db_error {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            db_error,
+        ) => {
+            db_error.constraint_name() == Some("email")
+                || db_error.message().ends_with("users.email")
+        }
+        _ => false,
+    }
 }
 
-impl Message for LoginWithEmail {
-    type Result = Result<LoginResponse, LoginError>;
+pub struct GetUser {
+    pub id: i64,
 }
 
-impl Handler<LoginWithEmail> for DbExecutor {
-    type Result = Result<LoginResponse, LoginError>;
+impl Message for GetUser {
+    type Result = Result<Option<models::User>, DbError>;
+}
 
-    fn handle(&mut self, msg: LoginWithEmail, _: &mut Self::Context) -> Self::Result {
-        use self::schema::users::dsl::*;
+/// Changes the given fields of a user, a changed email has to be verified again
+/// and the verification tokens of the previous one are dropped
+pub struct UpdateUser {
+    pub id: i64,
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub email: Option<String>,
+}
 
-        let conn = &self.0.get().unwrap();
+#[derive(Debug)]
+pub enum UpdateUserError {
+    NotFound,
+    UserAlreadyExists,
+    DbError(DbError),
+}
 
-        let token = schema::users::table
-            .filter(email.eq(msg.email))
-            .select(id)
-            .first(conn)
-            .optional()
-            .map_err(|db_error| LoginError::DbError(db_error))?;
+db_error_conversions!(UpdateUserError);
 
-        match token {
-            Some(token) => Ok(LoginResponse { token }),
-            None => Err(LoginError::InvalidCredentials),
-        }
-    }
+impl Message for UpdateUser {
+    type Result = Result<models::User, UpdateUserError>;
 }
 
-#[derive(Deserialize)]
-pub struct SearchWithKeyword {
-    pub keyword: String,
+/// Deletes the account, the bands and events it created are kept. Events the
+/// user organizes in person, not as a band, go with it.
+pub struct DeleteUser {
+    pub id: i64,
 }
 
-impl Message for SearchWithKeyword {
-    type Result = Result<Vec<models::User>, Error>;
+#[derive(Debug)]
+pub enum DeleteUserError {
+    /// The user is the only owner of a band, ownership is handed over first
+    LastOwner,
+    DbError(DbError),
 }
 
-impl Handler<SearchWithKeyword> for DbExecutor {
-    type Result = Result<Vec<models::User>, Error>;
+db_error_conversions!(DeleteUserError);
 
-    fn handle(&mut self, msg: SearchWithKeyword, _: &mut Self::Context) -> Self::Result {
-        use self::schema::users::dsl::*;
+impl Message for DeleteUser {
+    type Result = Result<(), DeleteUserError>;
+}
 
-        let conn = &self.0.get().unwrap();
+#[derive(Deserialize, Serialize)]
+pub struct LoginWithEmail {
+    pub email: String,
+    pub password: String,
+    /// Refuse users which did not verify their email yet
+    #[serde(skip)]
+    pub require_verified_email: bool,
+    /// The email as typed when `email` is its canonical form, see
+    /// `lookup_emails`
+    #[serde(skip)]
+    pub typed_email: Option<String>,
+}
 
-        let enquoted_keyword = enquote::enquote('%', &msg.keyword);
+#[derive(Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub token: String,
+}
 
-        let results = schema::users::table
-            .filter(
-                name.like(&enquoted_keyword)
-                    .or(about.like(&enquoted_keyword))
-                    .or(email.eq(&msg.keyword)),
-            )
-            .get_results::<models::User>(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error user search"))?;
+#[derive(Debug)]
+pub enum LoginError {
+    InvalidCredentials,
+    EmailNotVerified,
+    /// Right password of a suspended account
+    AccountSuspended,
+    DbError(DbError),
+}
 
-        Ok(results)
-    }
+db_error_conversions!(LoginError);
+
+impl Message for LoginWithEmail {
+    type Result = Result<LoginResponse, LoginError>;
 }
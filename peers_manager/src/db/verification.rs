@@ -0,0 +1,39 @@
+//! Email verification tokens
+use actix::prelude::*;
+
+use super::DbError;
+use models;
+use web::email_validator::Canonicalization;
+
+/// How long a verification link sent by email stays valid.
+pub const VERIFICATION_TTL_DAYS: i64 = 2;
+
+/// Issues a token proving ownership of the user's email once sent back.
+pub struct IssueVerificationToken {
+    pub user_id: i64,
+}
+
+impl Message for IssueVerificationToken {
+    type Result = Result<models::VerificationToken, DbError>;
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct VerifyEmail {
+    pub token: String,
+    /// Folds the email of the user as the emails of the invitations it
+    /// claims were, users registered before it keep their email as typed
+    #[serde(skip)]
+    pub canonicalization: Canonicalization,
+}
+
+#[derive(Debug)]
+pub enum VerifyEmailError {
+    InvalidToken,
+    DbError(DbError),
+}
+
+db_error_conversions!(VerifyEmailError);
+
+impl Message for VerifyEmail {
+    type Result = Result<models::User, VerifyEmailError>;
+}
@@ -0,0 +1,327 @@
+//! Signals of the process
+//!
+//! SIGINT and SIGTERM stop the server: it stops accepting connections and
+//! waits up to `server.shutdown_timeout` for the requests in flight, then the
+//! workers finish the messages they were sent and stop taking any, and the
+//! system stops. Sync actors can't end their thread, `ctx.stop()` only makes
+//! a new one, so the stopped workers are parked until the process exits. A second signal stops right away, as
+//! does SIGQUIT. SIGHUP reloads the configuration, only the settings
+//! `Reload` knows apply without a restart.
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+use actix::actors::signal::{ProcessSignals, Signal, SignalType, Subscribe};
+use actix::prelude::*;
+use actix_web::server::StopServer;
+use futures::{future, Future};
+
+use config::Config;
+use db::repository::UsersExecutor;
+use db::users::DbExecutor;
+use mail::MailExecutor;
+use web::app::{Reloadable, VerificationSettings};
+use web::email_validator::ValidateExecutor;
+use web::rate_limit::RateLimiter;
+
+/// Answered by a sync worker once every worker of its arbiter took one, each
+/// worker finished the messages it took before, so all of them were handled
+pub struct StopWorker(Arc<Barrier>);
+
+impl Message for StopWorker {
+    type Result = ();
+}
+
+/// Keeps the sync worker taking it from taking any other message
+pub struct Park;
+
+impl Message for Park {
+    type Result = ();
+}
+
+macro_rules! stop_on_message {
+    ($actor:ty) => {
+        impl Handler<StopWorker> for $actor {
+            type Result = ();
+
+            fn handle(&mut self, msg: StopWorker, _: &mut Self::Context) {
+                msg.0.wait();
+            }
+        }
+
+        impl Handler<Park> for $actor {
+            type Result = ();
+
+            fn handle(&mut self, _: Park, _: &mut Self::Context) {
+                loop {
+                    thread::park();
+                }
+            }
+        }
+    };
+}
+
+stop_on_message!(DbExecutor);
+stop_on_message!(UsersExecutor);
+stop_on_message!(ValidateExecutor);
+stop_on_message!(MailExecutor);
+
+/// Address of `count` workers started by `SyncArbiter::start`
+pub struct Workers<A: Actor> {
+    addr: Addr<A>,
+    count: usize,
+}
+
+impl<A> Workers<A>
+where
+    A: Actor<Context = SyncContext<A>> + Handler<StopWorker> + Handler<Park>,
+{
+    pub fn new(addr: Addr<A>, count: usize) -> Self {
+        Workers { addr, count }
+    }
+
+    /// Sends one `StopWorker` per worker, resolves once they all stopped or
+    /// after `timeout`
+    fn stop(
+        &self,
+        name: &'static str,
+        timeout: Duration,
+    ) -> Box<dyn Future<Item = (), Error = ()>> {
+        let all_stopping = Arc::new(Barrier::new(self.count));
+        let stopped = (0..self.count)
+            .map(|_| {
+                self.addr
+                    .send(StopWorker(all_stopping.clone()))
+                    .timeout(timeout)
+            })
+            .collect::<Vec<_>>();
+        // queued right behind, each worker takes one once past the barrier
+        for _ in 0..self.count {
+            self.addr.do_send(Park);
+        }
+
+        Box::new(future::join_all(stopped).then(move |result| {
+            if let Err(error) = result {
+                warn!("{} workers did not stop in time: {}", name, error);
+            }
+            Ok(())
+        }))
+    }
+}
+
+impl<A: Actor> Clone for Workers<A> {
+    fn clone(&self) -> Self {
+        Workers {
+            addr: self.addr.clone(),
+            count: self.count,
+        }
+    }
+}
+
+/// Settings shared with the server which a reload replaces, the others are
+/// only read at start
+#[derive(Clone)]
+pub struct Reload {
+    pub verification: Reloadable<VerificationSettings>,
+    pub rate_limiter: RateLimiter,
+}
+
+impl Reload {
+    pub fn apply(&self, config: &Config) {
+        self.verification.set(config.verification_settings());
+        self.rate_limiter.reload(&config.rate_limit);
+    }
+
+    /// Loads the configuration again, a broken one keeps the current
+    /// settings
+    fn reload(&self) {
+        match Config::load() {
+            Ok(config) => {
+                self.apply(&config);
+                info!("configuration reloaded");
+            }
+            Err(error) => error!("configuration not reloaded: {}", error),
+        }
+    }
+}
+
+/// Actor handling the signals of the process
+pub struct Lifecycle {
+    server: Recipient<StopServer>,
+    timeout: Duration,
+    db: Workers<DbExecutor>,
+    users: Workers<UsersExecutor>,
+    validators: Workers<ValidateExecutor>,
+    mailer: Workers<MailExecutor>,
+    reload: Reload,
+    stopping: bool,
+}
+
+impl Lifecycle {
+    pub fn new(
+        server: Recipient<StopServer>,
+        timeout: Duration,
+        db: Workers<DbExecutor>,
+        users: Workers<UsersExecutor>,
+        validators: Workers<ValidateExecutor>,
+        mailer: Workers<MailExecutor>,
+        reload: Reload,
+    ) -> Self {
+        Lifecycle {
+            server,
+            timeout,
+            db,
+            users,
+            validators,
+            mailer,
+            reload,
+            stopping: false,
+        }
+    }
+
+    fn shutdown(&mut self) {
+        if self.stopping {
+            warn!("stopping right away");
+            System::current().stop();
+            return;
+        }
+        self.stopping = true;
+        info!(
+            "stopping, waiting up to {}s for the requests in flight",
+            self.timeout.as_secs()
+        );
+
+        let timeout = self.timeout;
+        let (db, users, validators, mailer) = (
+            self.db.clone(),
+            self.users.clone(),
+            self.validators.clone(),
+            self.mailer.clone(),
+        );
+
+        // workers are only stopped once no request can send them messages
+        Arbiter::spawn(
+            self.server
+                .send(StopServer { graceful: true })
+                .then(move |_| {
+                    info!("server stopped, stopping the workers");
+                    db.stop("db", timeout).join4(
+                        users.stop("users", timeout),
+                        validators.stop("email validator", timeout),
+                        mailer.stop("mail", timeout),
+                    )
+                })
+                .then(|_| {
+                    info!("workers stopped");
+                    System::current().stop();
+                    Ok(())
+                }),
+        );
+    }
+}
+
+impl Actor for Lifecycle {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let signals = System::current().registry().get::<ProcessSignals>();
+        signals.do_send(Subscribe(ctx.address().recipient()));
+    }
+}
+
+impl Handler<Signal> for Lifecycle {
+    type Result = ();
+
+    fn handle(&mut self, signal: Signal, _: &mut Self::Context) {
+        match signal.0 {
+            SignalType::Int | SignalType::Term => self.shutdown(),
+            SignalType::Quit => {
+                warn!("stopping without waiting for the requests in flight");
+                System::current().stop();
+            }
+            SignalType::Hup => self.reload.reload(),
+            SignalType::Child => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod lifecycle_tests {
+
+    use super::*;
+    use config::{RateLimitConfig, RateLimitKey};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use web::rate_limit::memory::MemoryStore;
+
+    struct Work;
+
+    impl Message for Work {
+        type Result = ();
+    }
+
+    struct SlowWorker(Arc<AtomicUsize>);
+
+    impl Actor for SlowWorker {
+        type Context = SyncContext<Self>;
+    }
+
+    impl Handler<Work> for SlowWorker {
+        type Result = ();
+
+        fn handle(&mut self, _: Work, _: &mut Self::Context) {
+            thread::sleep(Duration::from_millis(50));
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    stop_on_message!(SlowWorker);
+
+    #[test]
+    fn test_stop_workers() {
+        let mut sys = System::new("test_stop_workers");
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let worker_done = done.clone();
+        let addr = SyncArbiter::start(2, move || SlowWorker(worker_done.clone()));
+        for _ in 0..5 {
+            addr.do_send(Work);
+        }
+
+        let workers = Workers::new(addr.clone(), 2);
+        sys.block_on(workers.stop("slow", Duration::from_secs(10)))
+            .unwrap();
+
+        // the messages sent before were handled, no worker takes new ones
+        assert_eq!(done.load(Ordering::SeqCst), 5);
+        addr.do_send(Work);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(done.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_apply_reload() {
+        let mut config = Config::load_environment("test").unwrap();
+        let rate_limiter = RateLimiter::new(&config.rate_limit, Arc::new(MemoryStore::new()));
+        let reload = Reload {
+            verification: Reloadable::new(config.verification_settings()),
+            rate_limiter: rate_limiter.clone(),
+        };
+        assert!(!reload.verification.get().required_for_login);
+
+        config.features.require_verified_email_for_login = true;
+        config.rate_limit = RateLimitConfig {
+            enabled: true,
+            ..config.rate_limit
+        };
+        reload.apply(&config);
+
+        assert!(reload.verification.get().required_for_login);
+        let check = || rate_limiter.check("/users/login", RateLimitKey::Email, "a@gmail.com");
+        for _ in 0..5 {
+            assert!(check().is_ok());
+        }
+        assert!(check().is_err());
+    }
+}
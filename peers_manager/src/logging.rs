@@ -0,0 +1,137 @@
+//! Log records, as text or as one JSON object per line
+//!
+//! Records carry the id of the http request they were written for. Workers
+//! handling a `Traced` message and the request id middleware set it for the
+//! current thread with `with_request_id`, records of background tasks have
+//! none.
+use std::cell::RefCell;
+use std::io::Write;
+
+use actix::Message;
+use chrono::{SecondsFormat, Utc};
+use env_logger;
+use log::Record;
+use serde_json::{Map, Value};
+
+use config::{LogConfig, LogFormat};
+
+thread_local! {
+    static REQUEST_ID: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Message `msg` sent while serving the request `request_id`
+pub struct Traced<M> {
+    pub request_id: Option<String>,
+    pub msg: M,
+}
+
+impl<M: Message> Message for Traced<M> {
+    type Result = M::Result;
+}
+
+/// Puts back the request id of the thread when `f` returns or panics
+struct Restore(Option<String>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        REQUEST_ID.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Runs `f` with the records of the thread tagged with `request_id`
+pub fn with_request_id<T, F: FnOnce() -> T>(request_id: Option<&str>, f: F) -> T {
+    let previous = REQUEST_ID.with(|current| current.replace(request_id.map(|id| id.to_string())));
+    let _restore = Restore(previous);
+
+    f()
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.with(|current| current.borrow().clone())
+}
+
+fn json_record(record: &Record) -> Value {
+    let mut fields = Map::new();
+    fields.insert(
+        "timestamp".to_string(),
+        Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+    );
+    fields.insert("level".to_string(), Value::from(record.level().to_string()));
+    fields.insert("target".to_string(), Value::from(record.target()));
+    fields.insert(
+        "message".to_string(),
+        Value::from(record.args().to_string()),
+    );
+    if let Some(request_id) = current_request_id() {
+        fields.insert("request_id".to_string(), Value::from(request_id));
+    }
+
+    Value::Object(fields)
+}
+
+fn text_record(record: &Record) -> String {
+    let request_id = current_request_id()
+        .map(|request_id| format!(" request_id={}", request_id))
+        .unwrap_or_default();
+
+    format!(
+        "[{} {} {}{}] {}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        record.level(),
+        record.target(),
+        request_id,
+        record.args()
+    )
+}
+
+/// Logs to stderr in the format of `config`, filtered by `RUST_LOG`
+pub fn init(config: &LogConfig) {
+    let mut builder = env_logger::Builder::from_default_env();
+    match config.format {
+        LogFormat::Text => builder.format(|buf, record| writeln!(buf, "{}", text_record(record))),
+        LogFormat::Json => builder.format(|buf, record| writeln!(buf, "{}", json_record(record))),
+    };
+    builder.init();
+}
+
+#[cfg(test)]
+mod logging_tests {
+
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_with_request_id() {
+        assert_eq!(current_request_id(), None);
+
+        with_request_id(Some("outer"), || {
+            assert_eq!(current_request_id(), Some("outer".to_string()));
+            with_request_id(None, || assert_eq!(current_request_id(), None));
+            assert_eq!(current_request_id(), Some("outer".to_string()));
+        });
+
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[test]
+    fn test_json_record() {
+        let json = with_request_id(Some("abc"), || {
+            json_record(
+                &Record::builder()
+                    .args(format_args!("slow query"))
+                    .level(Level::Warn)
+                    .target("peers_manager::db")
+                    .build(),
+            )
+        });
+
+        assert_eq!(json["level"], "WARN");
+        assert_eq!(json["target"], "peers_manager::db");
+        assert_eq!(json["message"], "slow query");
+        assert_eq!(json["request_id"], "abc");
+
+        let json = json_record(&Record::builder().args(format_args!("refreshed")).build());
+        assert!(json.get("request_id").is_none());
+    }
+}
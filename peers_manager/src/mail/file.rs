@@ -0,0 +1,40 @@
+//! Mailer writing each email to a file
+use chrono::Utc;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::{Email, MailError, Mailer};
+
+/// Writes emails as text files into a directory, handy for local runs.
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FileMailer {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&mut self, email: &Email) -> Result<(), MailError> {
+        fs::create_dir_all(&self.directory)?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%d%H%M%S%f"),
+            email.to.replace(|c: char| !c.is_alphanumeric(), "_")
+        );
+        let mut file = fs::File::create(self.directory.join(file_name))?;
+        write!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n",
+            email.to, email.subject, email.body
+        )?;
+
+        Ok(())
+    }
+}
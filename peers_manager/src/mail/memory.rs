@@ -0,0 +1,24 @@
+//! Mailer keeping emails in memory
+use std::sync::{Arc, Mutex};
+
+use super::{Email, MailError, Mailer};
+
+/// Emails sent so far, shared between the mailer workers and whoever reads them
+pub type Outbox = Arc<Mutex<Vec<Email>>>;
+
+pub struct MemoryMailer {
+    outbox: Outbox,
+}
+
+impl MemoryMailer {
+    pub fn new(outbox: Outbox) -> Self {
+        MemoryMailer { outbox }
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send(&mut self, email: &Email) -> Result<(), MailError> {
+        self.outbox.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
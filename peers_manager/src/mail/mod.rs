@@ -0,0 +1,89 @@
+//! Mail executor actor
+//!
+//! Sending mail blocks, so like diesel it runs on sync actors. The transport is
+//! pluggable: SMTP for deployments, a directory of files for local runs and an
+//! in-memory outbox for tests.
+use actix::prelude::*;
+use config::MailConfig;
+use std::fmt;
+use std::io;
+
+pub mod file;
+#[cfg(test)]
+pub mod memory;
+pub mod smtp;
+
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    Io(io::Error),
+    Transport(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MailError::Io(error) => write!(f, "mail io error: {}", error),
+            MailError::Transport(error) => write!(f, "mail transport error: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for MailError {
+    fn from(error: io::Error) -> Self {
+        MailError::Io(error)
+    }
+}
+
+/// Transport delivering emails
+pub trait Mailer: Send {
+    fn send(&mut self, email: &Email) -> Result<(), MailError>;
+}
+
+/// This is mail executor actor, it owns one transport per worker.
+pub struct MailExecutor(pub Box<dyn Mailer>);
+
+/// Delivers the email through the executor's transport
+pub struct SendMail(pub Email);
+
+impl Message for SendMail {
+    type Result = Result<(), MailError>;
+}
+
+impl Actor for MailExecutor {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<SendMail> for MailExecutor {
+    type Result = Result<(), MailError>;
+
+    fn handle(&mut self, msg: SendMail, _: &mut Self::Context) -> Self::Result {
+        self.0.send(&msg.0)
+    }
+}
+
+/// SMTP mailer when a relay is configured, files in the outbox directory
+/// otherwise
+pub fn create_mailer(config: &MailConfig) -> Result<Box<dyn Mailer>, MailError> {
+    match config.smtp {
+        Some(ref smtp) => {
+            let credentials = match (&smtp.username, &smtp.password) {
+                (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+                _ => None,
+            };
+
+            Ok(Box::new(smtp::SmtpMailer::new(
+                &smtp.relay,
+                credentials,
+                &smtp.from,
+            )?))
+        }
+        None => Ok(Box::new(file::FileMailer::new(config.outbox_dir.clone()))),
+    }
+}
@@ -0,0 +1,49 @@
+//! Mailer relaying emails to an SMTP server
+use lettre::smtp::authentication::Credentials;
+use lettre::{SmtpClient, SmtpTransport, Transport};
+use lettre_email::EmailBuilder;
+
+use super::{Email, MailError, Mailer};
+
+pub struct SmtpMailer {
+    from: String,
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    /// Connects over TLS to the relay, authenticating when credentials are given.
+    pub fn new(
+        relay: &str,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, MailError> {
+        let mut client = SmtpClient::new_simple(relay)
+            .map_err(|error| MailError::Transport(error.to_string()))?;
+
+        if let Some((username, password)) = credentials {
+            client = client.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer {
+            from: from.to_string(),
+            transport: client.transport(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&mut self, email: &Email) -> Result<(), MailError> {
+        let message = EmailBuilder::new()
+            .to(email.to.as_str())
+            .from(self.from.as_str())
+            .subject(email.subject.as_str())
+            .text(email.body.as_str())
+            .build()
+            .map_err(|error| MailError::Transport(error.to_string()))?;
+
+        self.transport
+            .send(message.into())
+            .map(|_| ())
+            .map_err(|error| MailError::Transport(error.to_string()))
+    }
+}
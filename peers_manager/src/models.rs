@@ -1,11 +1,69 @@
-use super::schema::users;
+use super::schema::{
+    band_members, bands, event_attendees, events, invitations, password_reset_requests,
+    password_reset_tokens, sessions, users, verification_tokens,
+};
+use chrono::NaiveDateTime;
 
-#[derive(Serialize, Queryable)]
+#[derive(Serialize, Deserialize, Queryable, Clone)]
 pub struct User {
     pub id: i64,
     pub name: String,
-    pub email: String,
     pub about: String,
+    pub email: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub email_verified: bool,
+    /// `UserRole` as text
+    pub role: String,
+    /// When an admin suspended the account, `None` while it is active
+    pub suspended_at: Option<NaiveDateTime>,
+}
+
+impl User {
+    pub fn role(&self) -> UserRole {
+        // the column only takes the known roles
+        UserRole::parse(&self.role).unwrap_or(UserRole::User)
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_at.is_some()
+    }
+}
+
+/// Role of a user on the whole site, stored as text in `users.role`. Each role
+/// may do what the ones before it may.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    User,
+    /// Sees suspended accounts
+    Moderator,
+    /// Sees emails, suspends and restores accounts and hands out roles
+    Admin,
+}
+
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::User => "user",
+            UserRole::Moderator => "moderator",
+            UserRole::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<UserRole> {
+        match role {
+            "user" => Some(UserRole::User),
+            "moderator" => Some(UserRole::Moderator),
+            "admin" => Some(UserRole::Admin),
+            _ => None,
+        }
+    }
+
+    /// Whether the role may do what `required` may
+    pub fn includes(&self, required: UserRole) -> bool {
+        *self >= required
+    }
 }
 
 #[derive(Insertable)]
@@ -14,4 +72,307 @@ pub struct NewUser<'a> {
     pub name: &'a str,
     pub email: &'a str,
     pub about: &'a str,
+    pub password_hash: &'a str,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "users"]
+pub struct UserChanges<'a> {
+    pub name: Option<&'a str>,
+    pub about: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub email_verified: Option<bool>,
+}
+
+impl<'a> UserChanges<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.about.is_none()
+            && self.email.is_none()
+            && self.email_verified.is_none()
+    }
+}
+
+#[derive(Queryable, Clone)]
+pub struct Session {
+    pub id: i64,
+    pub token: String,
+    pub user_id: i64,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession<'a> {
+    pub token: &'a str,
+    pub user_id: i64,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct VerificationToken {
+    pub id: i64,
+    pub token: String,
+    pub user_id: i64,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "verification_tokens"]
+pub struct NewVerificationToken<'a> {
+    pub token: &'a str,
+    pub user_id: i64,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Clone)]
+pub struct PasswordResetToken {
+    pub id: i64,
+    pub token: String,
+    pub user_id: i64,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "password_reset_tokens"]
+pub struct NewPasswordResetToken<'a> {
+    pub token: &'a str,
+    pub user_id: i64,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Reset asked for an email, registered or not
+#[derive(Insertable)]
+#[table_name = "password_reset_requests"]
+pub struct NewPasswordResetRequest<'a> {
+    pub email: &'a str,
+    pub requested_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Queryable)]
+pub struct Band {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+    /// `None` once the creator deleted its account
+    pub created_by: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "bands"]
+pub struct NewBand<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub genre: &'a str,
+    pub created_by: i64,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "bands"]
+pub struct BandChanges<'a> {
+    pub name: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub genre: Option<&'a str>,
+}
+
+impl<'a> BandChanges<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.description.is_none() && self.genre.is_none()
+    }
+}
+
+/// Role of a user within a band, stored as text in `band_members.role`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BandRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl BandRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BandRole::Owner => "owner",
+            BandRole::Admin => "admin",
+            BandRole::Member => "member",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<BandRole> {
+        match role {
+            "owner" => Some(BandRole::Owner),
+            "admin" => Some(BandRole::Admin),
+            "member" => Some(BandRole::Member),
+            _ => None,
+        }
+    }
+
+    /// Owners and admins may edit the band and manage its members
+    pub fn can_manage(&self) -> bool {
+        *self != BandRole::Member
+    }
+}
+
+#[derive(Serialize, Deserialize, Queryable)]
+pub struct BandMember {
+    pub band_id: i64,
+    pub user_id: i64,
+    pub role: String,
+    pub joined_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "band_members"]
+pub struct NewBandMember<'a> {
+    pub band_id: i64,
+    pub user_id: i64,
+    pub role: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Queryable)]
+pub struct Event {
+    pub id: i64,
+    pub title: String,
+    pub description: String,
+    /// UTC
+    pub starts_at: NaiveDateTime,
+    /// UTC
+    pub ends_at: NaiveDateTime,
+    /// Time zone of the venue, for displaying local times
+    pub timezone: String,
+    pub venue: String,
+    pub organizer_user_id: Option<i64>,
+    pub organizer_band_id: Option<i64>,
+    /// `None` once the creator deleted its account
+    pub created_by: Option<i64>,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "events"]
+pub struct NewEvent<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub timezone: &'a str,
+    pub venue: &'a str,
+    pub organizer_user_id: Option<i64>,
+    pub organizer_band_id: Option<i64>,
+    pub created_by: i64,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "events"]
+pub struct EventChanges<'a> {
+    pub title: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub timezone: Option<&'a str>,
+    pub venue: Option<&'a str>,
+}
+
+impl<'a> EventChanges<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.starts_at.is_none()
+            && self.ends_at.is_none()
+            && self.timezone.is_none()
+            && self.venue.is_none()
+    }
+}
+
+/// Answer of a user to an event, stored as text in `event_attendees.status`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RsvpStatus {
+    Going,
+    Maybe,
+    Declined,
+}
+
+impl RsvpStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "going",
+            RsvpStatus::Maybe => "maybe",
+            RsvpStatus::Declined => "declined",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Queryable)]
+pub struct EventAttendee {
+    pub event_id: i64,
+    pub user_id: i64,
+    pub status: String,
+    pub responded_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "event_attendees"]
+pub struct NewEventAttendee<'a> {
+    pub event_id: i64,
+    pub user_id: i64,
+    pub status: &'a str,
+}
+
+/// Invitation of a user, or of an email not registered yet, to join a band or
+/// to attend an event. Exactly one of `band_id` and `event_id` is set.
+#[derive(Serialize, Deserialize, Queryable)]
+pub struct Invitation {
+    pub id: i64,
+    pub band_id: Option<i64>,
+    pub event_id: Option<i64>,
+    pub invited_by: i64,
+    /// Set once the invitee is registered
+    pub invitee_user_id: Option<i64>,
+    pub invitee_email: Option<String>,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub responded_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "invitations"]
+pub struct NewInvitation<'a> {
+    pub band_id: Option<i64>,
+    pub event_id: Option<i64>,
+    pub invited_by: i64,
+    pub invitee_user_id: Option<i64>,
+    pub invitee_email: Option<&'a str>,
+    pub expires_at: NaiveDateTime,
+}
+
+/// State of an invitation, stored as text in `invitations.status`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Expired,
+    Revoked,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Declined => "declined",
+            InvitationStatus::Expired => "expired",
+            InvitationStatus::Revoked => "revoked",
+        }
+    }
 }
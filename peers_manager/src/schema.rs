@@ -1,8 +1,136 @@
+table! {
+    band_members (band_id, user_id) {
+        band_id -> BigInt,
+        user_id -> BigInt,
+        role -> Text,
+        joined_at -> Timestamp,
+    }
+}
+
+table! {
+    bands (id) {
+        id -> BigInt,
+        name -> Text,
+        description -> Text,
+        genre -> Text,
+        created_by -> Nullable<BigInt>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    event_attendees (event_id, user_id) {
+        event_id -> BigInt,
+        user_id -> BigInt,
+        status -> Text,
+        responded_at -> Timestamp,
+    }
+}
+
+table! {
+    events (id) {
+        id -> BigInt,
+        title -> Text,
+        description -> Text,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+        timezone -> Text,
+        venue -> Text,
+        organizer_user_id -> Nullable<BigInt>,
+        organizer_band_id -> Nullable<BigInt>,
+        created_by -> Nullable<BigInt>,
+        cancelled_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    invitations (id) {
+        id -> BigInt,
+        band_id -> Nullable<BigInt>,
+        event_id -> Nullable<BigInt>,
+        invited_by -> BigInt,
+        invitee_user_id -> Nullable<BigInt>,
+        invitee_email -> Nullable<Text>,
+        status -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        responded_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    password_reset_requests (id) {
+        id -> BigInt,
+        email -> Text,
+        requested_at -> Timestamp,
+    }
+}
+
+table! {
+    password_reset_tokens (id) {
+        id -> BigInt,
+        token -> Text,
+        user_id -> BigInt,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> BigInt,
+        token -> Text,
+        user_id -> BigInt,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     users (id) {
         id -> BigInt,
         name -> Text,
         about -> Text,
         email -> Text,
+        password_hash -> Text,
+        email_verified -> Bool,
+        role -> Text,
+        suspended_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    verification_tokens (id) {
+        id -> BigInt,
+        token -> Text,
+        user_id -> BigInt,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
     }
 }
+
+joinable!(band_members -> bands (band_id));
+joinable!(band_members -> users (user_id));
+joinable!(bands -> users (created_by));
+joinable!(event_attendees -> events (event_id));
+joinable!(event_attendees -> users (user_id));
+joinable!(events -> bands (organizer_band_id));
+joinable!(invitations -> bands (band_id));
+joinable!(invitations -> events (event_id));
+joinable!(password_reset_tokens -> users (user_id));
+joinable!(sessions -> users (user_id));
+joinable!(verification_tokens -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(
+    band_members,
+    bands,
+    event_attendees,
+    events,
+    invitations,
+    password_reset_requests,
+    password_reset_tokens,
+    sessions,
+    users,
+    verification_tokens,
+);
@@ -1,9 +1,74 @@
-use super::email_validator::ValidateExecutor;
+use std::sync::{Arc, RwLock};
+
+use super::email_validator::{Canonicalization, ValidateExecutor};
+use super::metrics::{MeteredAddr, Metrics};
+use super::rate_limit::RateLimiter;
 use actix::prelude::Addr;
+use db::repository::UsersExecutor;
 use db::users::DbExecutor;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use mail::MailExecutor;
 
 /// State with DbExecutor address
 pub struct AppState {
-    pub db: Addr<DbExecutor>,
-    pub email_validator: Addr<ValidateExecutor>,
+    pub db: MeteredAddr<DbExecutor>,
+    /// Pool of the `DbExecutor`s, for its usage in the metrics
+    pub db_pool: Pool<ConnectionManager<PgConnection>>,
+    /// Storage of users and their sessions, Postgres or in memory
+    pub users: MeteredAddr<UsersExecutor>,
+    pub email_validator: MeteredAddr<ValidateExecutor>,
+    /// Folding of the emails users log in or ask a password reset with, the
+    /// same as the validator's
+    pub canonicalization: Canonicalization,
+    pub mailer: Addr<MailExecutor>,
+    pub verification: Reloadable<VerificationSettings>,
+    pub rate_limiter: RateLimiter,
+    pub metrics: Arc<Metrics>,
+}
+
+/// Settings shared by the workers of the server, replaced when the
+/// configuration is reloaded
+pub struct Reloadable<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable(Arc::new(RwLock::new(Arc::new(value))))
+    }
+
+    /// The current value, requests keep the one they started with
+    pub fn get(&self) -> Arc<T> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.write().unwrap() = Arc::new(value);
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Reloadable(self.0.clone())
+    }
+}
+
+/// How users which did not verify their email yet are treated
+#[derive(Clone)]
+pub struct VerificationSettings {
+    /// Refuse to log in users with an unverified email
+    pub required_for_login: bool,
+    /// Hide users with an unverified email from search results
+    pub required_for_search: bool,
+    /// Base url of the verification links sent by email
+    pub link_base_url: String,
+}
+
+impl Default for VerificationSettings {
+    fn default() -> Self {
+        VerificationSettings {
+            required_for_login: false,
+            required_for_search: false,
+            link_base_url: "http://127.0.0.1:8080".to_string(),
+        }
+    }
 }
@@ -0,0 +1,90 @@
+//! Session token authentication
+use actix_web::middleware::{Middleware, Started};
+use actix_web::{
+    AsyncResponder, Error, FromRequest, FutureResponse, HttpRequest, HttpResponse, Result,
+};
+use futures::Future;
+
+use super::app::AppState;
+use super::error::{api_result, ApiError};
+use super::metrics::MeteredAddr;
+use super::request_id::RequestId;
+use db::repository::UsersExecutor;
+use db::sessions::{CreateSession, ResolveSession};
+use db::users::LoginResponse;
+use models;
+
+/// Header carrying the token issued by `/users/login` and `/users/create_user`.
+pub const AUTH_TOKEN_HEADER: &str = "auth-token";
+
+/// User owning the session token of the current request.
+#[derive(Clone)]
+pub struct AuthenticatedUser(pub models::User);
+
+/// Middleware resolving the `auth-token` header into an `AuthenticatedUser`.
+///
+/// Requests without a valid token are passed through untouched, handlers which
+/// require a user take `AuthenticatedUser` as an extractor.
+pub struct Authenticate;
+
+impl Middleware<AppState> for Authenticate {
+    fn start(&self, req: &HttpRequest<AppState>) -> Result<Started> {
+        let token = match req
+            .headers()
+            .get(AUTH_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(token) => token.to_string(),
+            None => return Ok(Started::Done),
+        };
+
+        let request_id = RequestId::from_request(req, &());
+        let resolve_session = req
+            .state()
+            .users
+            .send(&request_id, ResolveSession { token });
+        let req = req.clone();
+
+        Ok(Started::Future(Box::new(
+            resolve_session
+                .then(api_result)
+                .map(move |user| {
+                    if let Some(user) = user {
+                        req.extensions_mut().insert(AuthenticatedUser(user));
+                    }
+                    None
+                })
+                .from_err(),
+        )))
+    }
+}
+
+impl FromRequest<AppState> for AuthenticatedUser {
+    type Config = ();
+    type Result = Result<Self, Error>;
+
+    fn from_request(req: &HttpRequest<AppState>, _: &Self::Config) -> Self::Result {
+        req.extensions()
+            .get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or_else(|| ApiError::Unauthorized.into())
+    }
+}
+
+/// Issues a new session for the user and responds with its token.
+pub fn respond_with_session(
+    users: MeteredAddr<UsersExecutor>,
+    request_id: &RequestId,
+    user_id: i64,
+) -> FutureResponse<HttpResponse> {
+    users
+        .send(request_id, CreateSession { user_id })
+        .then(api_result)
+        .map(|session| {
+            HttpResponse::Ok().json(LoginResponse {
+                token: session.token,
+            })
+        })
+        .from_err()
+        .responder()
+}
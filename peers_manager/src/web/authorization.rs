@@ -0,0 +1,141 @@
+//! Site wide roles and what they let users see and do
+//!
+//! Handlers restricted to admins take `Admin` as an extractor, others call
+//! `AuthenticatedUser::require` with the role they need. Users are returned
+//! through `UserView`, which hides the fields the viewer may not see.
+use actix_web::{Error, FromRequest, HttpRequest, Result};
+use chrono::NaiveDateTime;
+
+use super::app::AppState;
+use super::auth::AuthenticatedUser;
+use super::error::ApiError;
+use models::{self, UserRole};
+
+impl AuthenticatedUser {
+    /// Refuses users whose role doesn't include `role`
+    pub fn require(&self, role: UserRole) -> Result<(), ApiError> {
+        if self.0.role().includes(role) {
+            Ok(())
+        } else {
+            Err(ApiError::NotAllowed("not allowed for this role"))
+        }
+    }
+}
+
+/// Authenticated admin, other users are refused with 401 or 403
+#[derive(Clone)]
+pub struct Admin(pub models::User);
+
+impl FromRequest<AppState> for Admin {
+    type Config = ();
+    type Result = Result<Self, Error>;
+
+    fn from_request(req: &HttpRequest<AppState>, config: &Self::Config) -> Self::Result {
+        let user = AuthenticatedUser::from_request(req, config)?;
+        user.require(UserRole::Admin)?;
+
+        Ok(Admin(user.0))
+    }
+}
+
+/// User as the viewer may see it. Emails are only visible to their owner and
+/// admins, suspensions to moderators and admins.
+#[derive(Serialize, Deserialize)]
+pub struct UserView {
+    pub id: i64,
+    pub name: String,
+    pub about: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub role: UserRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspended_at: Option<NaiveDateTime>,
+}
+
+impl UserView {
+    /// `user` as seen by `viewer`, `None` for anonymous requests
+    pub fn new(user: models::User, viewer: Option<&models::User>) -> UserView {
+        let viewer_role = viewer.map(|viewer| viewer.role());
+        let is_self = viewer.map_or(false, |viewer| viewer.id == user.id);
+        let sees = |role: UserRole| viewer_role.map_or(false, |viewer| viewer.includes(role));
+
+        let role = user.role();
+        UserView {
+            id: user.id,
+            name: user.name,
+            about: user.about,
+            email: if is_self || sees(UserRole::Admin) {
+                Some(user.email)
+            } else {
+                None
+            },
+            email_verified: user.email_verified,
+            role,
+            suspended_at: if sees(UserRole::Moderator) {
+                user.suspended_at
+            } else {
+                None
+            },
+        }
+    }
+
+    /// `user` as seen by itself
+    pub fn own(user: models::User) -> UserView {
+        let viewer = user.clone();
+        UserView::new(user, Some(&viewer))
+    }
+}
+
+#[cfg(test)]
+mod authorization_tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(id: i64, role: UserRole) -> models::User {
+        models::User {
+            id,
+            name: format!("name {}", id),
+            about: String::new(),
+            email: format!("user_{}@gmail.com", id),
+            password_hash: String::new(),
+            email_verified: true,
+            role: role.as_str().to_string(),
+            suspended_at: Some(Utc::now().naive_utc()),
+        }
+    }
+
+    #[test]
+    fn test_require() {
+        let moderator = AuthenticatedUser(user(1, UserRole::Moderator));
+
+        assert!(moderator.require(UserRole::User).is_ok());
+        assert!(moderator.require(UserRole::Moderator).is_ok());
+        assert!(moderator.require(UserRole::Admin).is_err());
+    }
+
+    #[test]
+    fn test_user_view() {
+        let viewed = user(1, UserRole::User);
+
+        let anonymous = UserView::new(viewed.clone(), None);
+        assert_eq!(anonymous.email, None);
+        assert_eq!(anonymous.suspended_at, None);
+        assert_eq!(anonymous.role, UserRole::User);
+
+        let other = UserView::new(viewed.clone(), Some(&user(2, UserRole::User)));
+        assert_eq!(other.email, None);
+
+        let moderator = UserView::new(viewed.clone(), Some(&user(2, UserRole::Moderator)));
+        assert_eq!(moderator.email, None);
+        assert_eq!(moderator.suspended_at, viewed.suspended_at);
+
+        let admin = UserView::new(viewed.clone(), Some(&user(2, UserRole::Admin)));
+        assert_eq!(admin.email, Some(viewed.email.clone()));
+        assert_eq!(admin.suspended_at, viewed.suspended_at);
+
+        let own = UserView::own(viewed.clone());
+        assert_eq!(own.email, Some(viewed.email.clone()));
+        assert_eq!(own.suspended_at, None);
+    }
+}
@@ -0,0 +1,278 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Path, Query, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use db::bands::{CreateBand, DeleteBand, GetBand, ListBands, UpdateBand};
+
+#[derive(Deserialize, Serialize)]
+pub struct NewBandInput {
+    pub name: String,
+    pub description: String,
+    pub genre: String,
+}
+
+/// Fields of the band to change, missing ones are kept
+#[derive(Deserialize, Serialize, Default)]
+pub struct UpdateBandInput {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub genre: Option<String>,
+}
+
+/// Creates a band, the current user becomes its owner
+pub fn create_band(
+    (user, new_band, state, request_id): (
+        AuthenticatedUser,
+        Json<NewBandInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let new_band = new_band.into_inner();
+
+    if new_band.name.trim().is_empty() {
+        return fail(ApiError::InvalidBandName);
+    }
+
+    // send async `CreateBand` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            CreateBand {
+                created_by: user.0.id,
+                name: new_band.name,
+                description: new_band.description,
+                genre: new_band.genre,
+            },
+        )
+        .then(api_result)
+        .map(|band| HttpResponse::Ok().json(band))
+        .from_err()
+        .responder()
+}
+
+/// Lists bands, `?member=<user id>` keeps the bands of that user only
+pub fn list_bands(
+    (list, state, request_id): (Query<ListBands>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `ListBands` message to a `DbExecutor`
+    state
+        .db
+        .send(&request_id, list.into_inner())
+        .then(api_result)
+        .map(|bands| HttpResponse::Ok().json(bands))
+        .from_err()
+        .responder()
+}
+
+pub fn get_band(
+    (id, state, request_id): (Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `GetBand` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            GetBand {
+                id: id.into_inner(),
+            },
+        )
+        .then(api_result)
+        .map(|band| HttpResponse::Ok().json(band))
+        .from_err()
+        .responder()
+}
+
+/// Changes the band, allowed to its owners and admins
+pub fn update_band(
+    (user, id, update, state, request_id): (
+        AuthenticatedUser,
+        Path<i64>,
+        Json<UpdateBandInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let update = update.into_inner();
+
+    if update
+        .name
+        .as_ref()
+        .map_or(false, |name| name.trim().is_empty())
+    {
+        return fail(ApiError::InvalidBandName);
+    }
+
+    // send async `UpdateBand` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            UpdateBand {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+                name: update.name,
+                description: update.description,
+                genre: update.genre,
+            },
+        )
+        .then(api_result)
+        .map(|band| HttpResponse::Ok().json(band))
+        .from_err()
+        .responder()
+}
+
+/// Deletes the band, allowed to its owners
+pub fn delete_band(
+    (user, id, state, request_id): (AuthenticatedUser, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `DeleteBand` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            DeleteBand {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+            },
+        )
+        .then(api_result)
+        .map(|()| HttpResponse::NoContent().finish())
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod manage_band_tests {
+
+    use super::*;
+    use actix_web::HttpMessage;
+    use models::{Band, BandRole};
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_create_band() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_band_1@gmail.com"));
+        let band = srv.test_create_new_band(&token, new_band("band 1"));
+        assert_eq!(band.name, "band 1");
+        assert_eq!(band.genre, "jazz");
+
+        let response = srv.get_band(band.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let fetched: Band = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(fetched.id, band.id);
+
+        let response = srv.list_bands(band.created_by);
+        let bytes = srv.execute(response.body()).unwrap();
+        let bands: Vec<Band> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(bands.len(), 1);
+        assert_eq!(bands[0].id, band.id);
+    }
+
+    #[test]
+    fn test_create_band_requires_login() {
+        let mut srv = create_test_server();
+
+        let response = srv.create_band("not a token", new_band("band 1"));
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    #[test]
+    fn test_create_band_invalid_name() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_band_2@gmail.com"));
+
+        let response = srv.create_band(&token, new_band(" "));
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_band_name");
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_only_managers_update_band() {
+        let mut srv = create_test_server();
+
+        let owner_token = srv.test_create_new_user(new_user("test_band_3@gmail.com"));
+        let other_token = srv.test_create_new_user(new_user("test_band_4@gmail.com"));
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let update = UpdateBandInput {
+            genre: Some("rock".to_string()),
+            ..UpdateBandInput::default()
+        };
+        let response = srv.update_band(&other_token, band.id, update);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "not_allowed");
+        assert_eq!(response.status().as_u16(), 403);
+
+        let update = UpdateBandInput {
+            genre: Some("rock".to_string()),
+            ..UpdateBandInput::default()
+        };
+        let response = srv.update_band(&owner_token, band.id, update);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let updated: Band = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(updated.genre, "rock");
+        assert_eq!(updated.name, "band 1");
+    }
+
+    #[test]
+    fn test_delete_band() {
+        let mut srv = create_test_server();
+
+        let owner_token = srv.test_create_new_user(new_user("test_band_5@gmail.com"));
+        let other_token = srv.test_create_new_user(new_user("test_band_6@gmail.com"));
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.delete_band(&other_token, band.id);
+        assert_eq!(response.status().as_u16(), 403);
+
+        let response = srv.delete_band(&owner_token, band.id);
+        assert!(response.status().is_success());
+
+        let response = srv.get_band(band.id);
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[test]
+    fn test_band_outlives_creator() {
+        let mut srv = create_test_server();
+
+        let (token, _) = create_member(&mut srv, "test_band_7@gmail.com");
+        let (_, heir_id) = create_member(&mut srv, "test_band_8@gmail.com");
+        let band = srv.test_create_new_band(&token, new_band("band 1"));
+
+        // the only owner hands the band over before leaving
+        let response = srv.delete_current_user(&token);
+        assert_eq!(response.status().as_u16(), 400);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "last_owner");
+
+        let response = srv.set_band_member(&token, band.id, heir_id, BandRole::Owner);
+        assert!(response.status().is_success());
+
+        let response = srv.delete_current_user(&token);
+        assert!(response.status().is_success());
+
+        let response = srv.get_band(band.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let kept: Band = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(kept.created_by, None);
+    }
+}
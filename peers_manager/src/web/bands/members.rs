@@ -0,0 +1,176 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Path, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::api_result;
+use super::super::request_id::RequestId;
+use db::bands::{ListBandMembers, RemoveBandMember, SetBandMember};
+use models::BandRole;
+
+#[derive(Deserialize, Serialize)]
+pub struct SetBandMemberInput {
+    pub role: BandRole,
+}
+
+pub fn list_band_members(
+    (id, state, request_id): (Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `ListBandMembers` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            ListBandMembers {
+                band_id: id.into_inner(),
+            },
+        )
+        .then(api_result)
+        .map(|members| HttpResponse::Ok().json(members))
+        .from_err()
+        .responder()
+}
+
+/// Adds the user to the band or changes its role
+pub fn set_band_member(
+    (user, path, member, state, request_id): (
+        AuthenticatedUser,
+        Path<(i64, i64)>,
+        Json<SetBandMemberInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let (band_id, user_id) = path.into_inner();
+
+    // send async `SetBandMember` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            SetBandMember {
+                band_id,
+                acting_user_id: user.0.id,
+                user_id,
+                role: member.role,
+            },
+        )
+        .then(api_result)
+        .map(|member| HttpResponse::Ok().json(member))
+        .from_err()
+        .responder()
+}
+
+/// Removes the user from the band, users may remove themselves to leave
+pub fn remove_band_member(
+    (user, path, state, request_id): (
+        AuthenticatedUser,
+        Path<(i64, i64)>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let (band_id, user_id) = path.into_inner();
+
+    // send async `RemoveBandMember` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            RemoveBandMember {
+                band_id,
+                acting_user_id: user.0.id,
+                user_id,
+            },
+        )
+        .then(api_result)
+        .map(|()| HttpResponse::NoContent().finish())
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod band_members_tests {
+
+    use actix_web::HttpMessage;
+    use models::{BandMember, BandRole};
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_manage_band_members() {
+        let mut srv = create_test_server();
+
+        let (owner_token, _) = create_member(&mut srv, "test_members_1@gmail.com");
+        let (admin_token, admin_id) = create_member(&mut srv, "test_members_2@gmail.com");
+        let (member_token, member_id) = create_member(&mut srv, "test_members_3@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.set_band_member(&owner_token, band.id, admin_id, BandRole::Admin);
+        assert!(response.status().is_success());
+
+        // admins manage members but can't hand out ownership
+        let response = srv.set_band_member(&admin_token, band.id, member_id, BandRole::Member);
+        assert!(response.status().is_success());
+
+        let response = srv.set_band_member(&admin_token, band.id, member_id, BandRole::Owner);
+        assert_eq!(response.status().as_u16(), 403);
+
+        // plain members can't manage members
+        let response = srv.set_band_member(&member_token, band.id, admin_id, BandRole::Member);
+        assert_eq!(response.status().as_u16(), 403);
+
+        let response = srv.list_band_members(band.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let members: Vec<BandMember> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(members.len(), 3);
+
+        // members may leave on their own
+        let response = srv.remove_band_member(&member_token, band.id, member_id);
+        assert!(response.status().is_success());
+
+        let response = srv.list_band_members(band.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let members: Vec<BandMember> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(members.len(), 2);
+    }
+
+    #[test]
+    fn test_band_keeps_last_owner() {
+        let mut srv = create_test_server();
+
+        let (owner_token, owner_id) = create_member(&mut srv, "test_members_4@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.remove_band_member(&owner_token, band.id, owner_id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "last_owner");
+
+        let response = srv.set_band_member(&owner_token, band.id, owner_id, BandRole::Member);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "last_owner");
+    }
+
+    #[test]
+    fn test_set_unknown_band_member() {
+        let mut srv = create_test_server();
+
+        let (owner_token, owner_id) = create_member(&mut srv, "test_members_5@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.set_band_member(
+            &owner_token,
+            band.id,
+            owner_id + 1_000_000,
+            BandRole::Member,
+        );
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "member_not_found");
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod manage;
+pub mod members;
@@ -0,0 +1,412 @@
+//! Validator executor actor
+//!
+//! Emails are checked against the public suffix list. All the validators read
+//! one `SharedList`, loaded at startup from the configured file or from the
+//! snapshot compiled into the binary, so the suffix check never waits for the
+//! network. A `SuffixListRefresher` replaces the list by the latest one in the
+//! background. Valid emails are answered in their canonical form, the one
+//! stored and compared for uniqueness.
+use std::cmp;
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix::prelude::*;
+
+extern crate publicsuffix;
+extern crate validator;
+
+use self::publicsuffix::List;
+use self::validator::validate_email;
+use config::EmailValidationConfig;
+use logging::{with_request_id, Traced};
+use web::metrics::Metrics;
+
+pub mod resolver;
+
+use self::resolver::{DnsResolver, MxResolver};
+
+/// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat used when
+/// no list file is configured
+pub const BUNDLED_LIST: &str = include_str!("../../../data/public_suffix_list.dat");
+
+/// First delay before downloading again after a failed refresh, it doubles
+/// after every failure up to the refresh interval
+pub const MIN_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Domains of gmail, folded into `gmail.com`
+const GMAIL_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+/// Public suffix list read by all the validators
+pub type SharedList = Arc<RwLock<List>>;
+
+/// The list of `path`, the bundled snapshot when `None`
+pub fn load_list(path: Option<&str>) -> Result<SharedList, publicsuffix::errors::Error> {
+    let list = match path {
+        Some(path) => List::from_path(path)?,
+        None => List::from_str(BUNDLED_LIST)?,
+    };
+
+    Ok(Arc::new(RwLock::new(list)))
+}
+
+/// Outcome of the validation of an email
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmailVerdict {
+    /// Carries the canonical form of the email
+    Valid(String),
+    InvalidSyntax,
+    /// The domain doesn't end with a known public suffix
+    UnknownTld,
+    /// The domain belongs to a disposable email provider
+    Disposable,
+    /// The domain has no MX record
+    NoMailExchanger,
+}
+
+impl EmailVerdict {
+    /// Why the email is refused, `None` when it is valid
+    pub fn refusal(&self) -> Option<&'static str> {
+        match self {
+            EmailVerdict::Valid(_) => None,
+            EmailVerdict::InvalidSyntax => Some("email is not valid"),
+            EmailVerdict::UnknownTld => Some("email domain has an unknown top level domain"),
+            EmailVerdict::Disposable => Some("disposable email addresses are not accepted"),
+            EmailVerdict::NoMailExchanger => Some("email domain does not receive mail"),
+        }
+    }
+
+    /// Stable name of the check which refused the email, `None` when it is
+    /// valid
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            EmailVerdict::Valid(_) => None,
+            EmailVerdict::InvalidSyntax => Some("invalid_syntax"),
+            EmailVerdict::UnknownTld => Some("unknown_tld"),
+            EmailVerdict::Disposable => Some("disposable"),
+            EmailVerdict::NoMailExchanger => Some("no_mail_exchanger"),
+        }
+    }
+}
+
+/// How emails are folded before being stored or looked up
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Canonicalization {
+    /// Drop the dots and the `+` suffix of gmail addresses, gmail delivers
+    /// `j.doe+peers@gmail.com` to `jdoe@gmail.com`
+    pub fold_gmail: bool,
+}
+
+impl Canonicalization {
+    /// The domain is lower-cased, the local part is kept as is unless it is
+    /// folded
+    pub fn canonicalize(&self, email: &str) -> String {
+        let at = match email.rfind('@') {
+            Some(at) => at,
+            None => return email.to_string(),
+        };
+        let local = &email[..at];
+        let domain = email[at + 1..].to_lowercase();
+
+        if self.fold_gmail && GMAIL_DOMAINS.contains(&domain.as_str()) {
+            let local = local.split('+').next().unwrap_or(local);
+            return format!("{}@gmail.com", local.replace('.', "").to_lowercase());
+        }
+
+        format!("{}@{}", local, domain)
+    }
+}
+
+/// Rules checked once the syntax and the suffix are known to be valid
+#[derive(Clone, Debug, Default)]
+pub struct EmailRules {
+    /// Refused domains, their subdomains are refused as well
+    pub disposable_domains: HashSet<String>,
+    pub canonicalization: Canonicalization,
+}
+
+impl EmailRules {
+    pub fn from_config(config: &EmailValidationConfig) -> Self {
+        EmailRules {
+            disposable_domains: config
+                .disposable_domains
+                .iter()
+                .map(|domain| domain.to_lowercase())
+                .collect(),
+            canonicalization: Canonicalization {
+                fold_gmail: config.fold_gmail_addresses,
+            },
+        }
+    }
+
+    fn is_disposable(&self, domain: &str) -> bool {
+        let mut domain = domain;
+        loop {
+            if self.disposable_domains.contains(domain) {
+                return true;
+            }
+            match domain.find('.') {
+                Some(dot) => domain = &domain[dot + 1..],
+                None => return false,
+            }
+        }
+    }
+}
+
+/// DNS resolver when `check_mx` is set, mail exchangers are not checked
+/// otherwise
+pub fn create_resolver(config: &EmailValidationConfig) -> io::Result<Option<Box<dyn MxResolver>>> {
+    if !config.check_mx {
+        return Ok(None);
+    }
+
+    Ok(Some(Box::new(DnsResolver::from_system_conf()?)))
+}
+
+/// This is validator executor actor, every worker reads the same list.
+pub struct ValidateExecutor {
+    pub list: SharedList,
+    pub rules: EmailRules,
+    /// Looking up the mail exchangers blocks the worker, `None` skips it
+    pub resolver: Option<Box<dyn MxResolver>>,
+}
+
+impl ValidateExecutor {
+    pub fn verdict(&self, email: &str) -> EmailVerdict {
+        if !validate_email(email) {
+            return EmailVerdict::InvalidSyntax;
+        }
+        let domain = match email.rfind('@') {
+            Some(at) => email[at + 1..].to_lowercase(),
+            None => return EmailVerdict::InvalidSyntax,
+        };
+
+        match self.list.read().unwrap().parse_domain(&domain) {
+            Ok(ref parsed) if parsed.has_known_suffix() => (),
+            Ok(_) => return EmailVerdict::UnknownTld,
+            Err(_) => return EmailVerdict::InvalidSyntax,
+        }
+
+        if self.rules.is_disposable(&domain) {
+            return EmailVerdict::Disposable;
+        }
+
+        if let Some(ref resolver) = self.resolver {
+            match resolver.has_mail_exchanger(&domain) {
+                Ok(true) => (),
+                Ok(false) => return EmailVerdict::NoMailExchanger,
+                // a DNS outage doesn't stop registrations
+                Err(error) => warn!("can't look up the mail exchangers of {}: {}", domain, error),
+            }
+        }
+
+        EmailVerdict::Valid(self.rules.canonicalization.canonicalize(email))
+    }
+}
+
+/// Asks for the verdict on `email`, sent as a `Traced` message
+pub struct ValidateEmail {
+    pub email: String,
+}
+
+impl Message for ValidateEmail {
+    type Result = EmailVerdict;
+}
+
+impl Actor for ValidateExecutor {
+    type Context = SyncContext<Self>;
+}
+
+/// Records of the lookups are tagged with the request id of the message
+impl Handler<Traced<ValidateEmail>> for ValidateExecutor {
+    type Result = MessageResult<Traced<ValidateEmail>>;
+
+    fn handle(&mut self, traced: Traced<ValidateEmail>, _: &mut Self::Context) -> Self::Result {
+        let Traced { request_id, msg } = traced;
+
+        MessageResult(with_request_id(
+            request_id.as_ref().map(|id| id.as_str()),
+            || self.verdict(&msg.email),
+        ))
+    }
+}
+
+/// Answered as soon as a validator is free
+pub struct Ping;
+
+impl Message for Ping {
+    type Result = ();
+}
+
+impl Handler<Traced<Ping>> for ValidateExecutor {
+    type Result = ();
+
+    fn handle(&mut self, _: Traced<Ping>, _: &mut Self::Context) {}
+}
+
+/// Downloads the latest list every `interval` and swaps it into the
+/// `SharedList`. Start it on an `Arbiter` of its own: the download blocks the
+/// thread running it.
+pub struct SuffixListRefresher {
+    list: SharedList,
+    interval: Duration,
+    retry_delay: Duration,
+    /// Told about every refresh, for the age of the list
+    metrics: Arc<Metrics>,
+}
+
+impl SuffixListRefresher {
+    pub fn new(list: SharedList, interval: Duration, metrics: Arc<Metrics>) -> Self {
+        SuffixListRefresher {
+            list,
+            interval,
+            retry_delay: cmp::min(MIN_RETRY_DELAY, interval),
+            metrics,
+        }
+    }
+
+    fn schedule(&self, delay: Duration, ctx: &mut Context<Self>) {
+        ctx.run_later(delay, |refresher, ctx| refresher.refresh(ctx));
+    }
+
+    fn refresh(&mut self, ctx: &mut Context<Self>) {
+        match List::fetch() {
+            Ok(list) => {
+                *self.list.write().unwrap() = list;
+                self.metrics.suffix_list_refreshed();
+                self.retry_delay = cmp::min(MIN_RETRY_DELAY, self.interval);
+                info!("public suffix list refreshed");
+
+                let interval = self.interval;
+                self.schedule(interval, ctx);
+            }
+            Err(error) => {
+                let delay = self.retry_delay;
+                self.retry_delay = next_retry_delay(delay, self.interval);
+                warn!(
+                    "can't refresh the public suffix list, retrying in {}s: {}",
+                    delay.as_secs(),
+                    error
+                );
+
+                self.schedule(delay, ctx);
+            }
+        }
+    }
+}
+
+impl Actor for SuffixListRefresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // the list loaded at startup is used until the first refresh
+        let interval = self.interval;
+        self.schedule(interval, ctx);
+    }
+}
+
+/// Delay after the failure of a retry which waited `delay`
+fn next_retry_delay(delay: Duration, interval: Duration) -> Duration {
+    cmp::min(delay * 2, interval)
+}
+
+#[cfg(test)]
+mod email_validator_tests {
+
+    use super::resolver::StaticResolver;
+    use super::*;
+
+    fn executor(rules: EmailRules, resolver: Option<StaticResolver>) -> ValidateExecutor {
+        ValidateExecutor {
+            list: load_list(None).unwrap(),
+            rules,
+            resolver: resolver.map(|resolver| Box::new(resolver) as Box<dyn MxResolver>),
+        }
+    }
+
+    #[test]
+    fn test_bundled_list() {
+        let list = load_list(None).unwrap();
+        let list = list.read().unwrap();
+
+        assert!(list.parse_email("user@gmail.com").is_ok());
+        assert!(list.parse_email("user@band.co.uk").is_ok());
+        assert!(list.parse_email("user.gmail.com").is_err());
+        assert!(list.parse_email("user@").is_err());
+    }
+
+    #[test]
+    fn test_missing_list_file() {
+        assert!(load_list(Some("data/missing_list.dat")).is_err());
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let interval = Duration::from_secs(3600);
+
+        let mut delays = vec![MIN_RETRY_DELAY];
+        for _ in 0..7 {
+            let delay = *delays.last().unwrap();
+            delays.push(next_retry_delay(delay, interval));
+        }
+
+        let seconds = delays
+            .iter()
+            .map(|delay| delay.as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(seconds, vec![60, 120, 240, 480, 960, 1920, 3600, 3600]);
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let mut rules = EmailRules::default();
+        rules
+            .disposable_domains
+            .insert("mailinator.com".to_string());
+        let validator = executor(rules, Some(StaticResolver::new(&["gmail.com"])));
+
+        assert_eq!(
+            validator.verdict("User@GMail.com"),
+            EmailVerdict::Valid("User@gmail.com".to_string())
+        );
+        assert_eq!(validator.verdict("user 1"), EmailVerdict::InvalidSyntax);
+        assert_eq!(
+            validator.verdict("user@band.unknowntld"),
+            EmailVerdict::UnknownTld
+        );
+        assert_eq!(
+            validator.verdict("user@eu.mailinator.com"),
+            EmailVerdict::Disposable
+        );
+        assert_eq!(
+            validator.verdict("user@band.co.uk"),
+            EmailVerdict::NoMailExchanger
+        );
+
+        let validator = executor(EmailRules::default(), None);
+        assert_eq!(
+            validator.verdict("user@band.co.uk"),
+            EmailVerdict::Valid("user@band.co.uk".to_string())
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let folding = Canonicalization { fold_gmail: true };
+        assert_eq!(
+            folding.canonicalize("J.Doe+peers@GoogleMail.com"),
+            "jdoe@gmail.com"
+        );
+        assert_eq!(
+            folding.canonicalize("J.Doe+peers@band.co.uk"),
+            "J.Doe+peers@band.co.uk"
+        );
+
+        let plain = Canonicalization::default();
+        assert_eq!(
+            plain.canonicalize("J.Doe+peers@GMail.com"),
+            "J.Doe+peers@gmail.com"
+        );
+    }
+}
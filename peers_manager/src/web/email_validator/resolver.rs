@@ -0,0 +1,64 @@
+//! Mail exchanger lookups
+//!
+//! The lookup is pluggable: DNS for deployments, a fixed set of domains for
+//! tests.
+#[cfg(test)]
+use std::collections::HashSet;
+use std::io;
+
+extern crate trust_dns_resolver;
+
+use self::trust_dns_resolver::error::ResolveErrorKind;
+use self::trust_dns_resolver::Resolver;
+
+/// Tells whether a domain accepts mail
+pub trait MxResolver: Send {
+    /// Whether `domain` has at least one MX record, `Err` when the lookup
+    /// itself failed
+    fn has_mail_exchanger(&self, domain: &str) -> Result<bool, String>;
+}
+
+/// Asks the name servers of the system configuration
+pub struct DnsResolver(Resolver);
+
+impl DnsResolver {
+    pub fn from_system_conf() -> io::Result<Self> {
+        Ok(DnsResolver(Resolver::from_system_conf()?))
+    }
+}
+
+impl MxResolver for DnsResolver {
+    fn has_mail_exchanger(&self, domain: &str) -> Result<bool, String> {
+        // fully qualified, the search domains of resolv.conf don't apply
+        match self.0.mx_lookup(format!("{}.", domain).as_str()) {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(error) => match error.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => Ok(false),
+                _ => Err(error.to_string()),
+            },
+        }
+    }
+}
+
+/// Only the given domains have a mail exchanger
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct StaticResolver {
+    domains: HashSet<String>,
+}
+
+#[cfg(test)]
+impl StaticResolver {
+    pub fn new(domains: &[&str]) -> Self {
+        StaticResolver {
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl MxResolver for StaticResolver {
+    fn has_mail_exchanger(&self, domain: &str) -> Result<bool, String> {
+        Ok(self.domains.contains(domain))
+    }
+}
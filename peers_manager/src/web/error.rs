@@ -0,0 +1,477 @@
+//! Errors of the http api
+//!
+//! Handlers fail with an `ApiError`, actix turns it into a response through
+//! `ResponseError`: its status and an `ErrorBody`. Codes are stable strings
+//! clients can match on, messages are meant for humans and may change.
+use std::fmt;
+use std::time::Duration;
+
+use actix::MailboxError;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::{FutureResponse, HttpResponse, ResponseError};
+use failure::Fail;
+use futures;
+
+use db::bands::BandError;
+use db::events::EventError;
+use db::invitations::InvitationError;
+use db::password_reset::{RequestPasswordResetError, ResetPasswordError};
+use db::search::SearchError;
+use db::users::{CreateUserError, DeleteUserError, LoginError, UpdateUserError};
+use db::verification::VerifyEmailError;
+use db::DbError;
+use logging::with_request_id;
+use mail::MailError;
+use web::email_validator::EmailVerdict;
+use web::users::create::MIN_PASSWORD_LENGTH;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request has no valid `auth-token`
+    Unauthorized,
+    InvalidCredentials,
+    EmailNotVerified,
+    /// An admin suspended the account
+    AccountSuspended,
+    UserNotFound,
+    UserAlreadyExists,
+    InvalidEmail(EmailVerdict),
+    WeakPassword,
+    /// Unknown or expired email verification or password reset token
+    InvalidToken,
+    TooManyRequests,
+    /// Over the rate limit of the route, retry after the duration
+    RateLimited(Duration),
+    InvalidCursor,
+    BandNotFound,
+    MemberNotFound,
+    /// A band always keeps at least one owner
+    LastOwner,
+    InvalidBandName,
+    EventNotFound,
+    InvalidSchedule,
+    EventCancelled,
+    InvalidEventTitle,
+    /// Not a time zone name of the IANA tz database
+    InvalidTimezone,
+    InvitationNotFound,
+    TargetNotFound,
+    InviteeNotFound,
+    InvalidTarget,
+    InvalidInvitee,
+    AlreadyInvited,
+    AlreadyMember,
+    NotPending,
+    InvitationExpired,
+    /// The current user may not do this, the message tells what
+    NotAllowed(&'static str),
+    /// The database or a worker is out of reach, retrying later may work
+    Unavailable(String),
+    Internal(String),
+}
+
+/// JSON body of the error responses
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::InvalidCredentials => "invalid_credentials",
+            ApiError::EmailNotVerified => "email_not_verified",
+            ApiError::AccountSuspended => "account_suspended",
+            ApiError::UserNotFound => "user_not_found",
+            ApiError::UserAlreadyExists => "user_already_exists",
+            ApiError::InvalidEmail(_) => "invalid_email",
+            ApiError::WeakPassword => "weak_password",
+            ApiError::InvalidToken => "invalid_token",
+            ApiError::TooManyRequests => "too_many_requests",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::InvalidCursor => "invalid_cursor",
+            ApiError::BandNotFound => "band_not_found",
+            ApiError::MemberNotFound => "member_not_found",
+            ApiError::LastOwner => "last_owner",
+            ApiError::InvalidBandName => "invalid_band_name",
+            ApiError::EventNotFound => "event_not_found",
+            ApiError::InvalidSchedule => "invalid_schedule",
+            ApiError::EventCancelled => "event_cancelled",
+            ApiError::InvalidEventTitle => "invalid_event_title",
+            ApiError::InvalidTimezone => "invalid_timezone",
+            ApiError::InvitationNotFound => "invitation_not_found",
+            ApiError::TargetNotFound => "target_not_found",
+            ApiError::InviteeNotFound => "invitee_not_found",
+            ApiError::InvalidTarget => "invalid_target",
+            ApiError::InvalidInvitee => "invalid_invitee",
+            ApiError::AlreadyInvited => "already_invited",
+            ApiError::AlreadyMember => "already_member",
+            ApiError::NotPending => "not_pending",
+            ApiError::InvitationExpired => "invitation_expired",
+            ApiError::NotAllowed(_) => "not_allowed",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::NotAllowed(_) | ApiError::AccountSuspended => StatusCode::FORBIDDEN,
+            ApiError::UserNotFound
+            | ApiError::BandNotFound
+            | ApiError::MemberNotFound
+            | ApiError::EventNotFound
+            | ApiError::InvitationNotFound
+            | ApiError::TargetNotFound
+            | ApiError::InviteeNotFound => StatusCode::NOT_FOUND,
+            ApiError::TooManyRequests | ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let message = match self {
+            ApiError::Unauthorized => "missing or invalid auth-token",
+            ApiError::InvalidCredentials => "invalid credentials",
+            ApiError::EmailNotVerified => "email is not verified",
+            ApiError::AccountSuspended => "account is suspended",
+            ApiError::UserNotFound => "user not found",
+            ApiError::UserAlreadyExists => "user already exists",
+            ApiError::InvalidEmail(verdict) => verdict.refusal().unwrap_or("email is not valid"),
+            ApiError::WeakPassword => {
+                return format!(
+                    "password must be at least {} characters long",
+                    MIN_PASSWORD_LENGTH
+                )
+            }
+            ApiError::InvalidToken => "invalid or expired token",
+            ApiError::TooManyRequests => "too many requests, try again later",
+            ApiError::RateLimited(_) => "rate limit exceeded, try again later",
+            ApiError::InvalidCursor => "invalid search cursor",
+            ApiError::BandNotFound => "band not found",
+            ApiError::MemberNotFound => "user not found",
+            ApiError::LastOwner => "a band needs at least one owner",
+            ApiError::InvalidBandName => "band name can't be empty",
+            ApiError::EventNotFound => "event not found",
+            ApiError::InvalidSchedule => "event can't end before it starts",
+            ApiError::EventCancelled => "event is cancelled",
+            ApiError::InvalidEventTitle => "event title can't be empty",
+            ApiError::InvalidTimezone => {
+                "unknown time zone, expected an IANA name like Europe/Kiev"
+            }
+            ApiError::InvitationNotFound => "invitation not found",
+            ApiError::TargetNotFound => "band or event not found",
+            ApiError::InviteeNotFound => "invited user not found",
+            ApiError::InvalidTarget => "invitation must name either a band or an event",
+            ApiError::InvalidInvitee => "invitation must name either a user or an email",
+            ApiError::AlreadyInvited => "user already has a pending invitation",
+            ApiError::AlreadyMember => "user is already a member of the band",
+            ApiError::NotPending => "invitation was already answered or revoked",
+            ApiError::InvitationExpired => "invitation expired",
+            ApiError::NotAllowed(message) => message,
+            ApiError::Unavailable(_) => "service unavailable, try again later",
+            ApiError::Internal(_) => "internal server error",
+        };
+
+        message.to_string()
+    }
+
+    /// More about the error for the client, server failures keep their cause
+    /// to the logs
+    pub fn details(&self) -> Option<String> {
+        match self {
+            ApiError::InvalidEmail(verdict) => verdict.reason().map(|reason| reason.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Response carrying `request_id` in its body, server failures are logged
+    /// with their cause
+    pub fn response(&self, request_id: Option<&str>) -> HttpResponse {
+        with_request_id(request_id, || match self {
+            ApiError::Unavailable(cause) => warn!("{}: {}", self.code(), cause),
+            ApiError::Internal(cause) => error!("{}: {}", self.code(), cause),
+            _ => (),
+        });
+
+        self.body_response(request_id)
+    }
+
+    fn body_response(&self, request_id: Option<&str>) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status());
+        if let ApiError::RateLimited(retry_after) = self {
+            // whole seconds, rounded up so retrying then succeeds
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.header(RETRY_AFTER, seconds.max(1).to_string());
+        }
+
+        response.json(ErrorBody {
+            code: self.code().to_string(),
+            message: self.message(),
+            details: self.details(),
+            request_id: request_id.map(|request_id| request_id.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl Fail for ApiError {}
+
+impl ResponseError for ApiError {
+    /// Without the request id, `AssignRequestId` answers with `response` once
+    /// it is known
+    fn error_response(&self) -> HttpResponse {
+        self.body_response(None)
+    }
+}
+
+/// Answer of a worker with both the mailbox and the message failures as an
+/// `ApiError`, meant for `Future::then`
+pub fn api_result<T, E>(sent: Result<Result<T, E>, MailboxError>) -> Result<T, ApiError>
+where
+    E: Into<ApiError>,
+{
+    match sent {
+        Ok(Ok(item)) => Ok(item),
+        Ok(Err(error)) => Err(error.into()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Fails the request right away
+pub fn fail(error: ApiError) -> FutureResponse<HttpResponse> {
+    Box::new(futures::future::err(error.into()))
+}
+
+impl From<DbError> for ApiError {
+    fn from(error: DbError) -> Self {
+        match error {
+            DbError::Pool(_) => ApiError::Unavailable(error.to_string()),
+            DbError::Query(_) => ApiError::Internal(error.to_string()),
+        }
+    }
+}
+
+impl From<MailboxError> for ApiError {
+    fn from(error: MailboxError) -> Self {
+        ApiError::Unavailable(format!("worker unreachable: {}", error))
+    }
+}
+
+impl From<MailError> for ApiError {
+    fn from(error: MailError) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
+
+impl From<CreateUserError> for ApiError {
+    fn from(error: CreateUserError) -> Self {
+        match error {
+            CreateUserError::UserAlreadyExists => ApiError::UserAlreadyExists,
+            CreateUserError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<UpdateUserError> for ApiError {
+    fn from(error: UpdateUserError) -> Self {
+        match error {
+            UpdateUserError::UserAlreadyExists => ApiError::UserAlreadyExists,
+            UpdateUserError::NotFound => ApiError::UserNotFound,
+            UpdateUserError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<DeleteUserError> for ApiError {
+    fn from(error: DeleteUserError) -> Self {
+        match error {
+            DeleteUserError::LastOwner => ApiError::LastOwner,
+            DeleteUserError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<LoginError> for ApiError {
+    fn from(error: LoginError) -> Self {
+        match error {
+            LoginError::InvalidCredentials => ApiError::InvalidCredentials,
+            LoginError::EmailNotVerified => ApiError::EmailNotVerified,
+            LoginError::AccountSuspended => ApiError::AccountSuspended,
+            LoginError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<VerifyEmailError> for ApiError {
+    fn from(error: VerifyEmailError) -> Self {
+        match error {
+            VerifyEmailError::InvalidToken => ApiError::InvalidToken,
+            VerifyEmailError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<RequestPasswordResetError> for ApiError {
+    fn from(error: RequestPasswordResetError) -> Self {
+        match error {
+            RequestPasswordResetError::TooManyRequests => ApiError::TooManyRequests,
+            RequestPasswordResetError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<ResetPasswordError> for ApiError {
+    fn from(error: ResetPasswordError) -> Self {
+        match error {
+            ResetPasswordError::InvalidToken => ApiError::InvalidToken,
+            ResetPasswordError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(error: SearchError) -> Self {
+        match error {
+            SearchError::InvalidCursor => ApiError::InvalidCursor,
+            SearchError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<BandError> for ApiError {
+    fn from(error: BandError) -> Self {
+        match error {
+            BandError::NotFound => ApiError::BandNotFound,
+            BandError::MemberNotFound => ApiError::MemberNotFound,
+            BandError::NotAllowed => ApiError::NotAllowed("not allowed to manage this band"),
+            BandError::LastOwner => ApiError::LastOwner,
+            BandError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<EventError> for ApiError {
+    fn from(error: EventError) -> Self {
+        match error {
+            EventError::NotFound => ApiError::EventNotFound,
+            EventError::BandNotFound => ApiError::BandNotFound,
+            EventError::NotAllowed => ApiError::NotAllowed("not allowed to manage this event"),
+            EventError::InvalidSchedule => ApiError::InvalidSchedule,
+            EventError::Cancelled => ApiError::EventCancelled,
+            EventError::DbError(error) => error.into(),
+        }
+    }
+}
+
+impl From<InvitationError> for ApiError {
+    fn from(error: InvitationError) -> Self {
+        match error {
+            InvitationError::NotFound => ApiError::InvitationNotFound,
+            InvitationError::TargetNotFound => ApiError::TargetNotFound,
+            InvitationError::InviteeNotFound => ApiError::InviteeNotFound,
+            InvitationError::NotAllowed => {
+                ApiError::NotAllowed("not allowed to manage invitations of this band or event")
+            }
+            InvitationError::InvalidTarget => ApiError::InvalidTarget,
+            InvitationError::AlreadyInvited => ApiError::AlreadyInvited,
+            InvitationError::AlreadyMember => ApiError::AlreadyMember,
+            InvitationError::NotPending => ApiError::NotPending,
+            InvitationError::Expired => ApiError::InvitationExpired,
+            InvitationError::EventCancelled => ApiError::EventCancelled,
+            InvitationError::DbError(error) => error.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pool_errors_are_unavailable() {
+        use diesel::pg::PgConnection;
+        use diesel::r2d2::ConnectionManager;
+
+        // nothing listens on the port, no connection gets free in time
+        let pool = ::r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(ConnectionManager::<PgConnection>::new(
+                "postgres://127.0.0.1:1/peers_test",
+            ));
+        let pool_error = pool.get().err().unwrap();
+
+        let error = ApiError::from(DbError::Pool(pool_error));
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "unavailable");
+
+        let error = ApiError::from(BandError::DbError(DbError::Query(
+            ::diesel::result::Error::NotFound,
+        )));
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let error = ApiError::from(::actix::MailboxError::Timeout);
+        assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.code(), "unavailable");
+    }
+
+    #[test]
+    fn test_codes_are_unique() {
+        let errors = vec![
+            ApiError::Unauthorized,
+            ApiError::InvalidCredentials,
+            ApiError::EmailNotVerified,
+            ApiError::AccountSuspended,
+            ApiError::UserNotFound,
+            ApiError::UserAlreadyExists,
+            ApiError::InvalidEmail(EmailVerdict::Disposable),
+            ApiError::WeakPassword,
+            ApiError::InvalidToken,
+            ApiError::TooManyRequests,
+            ApiError::RateLimited(Duration::from_secs(1)),
+            ApiError::InvalidCursor,
+            ApiError::BandNotFound,
+            ApiError::MemberNotFound,
+            ApiError::LastOwner,
+            ApiError::InvalidBandName,
+            ApiError::EventNotFound,
+            ApiError::InvalidSchedule,
+            ApiError::EventCancelled,
+            ApiError::InvalidEventTitle,
+            ApiError::InvalidTimezone,
+            ApiError::InvitationNotFound,
+            ApiError::TargetNotFound,
+            ApiError::InviteeNotFound,
+            ApiError::InvalidTarget,
+            ApiError::InvalidInvitee,
+            ApiError::AlreadyInvited,
+            ApiError::AlreadyMember,
+            ApiError::NotPending,
+            ApiError::InvitationExpired,
+            ApiError::NotAllowed("not allowed"),
+            ApiError::Unavailable("pool timeout".to_string()),
+            ApiError::Internal("query failed".to_string()),
+        ];
+
+        let mut codes = errors.iter().map(|error| error.code()).collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+    }
+}
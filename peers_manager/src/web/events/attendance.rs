@@ -0,0 +1,126 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Path, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::api_result;
+use super::super::request_id::RequestId;
+use db::events::{ListAttendees, SetRsvp};
+use models::RsvpStatus;
+
+#[derive(Deserialize, Serialize)]
+pub struct RsvpInput {
+    pub status: RsvpStatus,
+}
+
+/// Records whether the current user goes to the event
+pub fn set_rsvp(
+    (user, id, rsvp, state, request_id): (
+        AuthenticatedUser,
+        Path<i64>,
+        Json<RsvpInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    // send async `SetRsvp` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            SetRsvp {
+                event_id: id.into_inner(),
+                user_id: user.0.id,
+                status: rsvp.status,
+            },
+        )
+        .then(api_result)
+        .map(|attendee| HttpResponse::Ok().json(attendee))
+        .from_err()
+        .responder()
+}
+
+pub fn list_attendees(
+    (id, state, request_id): (Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `ListAttendees` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            ListAttendees {
+                event_id: id.into_inner(),
+            },
+        )
+        .then(api_result)
+        .map(|attendees| HttpResponse::Ok().json(attendees))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod attendance_tests {
+
+    use actix_web::HttpMessage;
+    use models::{Event, EventAttendee, RsvpStatus, User};
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_rsvp() {
+        let mut srv = create_test_server();
+
+        let organizer_token = srv.test_create_new_user(new_user("test_rsvp_1@gmail.com"));
+        let guest_token = srv.test_create_new_user(new_user("test_rsvp_2@gmail.com"));
+        let event = srv.test_create_new_event(&organizer_token, new_event("event 1", 1));
+
+        let response = srv.set_rsvp(&guest_token, event.id, RsvpStatus::Maybe);
+        assert!(response.status().is_success());
+
+        let response = srv.set_rsvp(&guest_token, event.id, RsvpStatus::Going);
+        assert!(response.status().is_success());
+
+        let response = srv.list_attendees(event.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let attendees: Vec<EventAttendee> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(attendees.len(), 1);
+        assert_eq!(attendees[0].status, "going");
+
+        // the event shows up among the guest's upcoming events
+        let response = srv.current_user(Some(&guest_token));
+        let bytes = srv.execute(response.body()).unwrap();
+        let guest: User = serde_json::from_slice(&bytes).unwrap();
+
+        let response = srv.list_upcoming_events(Some(guest.id), None);
+        let bytes = srv.execute(response.body()).unwrap();
+        let events: Vec<Event> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, event.id);
+
+        let response = srv.set_rsvp(&guest_token, event.id, RsvpStatus::Declined);
+        assert!(response.status().is_success());
+
+        let response = srv.list_upcoming_events(Some(guest.id), None);
+        let bytes = srv.execute(response.body()).unwrap();
+        let events: Vec<Event> = serde_json::from_slice(&bytes).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_rsvp_cancelled_event() {
+        let mut srv = create_test_server();
+
+        let organizer_token = srv.test_create_new_user(new_user("test_rsvp_3@gmail.com"));
+        let guest_token = srv.test_create_new_user(new_user("test_rsvp_4@gmail.com"));
+        let event = srv.test_create_new_event(&organizer_token, new_event("event 1", 1));
+        srv.cancel_event(&organizer_token, event.id);
+
+        let response = srv.set_rsvp(&guest_token, event.id, RsvpStatus::Going);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "event_cancelled");
+        assert!(response.status().is_client_error());
+    }
+}
@@ -0,0 +1,338 @@
+use super::super::app::AppState;
+
+use chrono::NaiveDateTime;
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Path, Query, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use super::time_zones::is_time_zone;
+use db::events::{CancelEvent, CreateEvent, GetEvent, ListUpcomingEvents, UpdateEvent};
+
+/// Times are in UTC, `timezone` is the venue's time zone for local display,
+/// an IANA name like `Europe/Kiev`
+#[derive(Deserialize, Serialize)]
+pub struct NewEventInput {
+    pub title: String,
+    pub description: String,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub timezone: String,
+    pub venue: String,
+    /// Band organizing the event, the current user organizes it when missing
+    pub band_id: Option<i64>,
+}
+
+/// Fields of the event to change, missing ones are kept
+#[derive(Deserialize, Serialize, Default)]
+pub struct UpdateEventInput {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub timezone: Option<String>,
+    pub venue: Option<String>,
+}
+
+/// Creates an event organized by the current user or by one of its bands
+pub fn create_event(
+    (user, new_event, state, request_id): (
+        AuthenticatedUser,
+        Json<NewEventInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let new_event = new_event.into_inner();
+
+    if new_event.title.trim().is_empty() {
+        return fail(ApiError::InvalidEventTitle);
+    }
+    if !is_time_zone(&new_event.timezone) {
+        return fail(ApiError::InvalidTimezone);
+    }
+
+    // send async `CreateEvent` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            CreateEvent {
+                created_by: user.0.id,
+                title: new_event.title,
+                description: new_event.description,
+                starts_at: new_event.starts_at,
+                ends_at: new_event.ends_at,
+                timezone: new_event.timezone,
+                venue: new_event.venue,
+                band_id: new_event.band_id,
+            },
+        )
+        .then(api_result)
+        .map(|event| HttpResponse::Ok().json(event))
+        .from_err()
+        .responder()
+}
+
+/// Lists upcoming events, `?user=<id>` or `?band=<id>` narrow them down
+pub fn list_upcoming_events(
+    (list, state, request_id): (Query<ListUpcomingEvents>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `ListUpcomingEvents` message to a `DbExecutor`
+    state
+        .db
+        .send(&request_id, list.into_inner())
+        .then(api_result)
+        .map(|events| HttpResponse::Ok().json(events))
+        .from_err()
+        .responder()
+}
+
+pub fn get_event(
+    (id, state, request_id): (Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `GetEvent` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            GetEvent {
+                id: id.into_inner(),
+            },
+        )
+        .then(api_result)
+        .map(|event| HttpResponse::Ok().json(event))
+        .from_err()
+        .responder()
+}
+
+/// Changes an event, allowed to its organizer
+pub fn update_event(
+    (user, id, update, state, request_id): (
+        AuthenticatedUser,
+        Path<i64>,
+        Json<UpdateEventInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let update = update.into_inner();
+
+    if update
+        .title
+        .as_ref()
+        .map_or(false, |title| title.trim().is_empty())
+    {
+        return fail(ApiError::InvalidEventTitle);
+    }
+    if update
+        .timezone
+        .as_ref()
+        .map_or(false, |timezone| !is_time_zone(timezone))
+    {
+        return fail(ApiError::InvalidTimezone);
+    }
+
+    // send async `UpdateEvent` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            UpdateEvent {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+                title: update.title,
+                description: update.description,
+                starts_at: update.starts_at,
+                ends_at: update.ends_at,
+                timezone: update.timezone,
+                venue: update.venue,
+            },
+        )
+        .then(api_result)
+        .map(|event| HttpResponse::Ok().json(event))
+        .from_err()
+        .responder()
+}
+
+/// Cancels an event, allowed to its organizer
+pub fn cancel_event(
+    (user, id, state, request_id): (AuthenticatedUser, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `CancelEvent` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            CancelEvent {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+            },
+        )
+        .then(api_result)
+        .map(|event| HttpResponse::Ok().json(event))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod manage_event_tests {
+
+    use super::*;
+    use actix_web::HttpMessage;
+    use chrono::Duration;
+    use models::Event;
+    use web::bands::manage::NewBandInput;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_create_event() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_event_1@gmail.com"));
+        let event = srv.test_create_new_event(&token, new_event("event 1", 1));
+        assert_eq!(event.title, "event 1");
+        assert!(event.organizer_user_id.is_some());
+        assert!(event.organizer_band_id.is_none());
+
+        let response = srv.get_event(event.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let fetched: Event = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(fetched.id, event.id);
+    }
+
+    #[test]
+    fn test_create_event_invalid_schedule() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_event_2@gmail.com"));
+
+        let mut event = new_event("event 1", 1);
+        event.ends_at = event.starts_at - Duration::hours(1);
+
+        let response = srv.create_event(&token, event);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_schedule");
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_event_unknown_timezone() {
+        // refused before reaching the database
+        let mut srv = create_memory_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_event_12@gmail.com"));
+
+        let mut event = new_event("event 1", 1);
+        event.timezone = "Europe/Atlantis".to_string();
+
+        let response = srv.create_event(&token, event);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_timezone");
+        assert_eq!(response.status().as_u16(), 400);
+
+        let update = UpdateEventInput {
+            timezone: Some("UTC+3".to_string()),
+            ..UpdateEventInput::default()
+        };
+        let response = srv.update_event(&token, 1, update);
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    #[test]
+    fn test_band_event_requires_band_manager() {
+        let mut srv = create_test_server();
+
+        let owner_token = srv.test_create_new_user(new_user("test_event_3@gmail.com"));
+        let other_token = srv.test_create_new_user(new_user("test_event_4@gmail.com"));
+        let band = srv.test_create_new_band(
+            &owner_token,
+            NewBandInput {
+                name: "band 1".to_string(),
+                description: "description 1".to_string(),
+                genre: "jazz".to_string(),
+            },
+        );
+
+        let mut event = new_event("event 1", 1);
+        event.band_id = Some(band.id);
+        let response = srv.create_event(&other_token, event);
+        assert_eq!(response.status().as_u16(), 403);
+
+        let mut event = new_event("event 1", 1);
+        event.band_id = Some(band.id);
+        let event = srv.test_create_new_event(&owner_token, event);
+        assert_eq!(event.organizer_band_id, Some(band.id));
+
+        let response = srv.list_upcoming_events(None, Some(band.id));
+        let bytes = srv.execute(response.body()).unwrap();
+        let events: Vec<Event> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, event.id);
+    }
+
+    #[test]
+    fn test_update_and_cancel_event() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_event_5@gmail.com"));
+        let other_token = srv.test_create_new_user(new_user("test_event_6@gmail.com"));
+        let event = srv.test_create_new_event(&token, new_event("event 1", 1));
+
+        let update = UpdateEventInput {
+            venue: Some("venue 2".to_string()),
+            ..UpdateEventInput::default()
+        };
+        let response = srv.update_event(&other_token, event.id, update);
+        assert_eq!(response.status().as_u16(), 403);
+
+        let update = UpdateEventInput {
+            venue: Some("venue 2".to_string()),
+            ..UpdateEventInput::default()
+        };
+        let response = srv.update_event(&token, event.id, update);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let updated: Event = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(updated.venue, "venue 2");
+
+        let response = srv.cancel_event(&token, event.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let cancelled: Event = serde_json::from_slice(&bytes).unwrap();
+        assert!(cancelled.cancelled_at.is_some());
+
+        let update = UpdateEventInput {
+            venue: Some("venue 3".to_string()),
+            ..UpdateEventInput::default()
+        };
+        let response = srv.update_event(&token, event.id, update);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "event_cancelled");
+    }
+
+    #[test]
+    fn test_list_upcoming_events() {
+        let mut srv = create_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_event_7@gmail.com"));
+        let later = srv.test_create_new_event(&token, new_event("event 2", 2));
+        let sooner = srv.test_create_new_event(&token, new_event("event 1", 1));
+        srv.test_create_new_event(&token, new_event("event 0", -1));
+        let cancelled = srv.test_create_new_event(&token, new_event("event 3", 3));
+        srv.cancel_event(&token, cancelled.id);
+
+        let response = srv.list_upcoming_events(later.organizer_user_id, None);
+        let bytes = srv.execute(response.body()).unwrap();
+        let events: Vec<Event> = serde_json::from_slice(&bytes).unwrap();
+        let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, vec![sooner.id, later.id]);
+    }
+}
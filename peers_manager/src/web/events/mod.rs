@@ -0,0 +1,3 @@
+pub mod attendance;
+pub mod manage;
+pub mod time_zones;
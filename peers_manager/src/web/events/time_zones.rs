@@ -0,0 +1,35 @@
+//! Time zones of event venues, named as in the IANA tz database
+use std::collections::HashSet;
+
+/// Zones and links of the tz database, one name per line
+const TIME_ZONES: &str = include_str!("../../../data/time_zones.txt");
+
+lazy_static! {
+    static ref NAMES: HashSet<&'static str> = TIME_ZONES
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+}
+
+/// Whether `name` is a time zone of the tz database, like `Europe/Kiev`
+pub fn is_time_zone(name: &str) -> bool {
+    NAMES.contains(name)
+}
+
+#[cfg(test)]
+mod time_zones_tests {
+
+    use super::*;
+
+    #[test]
+    fn test_is_time_zone() {
+        assert!(is_time_zone("Europe/Kiev"));
+        assert!(is_time_zone("America/Argentina/Buenos_Aires"));
+        assert!(is_time_zone("UTC"));
+
+        assert!(!is_time_zone("Europe/Atlantis"));
+        assert!(!is_time_zone("europe/kiev"));
+        assert!(!is_time_zone(""));
+        assert!(!is_time_zone("# Time zone names"));
+    }
+}
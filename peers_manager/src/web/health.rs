@@ -0,0 +1,113 @@
+//! Probes of the process, served outside of the api
+//!
+//! `/health` answers as long as the server runs, `/ready` only once the
+//! users store and, unless users are kept in SQLite, the Postgres pool give
+//! out a connection and a validator answers. Load balancers should stop
+//! sending requests otherwise.
+use std::time::Duration;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, State};
+use futures::{future, Future};
+
+use super::app::AppState;
+use super::email_validator::Ping;
+use super::error::{api_result, ApiError};
+use super::request_id::RequestId;
+use db::users::CheckConnection;
+
+/// Longest wait for the stores or a validator before answering not ready
+const READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+    pub status: String,
+}
+
+fn ok() -> HttpResponse {
+    HttpResponse::Ok().json(Status {
+        status: "ok".to_string(),
+    })
+}
+
+pub fn health(_state: State<AppState>) -> HttpResponse {
+    ok()
+}
+
+/// `with_postgres` is false when users are kept in SQLite, the pool of the
+/// `DbExecutor`s is never connected then
+pub fn ready(
+    (state, request_id): (State<AppState>, RequestId),
+    with_postgres: bool,
+) -> FutureResponse<HttpResponse> {
+    let database: Box<dyn Future<Item = (), Error = ApiError>> = if with_postgres {
+        Box::new(
+            state
+                .db
+                .send_timeout(&request_id, CheckConnection, READY_TIMEOUT)
+                .then(api_result),
+        )
+    } else {
+        Box::new(future::ok(()))
+    };
+    let users = state
+        .users
+        .send_timeout(&request_id, CheckConnection, READY_TIMEOUT)
+        .then(api_result);
+    let validator = state
+        .email_validator
+        .send_timeout(&request_id, Ping, READY_TIMEOUT)
+        .map_err(ApiError::from);
+
+    database
+        .join3(users, validator)
+        .map(|_| ok())
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod health_tests {
+
+    use super::*;
+    use actix_web::http::Method;
+    use actix_web::HttpMessage;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_health() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv.client(Method::GET, "/health").finish().unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let status: Status = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(status.status, "ok");
+    }
+
+    #[test]
+    fn test_ready() {
+        let mut srv = create_test_server();
+
+        let request = srv.client(Method::GET, "/ready").finish().unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn test_ready_on_sqlite() {
+        use config::SqliteConfig;
+
+        // Postgres is never connected to when users are kept in SQLite
+        let mut config = test_config();
+        config.database.sqlite = Some(SqliteConfig {
+            path: ":memory:".to_string(),
+        });
+        let mut srv = create_memory_test_server_configured(config);
+
+        let request = srv.client(Method::GET, "/ready").finish().unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+    }
+}
@@ -0,0 +1,279 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Path, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::api_result;
+use super::super::request_id::RequestId;
+use db::invitations::{AcceptInvitation, DeclineInvitation, ListPendingInvitations};
+
+/// Pending invitations of the current user
+pub fn list_inbox(
+    (user, state, request_id): (AuthenticatedUser, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `ListPendingInvitations` message to a `DbExecutor`
+    state
+        .db
+        .send(&request_id, ListPendingInvitations { user_id: user.0.id })
+        .then(api_result)
+        .map(|invitations| HttpResponse::Ok().json(invitations))
+        .from_err()
+        .responder()
+}
+
+/// Joins the band or goes to the event the current user was invited to
+pub fn accept_invitation(
+    (user, id, state, request_id): (AuthenticatedUser, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `AcceptInvitation` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            AcceptInvitation {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+            },
+        )
+        .then(api_result)
+        .map(|invitation| HttpResponse::Ok().json(invitation))
+        .from_err()
+        .responder()
+}
+
+pub fn decline_invitation(
+    (user, id, state, request_id): (AuthenticatedUser, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `DeclineInvitation` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            DeclineInvitation {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+            },
+        )
+        .then(api_result)
+        .map(|invitation| HttpResponse::Ok().json(invitation))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod inbox_tests {
+
+    use super::super::super::app::VerificationSettings;
+    use super::super::send::NewInvitationInput;
+    use actix_web::test::TestServer;
+    use actix_web::HttpMessage;
+    use chrono::{Duration, Utc};
+    use mail::memory::Outbox;
+    use models::{BandMember, EventAttendee, Invitation};
+    use web::error::ErrorBody;
+    use web::events::manage::NewEventInput;
+    use web::tests_tools::*;
+
+    fn inbox(srv: &mut TestServer, token: &str) -> Vec<Invitation> {
+        let response = srv.list_inbox(token);
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_accept_band_invitation() {
+        let mut srv = create_test_server();
+
+        let (owner_token, _) = create_member(&mut srv, "test_inbox_1@gmail.com");
+        let (guest_token, guest_id) = create_member(&mut srv, "test_inbox_2@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let invitation = srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(invitation.status, "pending");
+
+        let invitations = inbox(&mut srv, &guest_token);
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].id, invitation.id);
+
+        // only the invitee may answer
+        let response = srv.accept_invitation(&owner_token, invitation.id);
+        assert_eq!(response.status().as_u16(), 404);
+
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let accepted: Invitation = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(accepted.status, "accepted");
+
+        let response = srv.list_band_members(band.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let members: Vec<BandMember> = serde_json::from_slice(&bytes).unwrap();
+        let guest = members.iter().find(|member| member.user_id == guest_id);
+        assert_eq!(guest.unwrap().role, "member");
+
+        assert!(inbox(&mut srv, &guest_token).is_empty());
+
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "not_pending");
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_accept_event_invitation() {
+        let mut srv = create_test_server();
+
+        let (organizer_token, _) = create_member(&mut srv, "test_inbox_3@gmail.com");
+        let (guest_token, guest_id) = create_member(&mut srv, "test_inbox_4@gmail.com");
+
+        let starts_at = (Utc::now() + Duration::days(1)).naive_utc();
+        let event = srv.test_create_new_event(
+            &organizer_token,
+            NewEventInput {
+                title: "event 1".to_string(),
+                description: "description 1".to_string(),
+                starts_at,
+                ends_at: starts_at + Duration::hours(3),
+                timezone: "Europe/Kiev".to_string(),
+                venue: "venue 1".to_string(),
+                band_id: None,
+            },
+        );
+
+        let invitation = srv.test_create_new_invitation(
+            &organizer_token,
+            NewInvitationInput {
+                event_id: Some(event.id),
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        assert!(response.status().is_success());
+
+        let response = srv.list_attendees(event.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let attendees: Vec<EventAttendee> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(attendees.len(), 1);
+        assert_eq!(attendees[0].user_id, guest_id);
+        assert_eq!(attendees[0].status, "going");
+    }
+
+    #[test]
+    fn test_decline_invitation() {
+        let mut srv = create_test_server();
+
+        let (owner_token, _) = create_member(&mut srv, "test_inbox_5@gmail.com");
+        let (guest_token, guest_id) = create_member(&mut srv, "test_inbox_6@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let invitation = srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+
+        let response = srv.decline_invitation(&guest_token, invitation.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let declined: Invitation = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(declined.status, "declined");
+
+        let response = srv.list_band_members(band.id);
+        let bytes = srv.execute(response.body()).unwrap();
+        let members: Vec<BandMember> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(members.len(), 1);
+
+        assert!(inbox(&mut srv, &guest_token).is_empty());
+    }
+
+    #[test]
+    fn test_invitation_to_unregistered_email() {
+        let outbox = Outbox::default();
+        let mut srv = create_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let email = "test_inbox_8@gmail.com";
+
+        let (owner_token, _) = create_member(&mut srv, "test_inbox_7@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let invitation = srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                email: Some("Test_Inbox_8@gmail.com".to_string()),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(invitation.invitee_user_id, None);
+        assert!(outbox.lock().unwrap().iter().any(|sent| sent.to == email));
+
+        // registering with the email isn't enough, verifying it makes the
+        // invitation show up in the inbox
+        let (guest_token, guest_id) = create_member(&mut srv, email);
+        assert!(inbox(&mut srv, &guest_token).is_empty());
+
+        let response = srv.verify_email(&verification_token(&outbox, email));
+        assert!(response.status().is_success());
+
+        let invitations = inbox(&mut srv, &guest_token);
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].id, invitation.id);
+        assert_eq!(invitations[0].invitee_user_id, Some(guest_id));
+
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn test_invitation_to_unverified_email() {
+        let outbox = Outbox::default();
+        let mut srv = create_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let email = "test_inbox_10@gmail.com";
+
+        // anyone can register an email, the account isn't trusted with the
+        // invitations sent to it until the email is verified
+        let (guest_token, _) = create_member(&mut srv, email);
+
+        let (owner_token, _) = create_member(&mut srv, "test_inbox_9@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let invitation = srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                email: Some(email.to_string()),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(invitation.invitee_user_id, None);
+
+        assert!(inbox(&mut srv, &guest_token).is_empty());
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        assert!(response.status().is_client_error());
+
+        let response = srv.verify_email(&verification_token(&outbox, email));
+        assert!(response.status().is_success());
+
+        let invitations = inbox(&mut srv, &guest_token);
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].id, invitation.id);
+    }
+}
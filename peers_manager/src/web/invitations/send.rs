@@ -0,0 +1,285 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, Error, FutureResponse, HttpResponse, Json, Path, State};
+
+use super::super::auth::AuthenticatedUser;
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use super::super::users::create::validate_email_request;
+use db::invitations::{CreateInvitation, RevokeInvitation};
+use db::users::DbExecutor;
+use mail::{Email, MailExecutor, SendMail};
+use web::email_validator::EmailVerdict;
+use web::metrics::MeteredAddr;
+
+/// Invites to either `band_id` or `event_id` a registered user by `user_id`
+/// or anybody by `email`
+#[derive(Deserialize, Serialize, Default)]
+pub struct NewInvitationInput {
+    pub band_id: Option<i64>,
+    pub event_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub email: Option<String>,
+}
+
+/// Invites a user to a band or an event managed by the current user. Emails
+/// which are not registered and verified yet get a mail asking them to sign
+/// up.
+pub fn create_invitation(
+    (user, new_invitation, state, request_id): (
+        AuthenticatedUser,
+        Json<NewInvitationInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let new_invitation = new_invitation.into_inner();
+
+    if new_invitation.band_id.is_some() == new_invitation.event_id.is_some() {
+        return fail(ApiError::InvalidTarget);
+    }
+
+    if new_invitation.user_id.is_some() == new_invitation.email.is_some() {
+        return fail(ApiError::InvalidInvitee);
+    }
+
+    let db = state.db.clone();
+    let mailer = state.mailer.clone();
+    let link_base_url = state.verification.get().link_base_url.clone();
+    let invited_by = user.0.name.clone();
+
+    let create = CreateInvitation {
+        invited_by: user.0.id,
+        band_id: new_invitation.band_id,
+        event_id: new_invitation.event_id,
+        user_id: new_invitation.user_id,
+        email: new_invitation.email.clone(),
+    };
+
+    let email = match new_invitation.email {
+        Some(email) => email,
+        None => {
+            return db_create_invitation(db, request_id, mailer, link_base_url, invited_by, create)
+        }
+    };
+
+    Box::new(
+        validate_email_request(state.email_validator.clone(), &request_id, &email).and_then(
+            move |verdict| {
+                match verdict {
+                    // canonical as the emails of the users, verifying the
+                    // email claims the invitation
+                    EmailVerdict::Valid(email) => {
+                        let create = CreateInvitation {
+                            email: Some(email),
+                            ..create
+                        };
+                        db_create_invitation(
+                            db,
+                            request_id,
+                            mailer,
+                            link_base_url,
+                            invited_by,
+                            create,
+                        )
+                    }
+                    refused => fail(ApiError::InvalidEmail(refused)),
+                }
+            },
+        ),
+    )
+}
+
+fn db_create_invitation(
+    db: MeteredAddr<DbExecutor>,
+    request_id: RequestId,
+    mailer: actix::Addr<MailExecutor>,
+    link_base_url: String,
+    invited_by: String,
+    create: CreateInvitation,
+) -> FutureResponse<HttpResponse> {
+    // send async `CreateInvitation` message to a `DbExecutor`
+    db.send(&request_id, create)
+        .then(api_result)
+        .from_err()
+        .and_then(move |invitation| -> FutureResponse<HttpResponse> {
+            // registered users find the invitation in the app, emails get a mail
+            match (invitation.invitee_user_id, invitation.invitee_email.clone()) {
+                (None, Some(to)) => {
+                    let email = Email {
+                        to,
+                        subject: "You are invited to Peers".to_string(),
+                        body: format!(
+                            "{} invited you to join them on Peers. Sign up with this email \
+                             address at {} and verify it to answer the invitation.",
+                            invited_by, link_base_url
+                        ),
+                    };
+
+                    Box::new(mailer.send(SendMail(email)).then(move |sent| {
+                        if let Err(error) = api_result(sent) {
+                            error!("Failed to send invitation email: {}", error);
+                        }
+                        Ok::<_, Error>(HttpResponse::Ok().json(invitation))
+                    }))
+                }
+                _ => Box::new(futures::future::ok(HttpResponse::Ok().json(invitation))),
+            }
+        })
+        .responder()
+}
+
+/// Withdraws a pending invitation sent by the current user or to a band or an
+/// event it manages
+pub fn revoke_invitation(
+    (user, id, state, request_id): (AuthenticatedUser, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `RevokeInvitation` message to a `DbExecutor`
+    state
+        .db
+        .send(
+            &request_id,
+            RevokeInvitation {
+                id: id.into_inner(),
+                acting_user_id: user.0.id,
+            },
+        )
+        .then(api_result)
+        .map(|invitation| HttpResponse::Ok().json(invitation))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod send_tests {
+
+    use super::super::super::app::VerificationSettings;
+    use super::*;
+    use actix_web::test::TestServer;
+    use actix_web::HttpMessage;
+    use mail::memory::Outbox;
+    use models::Invitation;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    fn error_code(srv: &mut TestServer, response: actix_web::client::ClientResponse) -> String {
+        assert!(response.status().is_client_error());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        error_data.code
+    }
+
+    #[test]
+    fn test_invite_requires_manager() {
+        let mut srv = create_test_server();
+
+        let (owner_token, _) = create_member(&mut srv, "test_invite_1@gmail.com");
+        let (other_token, other_id) = create_member(&mut srv, "test_invite_2@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.create_invitation(
+            &other_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(other_id),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(response.status().as_u16(), 403);
+        assert_eq!(error_code(&mut srv, response), "not_allowed");
+    }
+
+    #[test]
+    fn test_invite_validation() {
+        let outbox = Outbox::default();
+        let mut srv = create_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let (owner_token, owner_id) = create_member(&mut srv, "test_invite_3@gmail.com");
+        let (_, guest_id) = create_member(&mut srv, "test_invite_4@gmail.com");
+        let response = srv.verify_email(&verification_token(&outbox, "test_invite_4@gmail.com"));
+        assert!(response.status().is_success());
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let response = srv.create_invitation(
+            &owner_token,
+            NewInvitationInput {
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(error_code(&mut srv, response), "invalid_target");
+
+        let response = srv.create_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                email: Some("email 1".to_string()),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(error_code(&mut srv, response), "invalid_email");
+
+        let response = srv.create_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(owner_id),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(error_code(&mut srv, response), "already_member");
+
+        srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+
+        // the verified email of the guest matches the pending invitation too
+        let response = srv.create_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                email: Some("test_invite_4@gmail.com".to_string()),
+                ..NewInvitationInput::default()
+            },
+        );
+        assert_eq!(error_code(&mut srv, response), "already_invited");
+    }
+
+    #[test]
+    fn test_revoke_invitation() {
+        let mut srv = create_test_server();
+
+        let (owner_token, _) = create_member(&mut srv, "test_invite_5@gmail.com");
+        let (guest_token, guest_id) = create_member(&mut srv, "test_invite_6@gmail.com");
+        let band = srv.test_create_new_band(&owner_token, new_band("band 1"));
+
+        let invitation = srv.test_create_new_invitation(
+            &owner_token,
+            NewInvitationInput {
+                band_id: Some(band.id),
+                user_id: Some(guest_id),
+                ..NewInvitationInput::default()
+            },
+        );
+
+        let response = srv.revoke_invitation(&guest_token, invitation.id);
+        assert_eq!(error_code(&mut srv, response), "not_allowed");
+
+        let response = srv.revoke_invitation(&owner_token, invitation.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let revoked: Invitation = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(revoked.status, "revoked");
+
+        let response = srv.accept_invitation(&guest_token, invitation.id);
+        assert_eq!(error_code(&mut srv, response), "not_pending");
+    }
+}
@@ -0,0 +1,457 @@
+//! Prometheus metrics, served at `/metrics`
+//!
+//! `RecordMetrics` counts the requests and their latency by route pattern,
+//! unknown paths share one label so scanners can't grow the series.
+//! `MeteredAddr` counts the messages an actor did not answer yet, actix
+//! doesn't tell the length of a mailbox. It also tags the messages with the
+//! id of their request, for the logs of the actor.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix::dev::ToEnvelope;
+use actix::{Actor, Addr, Handler, MailboxError, Message};
+use actix_web::middleware::{Finished, Middleware, Started};
+use actix_web::{HttpRequest, HttpResponse, Result, State};
+use futures::Future;
+use logging::Traced;
+
+use super::app::AppState;
+use super::request_id::RequestId;
+use super::routes::{routes, API_PREFIX};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Paths served outside of the api
+pub const PROBE_PATHS: &[&str] = &["/health", "/ready", "/metrics"];
+
+/// Label of the paths matching no route
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+lazy_static! {
+    /// Patterns of the api routes with their prefix, `/api/v1/users/{id}`
+    static ref ROUTE_PATTERNS: Vec<String> = {
+        let mut patterns = routes()
+            .into_iter()
+            .map(|(_, path)| format!("{}{}", API_PREFIX, path))
+            .collect::<Vec<_>>();
+        patterns.dedup();
+        patterns
+    };
+}
+
+/// Pattern of the route serving `path`, literal segments win over
+/// parameters as in the router
+fn route_label(path: &str) -> &'static str {
+    if let Some(probe) = PROBE_PATHS.iter().find(|probe| **probe == path) {
+        return probe;
+    }
+
+    let segments = path.split('/').collect::<Vec<_>>();
+    let matches = |pattern: &str, literal_only: bool| {
+        let pattern = pattern.split('/').collect::<Vec<_>>();
+        pattern.len() == segments.len()
+            && pattern.iter().zip(&segments).all(|(expected, segment)| {
+                expected == segment
+                    || (!literal_only && expected.starts_with('{') && !segment.is_empty())
+            })
+    };
+
+    ROUTE_PATTERNS
+        .iter()
+        .find(|pattern| matches(pattern, true))
+        .or_else(|| {
+            ROUTE_PATTERNS
+                .iter()
+                .find(|pattern| matches(pattern, false))
+        })
+        .map(|pattern| pattern.as_str())
+        .unwrap_or(UNMATCHED_ROUTE)
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket of `LATENCY_BUCKETS`, not cumulated
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Method and route pattern of a request
+type RouteKey = (String, &'static str);
+
+/// Metrics collected while serving, shared by every worker
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(RouteKey, u16), u64>>,
+    latencies: Mutex<BTreeMap<RouteKey, Histogram>>,
+    suffix_list_refreshed: Mutex<Instant>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            latencies: Mutex::new(BTreeMap::new()),
+            suffix_list_refreshed: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn record_request(&self, method: &str, path: &str, status: u16, latency: Duration) {
+        let key = (method.to_string(), route_label(path));
+        let seconds = latency.as_secs() as f64 + f64::from(latency.subsec_nanos()) / 1e9;
+
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((key.clone(), status))
+            .or_insert(0) += 1;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(Histogram::default)
+            .observe(seconds);
+    }
+
+    /// The public suffix list was loaded or downloaded again
+    pub fn suffix_list_refreshed(&self) {
+        *self.suffix_list_refreshed.lock().unwrap() = Instant::now();
+    }
+
+    /// Prometheus text format of the metrics and of the pool and actors of
+    /// `state`
+    pub fn render(&self, state: &AppState) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "peers_http_requests_total",
+            "counter",
+            "Requests answered by route and status",
+        );
+        for (&((ref method, route), status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "peers_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method, route, status, count
+            );
+        }
+
+        header(
+            &mut out,
+            "peers_http_request_duration_seconds",
+            "histogram",
+            "Time to answer requests by route",
+        );
+        for (&(ref method, route), histogram) in self.latencies.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, route);
+            let mut cumulated = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulated += count;
+                let _ = writeln!(
+                    out,
+                    "peers_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulated
+                );
+            }
+            let _ = writeln!(
+                out,
+                "peers_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "peers_http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "peers_http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        let pool = state.db_pool.state();
+        header(
+            &mut out,
+            "peers_db_pool_connections",
+            "gauge",
+            "Connections of the Postgres pool by state",
+        );
+        let _ = writeln!(
+            out,
+            "peers_db_pool_connections{{state=\"idle\"}} {}",
+            pool.idle_connections
+        );
+        let _ = writeln!(
+            out,
+            "peers_db_pool_connections{{state=\"in_use\"}} {}",
+            pool.connections - pool.idle_connections
+        );
+        header(
+            &mut out,
+            "peers_db_pool_max_connections",
+            "gauge",
+            "Size of the Postgres pool",
+        );
+        let _ = writeln!(
+            out,
+            "peers_db_pool_max_connections {}",
+            state.db_pool.max_size()
+        );
+
+        header(
+            &mut out,
+            "peers_actor_pending_messages",
+            "gauge",
+            "Messages sent to an actor and not answered yet",
+        );
+        let _ = writeln!(
+            out,
+            "peers_actor_pending_messages{{actor=\"db\"}} {}",
+            state.db.pending()
+        );
+        let _ = writeln!(
+            out,
+            "peers_actor_pending_messages{{actor=\"users\"}} {}",
+            state.users.pending()
+        );
+        let _ = writeln!(
+            out,
+            "peers_actor_pending_messages{{actor=\"email_validator\"}} {}",
+            state.email_validator.pending()
+        );
+
+        header(
+            &mut out,
+            "peers_public_suffix_list_age_seconds",
+            "gauge",
+            "Time since the public suffix list was loaded or refreshed",
+        );
+        let _ = writeln!(
+            out,
+            "peers_public_suffix_list_age_seconds {}",
+            self.suffix_list_refreshed
+                .lock()
+                .unwrap()
+                .elapsed()
+                .as_secs()
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Decrements the pending messages when the answer arrives or the request is
+/// dropped
+struct Pending(Arc<AtomicUsize>);
+
+impl Pending {
+    fn new(pending: &Arc<AtomicUsize>) -> Self {
+        pending.fetch_add(1, Ordering::SeqCst);
+        Pending(pending.clone())
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Address of an actor counting the messages it did not answer yet
+pub struct MeteredAddr<A: Actor> {
+    addr: Addr<A>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl<A: Actor> MeteredAddr<A> {
+    pub fn new(addr: Addr<A>) -> Self {
+        MeteredAddr {
+            addr,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn send<M>(
+        &self,
+        request_id: &RequestId,
+        msg: M,
+    ) -> impl Future<Item = M::Result, Error = MailboxError>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Traced<M>>,
+        A::Context: ToEnvelope<A, Traced<M>>,
+    {
+        let pending = Pending::new(&self.pending);
+
+        self.addr.send(request_id.trace(msg)).then(move |answer| {
+            drop(pending);
+            answer
+        })
+    }
+
+    /// `send` failing with `MailboxError::Timeout` after `timeout`
+    pub fn send_timeout<M>(
+        &self,
+        request_id: &RequestId,
+        msg: M,
+        timeout: Duration,
+    ) -> impl Future<Item = M::Result, Error = MailboxError>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Traced<M>>,
+        A::Context: ToEnvelope<A, Traced<M>>,
+    {
+        let pending = Pending::new(&self.pending);
+
+        self.addr
+            .send(request_id.trace(msg))
+            .timeout(timeout)
+            .then(move |answer| {
+                drop(pending);
+                answer
+            })
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+}
+
+impl<A: Actor> Clone for MeteredAddr<A> {
+    fn clone(&self) -> Self {
+        MeteredAddr {
+            addr: self.addr.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+/// When the request started, set by `RecordMetrics`
+struct RequestStart(Instant);
+
+/// Middleware recording every request in the `Metrics` of the state
+pub struct RecordMetrics;
+
+impl Middleware<AppState> for RecordMetrics {
+    fn start(&self, req: &HttpRequest<AppState>) -> Result<Started> {
+        req.extensions_mut().insert(RequestStart(Instant::now()));
+        Ok(Started::Done)
+    }
+
+    fn finish(&self, req: &HttpRequest<AppState>, resp: &HttpResponse) -> Finished {
+        if let Some(start) = req.extensions().get::<RequestStart>() {
+            req.state().metrics.record_request(
+                req.method().as_str(),
+                req.path(),
+                resp.status().as_u16(),
+                start.0.elapsed(),
+            );
+        }
+        Finished::Done
+    }
+}
+
+pub fn metrics(state: State<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render(&state))
+}
+
+#[cfg(test)]
+mod metrics_tests {
+
+    use super::*;
+    use actix_web::http;
+    use actix_web::HttpMessage;
+    use web::tests_tools::*;
+    use web::users::create::NewUserInput;
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!(route_label("/api/v1/users/12"), "/api/v1/users/{id}");
+        assert_eq!(route_label("/api/v1/users/me"), "/api/v1/users/me");
+        assert_eq!(route_label("/api/v1/users/search"), "/api/v1/users/search");
+        assert_eq!(
+            route_label("/api/v1/bands/1/members/2"),
+            "/api/v1/bands/{id}/members/{user_id}"
+        );
+        assert_eq!(route_label("/health"), "/health");
+        assert_eq!(route_label("/api/v1/users/"), UNMATCHED_ROUTE);
+        assert_eq!(route_label("/wp-login.php"), UNMATCHED_ROUTE);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.001);
+        histogram.observe(0.3);
+        histogram.observe(60.0);
+
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[6], 1);
+        assert_eq!(histogram.buckets.iter().sum::<u64>(), 2);
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let mut srv = create_memory_test_server();
+        srv.test_create_new_user(NewUserInput {
+            name: "name 1".to_string(),
+            email: "test_metrics@gmail.com".to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        });
+
+        let request = srv.client(http::Method::GET, "/metrics").finish().unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains(
+            "peers_http_requests_total{method=\"POST\",route=\"/api/v1/users/create_user\",status=\"200\"} 1"
+        ));
+        assert!(text.contains(
+            "peers_http_request_duration_seconds_count{method=\"POST\",route=\"/api/v1/users/create_user\"} 1"
+        ));
+        assert!(text.contains("peers_actor_pending_messages{actor=\"db\"} 0"));
+        assert!(text.contains("peers_actor_pending_messages{actor=\"users\"} 0"));
+        assert!(text.contains("peers_db_pool_max_connections"));
+        assert!(text.contains("peers_public_suffix_list_age_seconds"));
+    }
+}
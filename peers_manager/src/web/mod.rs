@@ -1,3 +1,19 @@
 pub mod app;
+pub mod auth;
+pub mod authorization;
+pub mod bands;
 pub mod email_validator;
+pub mod error;
+pub mod events;
+pub mod health;
+pub mod invitations;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
+pub mod request_id;
+pub mod routes;
+pub mod security;
+#[cfg(test)]
+pub mod tests_tools;
+pub mod tls;
 pub mod users;
@@ -0,0 +1,728 @@
+//! OpenAPI 3 document of the api, served at `/api/v1/openapi.json`
+//!
+//! Schemas come from the request and response types through `Schema`,
+//! `object_schema!` lists the serialized fields of a type. Operations are
+//! listed by hand, a test of `web::routes` fails when they and the router
+//! diverge.
+use actix_web::{HttpResponse, State};
+use chrono::NaiveDateTime;
+use serde_json::{Map, Value};
+
+use super::app::AppState;
+use super::auth::AUTH_TOKEN_HEADER;
+use super::authorization::UserView;
+use super::bands::manage::{NewBandInput, UpdateBandInput};
+use super::bands::members::SetBandMemberInput;
+use super::error::ErrorBody;
+use super::events::attendance::RsvpInput;
+use super::events::manage::{NewEventInput, UpdateEventInput};
+use super::invitations::send::NewInvitationInput;
+use super::routes::API_PREFIX;
+use super::users::admin::SetRoleInput;
+use super::users::create::NewUserInput;
+use super::users::profile::UpdateUserInput;
+use super::users::search::UserPage;
+use db::password_reset::{RequestPasswordReset, ResetPassword};
+use db::search::SearchWithKeyword;
+use db::users::{LoginResponse, LoginWithEmail};
+use models::{BandRole, RsvpStatus, UserRole};
+
+/// JSON schema of the serialized form of a type
+pub trait Schema {
+    fn schema() -> Value;
+
+    /// Whether objects always have a field of this type
+    fn required() -> bool {
+        true
+    }
+}
+
+impl Schema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for i64 {
+    fn schema() -> Value {
+        json!({ "type": "integer", "format": "int64" })
+    }
+}
+
+impl Schema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl Schema for NaiveDateTime {
+    fn schema() -> Value {
+        // serialized without offset, the api uses UTC
+        json!({ "type": "string", "format": "date-time" })
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        let mut schema = T::schema();
+        schema["nullable"] = Value::Bool(true);
+        schema
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl Schema for BandRole {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["owner", "admin", "member"] })
+    }
+}
+
+impl Schema for RsvpStatus {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["going", "maybe", "declined"] })
+    }
+}
+
+impl Schema for UserRole {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["user", "moderator", "admin"] })
+    }
+}
+
+macro_rules! object_schema {
+    ($($type:ident { $($field:ident: $field_type:ty,)* })+) => {$(
+        impl Schema for $type {
+            fn schema() -> Value {
+                let mut properties = Map::new();
+                let mut required: Vec<&str> = Vec::new();
+                $(
+                    properties.insert(
+                        stringify!($field).to_string(),
+                        <$field_type as Schema>::schema(),
+                    );
+                    if <$field_type as Schema>::required() {
+                        required.push(stringify!($field));
+                    }
+                )*
+                json!({ "type": "object", "properties": properties, "required": required })
+            }
+        }
+    )+};
+}
+
+object_schema! {
+    NewUserInput {
+        name: String,
+        email: String,
+        about: String,
+        password: String,
+    }
+    LoginWithEmail {
+        email: String,
+        password: String,
+    }
+    LoginResponse {
+        token: String,
+    }
+    SearchWithKeyword {
+        keyword: String,
+        limit: Option<i64>,
+        cursor: Option<String>,
+        with_total: Option<bool>,
+    }
+    UserPage {
+        users: Vec<UserView>,
+        next_cursor: Option<String>,
+        total: Option<i64>,
+    }
+    ErrorBody {
+        code: String,
+        message: String,
+        details: Option<String>,
+        request_id: Option<String>,
+    }
+    UserView {
+        id: i64,
+        name: String,
+        about: String,
+        email: Option<String>,
+        email_verified: bool,
+        role: UserRole,
+        suspended_at: Option<NaiveDateTime>,
+    }
+    SetRoleInput {
+        role: UserRole,
+    }
+    UpdateUserInput {
+        name: Option<String>,
+        about: Option<String>,
+        email: Option<String>,
+    }
+    RequestPasswordReset {
+        email: String,
+    }
+    ResetPassword {
+        token: String,
+        password: String,
+    }
+    NewBandInput {
+        name: String,
+        description: String,
+        genre: String,
+    }
+    UpdateBandInput {
+        name: Option<String>,
+        description: Option<String>,
+        genre: Option<String>,
+    }
+    SetBandMemberInput {
+        role: BandRole,
+    }
+    NewEventInput {
+        title: String,
+        description: String,
+        starts_at: NaiveDateTime,
+        ends_at: NaiveDateTime,
+        timezone: String,
+        venue: String,
+        band_id: Option<i64>,
+    }
+    UpdateEventInput {
+        title: Option<String>,
+        description: Option<String>,
+        starts_at: Option<NaiveDateTime>,
+        ends_at: Option<NaiveDateTime>,
+        timezone: Option<String>,
+        venue: Option<String>,
+    }
+    RsvpInput {
+        status: RsvpStatus,
+    }
+    NewInvitationInput {
+        band_id: Option<i64>,
+        event_id: Option<i64>,
+        user_id: Option<i64>,
+        email: Option<String>,
+    }
+}
+
+macro_rules! schemas {
+    ($($type:ident),+) => {{
+        let mut schemas = Map::new();
+        $(schemas.insert(stringify!($type).to_string(), <$type as Schema>::schema());)+
+        schemas
+    }};
+}
+
+/// Schemas referenced by the operations
+fn components() -> Map<String, Value> {
+    schemas!(
+        NewUserInput,
+        LoginWithEmail,
+        LoginResponse,
+        SearchWithKeyword,
+        UserPage,
+        ErrorBody,
+        UserView,
+        SetRoleInput,
+        UpdateUserInput,
+        RequestPasswordReset,
+        ResetPassword,
+        NewBandInput,
+        UpdateBandInput,
+        SetBandMemberInput,
+        NewEventInput,
+        UpdateEventInput,
+        RsvpInput,
+        NewInvitationInput
+    )
+}
+
+/// Successful response of an operation
+enum Body {
+    /// One of the `components` schemas
+    Schema(&'static str),
+    Object,
+    List,
+    Empty,
+    /// Page for browsers
+    Html,
+}
+
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    /// Needs the `auth-token` header
+    authenticated: bool,
+    /// Name and type of the query parameters
+    query: &'static [(&'static str, &'static str)],
+    request: Option<&'static str>,
+    response: Body,
+}
+
+const ADMIN_USERS_QUERY: &[(&str, &str)] = &[
+    ("query", "string"),
+    ("role", "string"),
+    ("suspended", "boolean"),
+    ("limit", "integer"),
+    ("cursor", "string"),
+    ("with_total", "boolean"),
+];
+
+const SEARCH_QUERY: &[(&str, &str)] = &[
+    ("query", "string"),
+    ("name", "string"),
+    ("email", "string"),
+    ("limit", "integer"),
+    ("cursor", "string"),
+    ("sort", "string"),
+    ("with_total", "boolean"),
+];
+
+fn operations() -> Vec<Operation> {
+    fn operation(method: &'static str, path: &'static str, summary: &'static str) -> Operation {
+        Operation {
+            method,
+            path,
+            summary,
+            authenticated: false,
+            query: &[],
+            request: None,
+            response: Body::Object,
+        }
+    }
+
+    vec![
+        operation("get", "/openapi.json", "This document"),
+        Operation {
+            request: Some("NewUserInput"),
+            response: Body::Schema("LoginResponse"),
+            ..operation(
+                "post",
+                "/users/create_user",
+                "Registers a user, logs it in unless login needs a verified email",
+            )
+        },
+        Operation {
+            request: Some("LoginWithEmail"),
+            response: Body::Schema("LoginResponse"),
+            ..operation("post", "/users/login", "Opens a session")
+        },
+        Operation {
+            query: SEARCH_QUERY,
+            response: Body::Schema("UserPage"),
+            ..operation("get", "/users", "Searches users")
+        },
+        Operation {
+            request: Some("SearchWithKeyword"),
+            response: Body::Schema("UserPage"),
+            ..operation("post", "/users/search", "Deprecated, use GET /users")
+        },
+        Operation {
+            query: &[("token", "string")],
+            response: Body::Schema("UserView"),
+            ..operation("get", "/users/verify_email", "Verifies the email of a user")
+        },
+        Operation {
+            request: Some("RequestPasswordReset"),
+            response: Body::Empty,
+            ..operation(
+                "post",
+                "/users/request_password_reset",
+                "Mails a password reset link",
+            )
+        },
+        Operation {
+            query: &[("token", "string")],
+            response: Body::Html,
+            ..operation(
+                "get",
+                "/users/reset_password",
+                "Page of the reset link, sets a new password",
+            )
+        },
+        Operation {
+            request: Some("ResetPassword"),
+            response: Body::Empty,
+            ..operation("post", "/users/reset_password", "Sets a new password")
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Schema("UserView"),
+            ..operation("get", "/users/me", "The current user")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("UpdateUserInput"),
+            response: Body::Schema("UserView"),
+            ..operation(
+                "patch",
+                "/users/me",
+                "Changes the profile of the current user",
+            )
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Empty,
+            ..operation(
+                "delete",
+                "/users/me",
+                "Deletes the current user, refused while the only owner of a band",
+            )
+        },
+        Operation {
+            response: Body::Schema("UserView"),
+            ..operation("get", "/users/{id}", "Profile of a user")
+        },
+        Operation {
+            query: &[("member", "integer")],
+            response: Body::List,
+            ..operation("get", "/bands", "Lists bands")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("NewBandInput"),
+            ..operation("post", "/bands", "Creates a band")
+        },
+        operation("get", "/bands/{id}", "A band"),
+        Operation {
+            authenticated: true,
+            request: Some("UpdateBandInput"),
+            ..operation("patch", "/bands/{id}", "Changes a band")
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Empty,
+            ..operation("delete", "/bands/{id}", "Deletes a band")
+        },
+        Operation {
+            response: Body::List,
+            ..operation("get", "/bands/{id}/members", "Members of a band")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("SetBandMemberInput"),
+            ..operation(
+                "put",
+                "/bands/{id}/members/{user_id}",
+                "Sets the role of a member",
+            )
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Empty,
+            ..operation(
+                "delete",
+                "/bands/{id}/members/{user_id}",
+                "Removes a member",
+            )
+        },
+        Operation {
+            query: &[("user", "integer"), ("band", "integer")],
+            response: Body::List,
+            ..operation("get", "/events", "Lists upcoming events")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("NewEventInput"),
+            ..operation("post", "/events", "Creates an event")
+        },
+        operation("get", "/events/{id}", "An event"),
+        Operation {
+            authenticated: true,
+            request: Some("UpdateEventInput"),
+            ..operation("patch", "/events/{id}", "Changes an event")
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/events/{id}/cancel", "Cancels an event")
+        },
+        Operation {
+            response: Body::List,
+            ..operation("get", "/events/{id}/attendees", "Attendees of an event")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("RsvpInput"),
+            ..operation("put", "/events/{id}/rsvp", "Answers an event")
+        },
+        Operation {
+            authenticated: true,
+            request: Some("NewInvitationInput"),
+            ..operation("post", "/invitations", "Invites to a band or an event")
+        },
+        Operation {
+            authenticated: true,
+            response: Body::List,
+            ..operation(
+                "get",
+                "/invitations/inbox",
+                "Pending invitations of the current user",
+            )
+        },
+        Operation {
+            authenticated: true,
+            ..operation("post", "/invitations/{id}/accept", "Accepts an invitation")
+        },
+        Operation {
+            authenticated: true,
+            ..operation(
+                "post",
+                "/invitations/{id}/decline",
+                "Declines an invitation",
+            )
+        },
+        Operation {
+            authenticated: true,
+            ..operation(
+                "post",
+                "/invitations/{id}/revoke",
+                "Withdraws an invitation",
+            )
+        },
+        Operation {
+            authenticated: true,
+            query: ADMIN_USERS_QUERY,
+            response: Body::Schema("UserPage"),
+            ..operation("get", "/admin/users", "Lists all the accounts, admins only")
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Schema("UserView"),
+            ..operation(
+                "post",
+                "/admin/users/{id}/suspend",
+                "Suspends an account, admins only",
+            )
+        },
+        Operation {
+            authenticated: true,
+            response: Body::Schema("UserView"),
+            ..operation(
+                "post",
+                "/admin/users/{id}/restore",
+                "Restores a suspended account, admins only",
+            )
+        },
+        Operation {
+            authenticated: true,
+            request: Some("SetRoleInput"),
+            response: Body::Schema("UserView"),
+            ..operation(
+                "put",
+                "/admin/users/{id}/role",
+                "Sets the role of a user, admins only",
+            )
+        },
+    ]
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+impl Operation {
+    fn to_json(&self) -> Value {
+        // `{id}` segments of the path are ids
+        let mut parameters = self
+            .path
+            .split('/')
+            .filter(|segment| segment.starts_with('{') && segment.ends_with('}'))
+            .map(|segment| {
+                json!({
+                    "name": segment.trim_matches(|c| c == '{' || c == '}'),
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer", "format": "int64" },
+                })
+            })
+            .collect::<Vec<_>>();
+        parameters.extend(self.query.iter().map(
+            |&(name, kind)| json!({ "name": name, "in": "query", "schema": { "type": kind } }),
+        ));
+
+        let success = match self.response {
+            Body::Schema(name) => json!({
+                "200": { "description": "OK", "content": json_content(schema_ref(name)) }
+            }),
+            Body::Object => json!({
+                "200": { "description": "OK", "content": json_content(json!({ "type": "object" })) }
+            }),
+            Body::List => json!({
+                "200": {
+                    "description": "OK",
+                    "content": json_content(json!({ "type": "array", "items": { "type": "object" } })),
+                }
+            }),
+            Body::Empty => json!({ "200": { "description": "OK" } }),
+            Body::Html => json!({
+                "200": {
+                    "description": "OK",
+                    "content": { "text/html": { "schema": { "type": "string" } } },
+                }
+            }),
+        };
+        let mut responses = success.as_object().cloned().unwrap_or_default();
+        responses.insert(
+            "default".to_string(),
+            json!({ "description": "Error", "content": json_content(schema_ref("ErrorBody")) }),
+        );
+
+        let mut operation = json!({
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses,
+        });
+        if let Some(request) = self.request {
+            operation["requestBody"] =
+                json!({ "required": true, "content": json_content(schema_ref(request)) });
+        }
+        if self.authenticated {
+            operation["security"] = json!([{ "authToken": [] }]);
+        }
+        operation
+    }
+}
+
+fn build_document() -> Value {
+    let mut paths = Map::new();
+    for operation in operations() {
+        let path = paths
+            .entry(operation.path.to_string())
+            .or_insert_with(|| json!({}));
+        path[operation.method] = operation.to_json();
+    }
+
+    json!({
+        "openapi": "3.0.2",
+        "info": { "title": "Peers", "version": "1" },
+        "servers": [{ "url": API_PREFIX }],
+        "paths": paths,
+        "components": {
+            "schemas": components(),
+            "securitySchemes": {
+                "authToken": { "type": "apiKey", "in": "header", "name": AUTH_TOKEN_HEADER },
+            },
+        },
+    })
+}
+
+lazy_static! {
+    static ref DOCUMENT: Value = build_document();
+}
+
+/// The document, built once
+pub fn document() -> &'static Value {
+    &DOCUMENT
+}
+
+pub fn openapi_spec(_state: State<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(document())
+}
+
+#[cfg(test)]
+mod openapi_tests {
+
+    use super::*;
+    use serde::Serialize;
+
+    /// Fails when `object_schema!` misses or invents a field of `T`
+    fn assert_fields<T: Schema + Serialize>(value: T) {
+        let serialized = serde_json::to_value(value).unwrap();
+        let mut fields = serialized
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        let schema = T::schema();
+        let mut properties = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        fields.sort();
+        properties.sort();
+        assert_eq!(fields, properties);
+    }
+
+    #[test]
+    fn test_schemas_match_types() {
+        assert_fields(NewUserInput {
+            name: String::new(),
+            email: String::new(),
+            about: String::new(),
+            password: String::new(),
+        });
+        assert_fields(LoginWithEmail {
+            email: String::new(),
+            password: String::new(),
+            require_verified_email: true,
+            typed_email: None,
+        });
+        assert_fields(LoginResponse {
+            token: String::new(),
+        });
+        assert_fields(SearchWithKeyword {
+            keyword: String::new(),
+            limit: None,
+            cursor: None,
+            with_total: false,
+        });
+        assert_fields(UserPage {
+            users: Vec::new(),
+            next_cursor: Some(String::new()),
+            total: Some(1),
+        });
+        assert_fields(ErrorBody {
+            code: String::new(),
+            message: String::new(),
+            details: None,
+            request_id: None,
+        });
+        // optional fields are left out when hidden
+        assert_fields(UserView {
+            id: 1,
+            name: String::new(),
+            about: String::new(),
+            email: Some(String::new()),
+            email_verified: false,
+            role: UserRole::User,
+            suspended_at: Some(NaiveDateTime::from_timestamp(0, 0)),
+        });
+        assert_fields(SetRoleInput {
+            role: UserRole::Admin,
+        });
+        assert_fields(UpdateUserInput::default());
+        assert_fields(UpdateBandInput::default());
+        assert_fields(UpdateEventInput::default());
+        assert_fields(NewInvitationInput::default());
+    }
+
+    #[test]
+    fn test_references_resolve() {
+        let document = document();
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+
+        let serialized = document.to_string();
+        for reference in serialized.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "unknown schema {}", name);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Buckets kept in the memory of the server
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::RateLimitStore;
+use config::RateLimitRule;
+
+/// Takes between two removals of the full buckets
+const PRUNE_EVERY: usize = 1000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket is full again, the same as no bucket
+    full_at: Instant,
+}
+
+/// Buckets of a single server, workers share them through an `Arc`
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<(HashMap<String, Bucket>, usize)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn duration(seconds: f64) -> Duration {
+    Duration::new(seconds.trunc() as u64, (seconds.fract() * 1e9) as u32)
+}
+
+impl RateLimitStore for MemoryStore {
+    fn take(&self, key: &str, rule: &RateLimitRule, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(rule.capacity);
+        let per_second = f64::from(rule.per_minute) / 60.0;
+
+        let mut guard = self.buckets.lock().unwrap();
+        let (ref mut buckets, ref mut takes) = *guard;
+
+        *takes += 1;
+        if *takes % PRUNE_EVERY == 0 {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            full_at: now,
+        });
+
+        if now > bucket.updated {
+            let refilled = seconds(now - bucket.updated) * per_second;
+            bucket.tokens = (bucket.tokens + refilled).min(capacity);
+            bucket.updated = now;
+        }
+
+        if bucket.tokens < 1.0 {
+            return Err(duration((1.0 - bucket.tokens) / per_second));
+        }
+
+        bucket.tokens -= 1.0;
+        bucket.full_at = now + duration((capacity - bucket.tokens) / per_second);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod memory_store_tests {
+
+    use super::*;
+    use config::RateLimitKey;
+
+    fn rule(capacity: u32, per_minute: u32) -> RateLimitRule {
+        RateLimitRule {
+            route: "/users/login".to_string(),
+            key: RateLimitKey::Ip,
+            capacity,
+            per_minute,
+        }
+    }
+
+    #[test]
+    fn test_bucket() {
+        let store = MemoryStore::new();
+        let rule = rule(3, 6);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(store.take("a", &rule, now).is_ok());
+        }
+        let retry_after = store.take("a", &rule, now).unwrap_err();
+        assert_eq!(retry_after.as_secs(), 10);
+
+        // other keys have their own bucket
+        assert!(store.take("b", &rule, now).is_ok());
+
+        // one token every 10 seconds
+        assert!(store
+            .take("a", &rule, now + Duration::from_secs(5))
+            .is_err());
+        assert!(store
+            .take("a", &rule, now + Duration::from_secs(10))
+            .is_ok());
+        assert!(store
+            .take("a", &rule, now + Duration::from_secs(10))
+            .is_err());
+
+        // never more than the capacity
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert!(store.take("a", &rule, later).is_ok());
+        }
+        assert!(store.take("a", &rule, later).is_err());
+    }
+
+    #[test]
+    fn test_prune_full_buckets() {
+        let store = MemoryStore::new();
+        let rule = rule(1, 60);
+        let now = Instant::now();
+
+        for i in 0..PRUNE_EVERY - 1 {
+            assert!(store.take(&i.to_string(), &rule, now).is_ok());
+        }
+        assert_eq!(store.buckets.lock().unwrap().0.len(), PRUNE_EVERY - 1);
+
+        assert!(store
+            .take("last", &rule, now + Duration::from_secs(2))
+            .is_ok());
+        assert_eq!(store.buckets.lock().unwrap().0.len(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! Request rate limiting
+//!
+//! Token buckets keyed by client ip or by the email a request is about, the
+//! rules name the routes they throttle. `RateLimit` checks the ip buckets
+//! before the handler runs, handlers reading an email call
+//! `RateLimiter::check_email` since the body is not parsed yet in a
+//! middleware. Buckets live in a `RateLimitStore`, in memory for now.
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::middleware::{Middleware, Started};
+use actix_web::{FromRequest, HttpRequest, Result};
+
+use super::app::{AppState, Reloadable};
+use super::error::ApiError;
+use super::request_id::RequestId;
+use super::routes::API_PREFIX;
+use config::{RateLimitConfig, RateLimitKey, RateLimitRule};
+use logging::with_request_id;
+
+pub mod memory;
+
+/// Keeps the buckets, shared by every worker of the server
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token of the bucket `key` following `rule`, `Err` tells how
+    /// long until the next token
+    fn take(&self, key: &str, rule: &RateLimitRule, now: Instant) -> Result<(), Duration>;
+}
+
+/// Part of the configuration a reload replaces
+struct Rules {
+    /// Empty when rate limiting is disabled
+    rules: Vec<RateLimitRule>,
+    trust_forwarded_for: bool,
+}
+
+impl Rules {
+    fn new(config: &RateLimitConfig) -> Self {
+        let rules = if config.enabled {
+            config.rules.clone()
+        } else {
+            Vec::new()
+        };
+
+        Rules {
+            rules,
+            trust_forwarded_for: config.trust_forwarded_for,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    rules: Reloadable<Rules>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig, store: Arc<dyn RateLimitStore>) -> Self {
+        RateLimiter {
+            rules: Reloadable::new(Rules::new(config)),
+            store,
+        }
+    }
+
+    /// Applies the rules of `config` from now on, buckets are kept
+    pub fn reload(&self, config: &RateLimitConfig) {
+        self.rules.set(Rules::new(config));
+    }
+
+    /// Takes a token of every bucket of `route` for the `key` of the request,
+    /// `route` is relative to `API_PREFIX`
+    pub fn check(&self, route: &str, key: RateLimitKey, value: &str) -> Result<(), ApiError> {
+        let now = Instant::now();
+        let rules = self.rules.get();
+
+        for rule in rules.rules.iter() {
+            if rule.route != route || rule.key != key {
+                continue;
+            }
+
+            let bucket = format!("{:?}:{}:{}", key, route, value);
+            if let Err(retry_after) = self.store.take(&bucket, rule, now) {
+                return Err(ApiError::RateLimited(retry_after));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the email buckets of `route`, `email` should be canonical so
+    /// its variants share a bucket
+    pub fn check_email(&self, route: &str, email: &str) -> Result<(), ApiError> {
+        self.check(route, RateLimitKey::Email, email)
+    }
+
+    /// The peer address, or behind a proxy the address the proxy appended
+    /// to `X-Forwarded-For`. The entries before it come from the client and
+    /// could be anything.
+    fn client_ip(&self, req: &HttpRequest<AppState>) -> String {
+        if self.rules.get().trust_forwarded_for {
+            let forwarded = req
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(|ip| ip.trim())
+                .filter(|ip| !ip.is_empty());
+            if let Some(ip) = forwarded {
+                return ip.to_string();
+            }
+        }
+
+        req.peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// Middleware refusing the requests of a client ip over its rate limit
+pub struct RateLimit;
+
+impl Middleware<AppState> for RateLimit {
+    fn start(&self, req: &HttpRequest<AppState>) -> Result<Started> {
+        let path = req.path();
+        if !path.starts_with(API_PREFIX) {
+            return Ok(Started::Done);
+        }
+
+        let limiter = &req.state().rate_limiter;
+        let ip = limiter.client_ip(req);
+
+        match limiter.check(&path[API_PREFIX.len()..], RateLimitKey::Ip, &ip) {
+            Ok(()) => Ok(Started::Done),
+            Err(error) => {
+                let request_id = RequestId::from_request(req, &());
+                with_request_id(Some(&request_id.0), || {
+                    info!("Rate limited {} {} from {}", req.method(), path, ip)
+                });
+                Ok(Started::Response(error.response(Some(&request_id.0))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+
+    use super::memory::MemoryStore;
+    use super::*;
+    use actix_web::http::header::RETRY_AFTER;
+    use actix_web::HttpMessage;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    fn rule(route: &str, key: RateLimitKey, capacity: u32) -> RateLimitRule {
+        RateLimitRule {
+            route: route.to_string(),
+            key,
+            capacity,
+            per_minute: 1,
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = RateLimitConfig {
+            enabled: false,
+            trust_forwarded_for: false,
+            rules: vec![rule("/users/login", RateLimitKey::Ip, 1)],
+        };
+        let limiter = RateLimiter::new(&config, Arc::new(MemoryStore::new()));
+
+        for _ in 0..3 {
+            assert!(limiter
+                .check("/users/login", RateLimitKey::Ip, "127.0.0.1")
+                .is_ok());
+        }
+
+        limiter.reload(&RateLimitConfig {
+            enabled: true,
+            ..config
+        });
+        assert!(limiter
+            .check("/users/login", RateLimitKey::Ip, "127.0.0.1")
+            .is_ok());
+        assert!(limiter
+            .check("/users/login", RateLimitKey::Ip, "127.0.0.1")
+            .is_err());
+    }
+
+    #[test]
+    fn test_ip_limit() {
+        let mut srv =
+            create_rate_limited_test_server(vec![rule("/users/login", RateLimitKey::Ip, 2)]);
+
+        for i in 0..2 {
+            let response = srv.login_user(&format!("test_ip_limit_{}@gmail.com", i), "password");
+            assert_eq!(response.status().as_u16(), 400);
+        }
+
+        let response = srv.login_user("test_ip_limit_3@gmail.com", "password");
+        assert_eq!(response.status().as_u16(), 429);
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert_eq!(retry_after, "60");
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "rate_limited");
+
+        // other routes are not throttled
+        srv.test_create_new_user(new_user("test_ip_limit@gmail.com"));
+    }
+
+    #[test]
+    fn test_email_limit() {
+        let mut srv =
+            create_rate_limited_test_server(vec![rule("/users/login", RateLimitKey::Email, 1)]);
+        srv.test_create_new_user(new_user("test_email_limit@gmail.com"));
+
+        let response = srv.login_user("test_email_limit@gmail.com", "wrong password");
+        assert_eq!(response.status().as_u16(), 400);
+
+        // the right password doesn't help once throttled, nor another case
+        let response = srv.login_user("test_email_limit@GMAIL.com", "password 1");
+        assert_eq!(response.status().as_u16(), 429);
+
+        let response = srv.login_user("test_email_limit_other@gmail.com", "password 1");
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for() {
+        use actix_web::http;
+        use db::users::LoginWithEmail;
+        use std::time::Duration;
+
+        let mut config = test_config();
+        config.rate_limit = RateLimitConfig {
+            enabled: true,
+            trust_forwarded_for: true,
+            rules: vec![rule("/users/login", RateLimitKey::Ip, 2)],
+        };
+        let mut srv = create_memory_test_server_configured(config);
+
+        let mut login_from = |forwarded_for: &str| {
+            let login = LoginWithEmail {
+                email: "test_spoofed_forwarded_for@gmail.com".to_string(),
+                password: "password".to_string(),
+                require_verified_email: false,
+                typed_email: None,
+            };
+            let request = srv
+                .client(http::Method::POST, "/api/v1/users/login")
+                .header("x-forwarded-for", forwarded_for)
+                .timeout(Duration::from_secs(10))
+                .json(login)
+                .unwrap();
+            srv.execute(request.send()).unwrap().status().as_u16()
+        };
+
+        // the client makes up the first entries, the proxy appends its peer
+        for i in 0..2 {
+            assert_eq!(login_from(&format!("10.0.0.{}, 192.0.2.1", i)), 400);
+        }
+        assert_eq!(login_from("10.0.0.3, 192.0.2.1"), 429);
+
+        assert_eq!(login_from("192.0.2.1, 192.0.2.2"), 400);
+    }
+}
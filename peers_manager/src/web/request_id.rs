@@ -0,0 +1,214 @@
+//! Request ids
+//!
+//! `AssignRequestId` keeps the `X-Request-Id` of a request, or makes one up,
+//! and sends it back in the response. Handlers take it as the `RequestId`
+//! extractor and pass it along with their messages to the workers, so the
+//! access log, the error bodies and the records of the workers all carry it.
+use std::time::Instant;
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::{Finished, Middleware, Response, Started};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Result};
+use rand::Rng;
+
+use super::app::AppState;
+use super::error::ApiError;
+use logging::{with_request_id, Traced};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest id taken from a request, longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn generate() -> Self {
+        let bytes: [u8; 16] = rand::thread_rng().gen();
+        RequestId(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// The id sent by the client, unless it could mess up the logs
+    fn from_header(value: &str) -> Option<Self> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LENGTH
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+        if valid {
+            Some(RequestId(value.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// `msg` tagged with this id, for a worker
+    pub fn trace<M>(&self, msg: M) -> Traced<M> {
+        Traced {
+            request_id: Some(self.0.clone()),
+            msg,
+        }
+    }
+}
+
+impl FromRequest<AppState> for RequestId {
+    type Config = ();
+    type Result = Self;
+
+    fn from_request(req: &HttpRequest<AppState>, _: &Self::Config) -> Self::Result {
+        req.extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(RequestId::generate)
+    }
+}
+
+/// When the request started, for the access log
+struct RequestStart(Instant);
+
+/// Middleware giving every request an id and logging it once answered
+pub struct AssignRequestId;
+
+impl Middleware<AppState> for AssignRequestId {
+    fn start(&self, req: &HttpRequest<AppState>) -> Result<Started> {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+
+        req.extensions_mut().insert(request_id);
+        req.extensions_mut().insert(RequestStart(Instant::now()));
+        Ok(Started::Done)
+    }
+
+    fn response(&self, req: &HttpRequest<AppState>, resp: HttpResponse) -> Result<Response> {
+        let request_id = RequestId::from_request(req, &());
+
+        // errors are answered before the id is known, their body gets it now
+        let mut resp = match resp
+            .error()
+            .and_then(|error| error.as_fail().downcast_ref::<ApiError>())
+        {
+            Some(error) => {
+                // keeping the headers already set, the security headers among them
+                let mut with_id = error.response(Some(&request_id.0));
+                for name in resp.headers().keys() {
+                    if !with_id.headers().contains_key(name) {
+                        for value in resp.headers().get_all(name) {
+                            with_id.headers_mut().append(name.clone(), value.clone());
+                        }
+                    }
+                }
+                with_id
+            }
+            None => resp,
+        };
+
+        if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+            resp.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+        Ok(Response::Done(resp))
+    }
+
+    fn finish(&self, req: &HttpRequest<AppState>, resp: &HttpResponse) -> Finished {
+        let request_id = RequestId::from_request(req, &());
+        let elapsed = req
+            .extensions()
+            .get::<RequestStart>()
+            .map(|start| start.0.elapsed())
+            .unwrap_or_default();
+
+        with_request_id(Some(&request_id.0), || {
+            info!(
+                "{} {} {} {}ms",
+                req.method(),
+                req.path(),
+                resp.status().as_u16(),
+                elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+            )
+        });
+        Finished::Done
+    }
+}
+
+#[cfg(test)]
+mod request_id_tests {
+
+    use super::*;
+    use actix_web::http::Method;
+    use actix_web::HttpMessage;
+    use std::time::Duration;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(
+            RequestId::from_header("edge-1.abc_2"),
+            Some(RequestId("edge-1.abc_2".to_string()))
+        );
+        assert_eq!(RequestId::from_header(""), None);
+        assert_eq!(RequestId::from_header("two words"), None);
+        assert_eq!(RequestId::from_header("line\nbreak"), None);
+        assert_eq!(RequestId::from_header(&"a".repeat(65)), None);
+
+        let generated = RequestId::generate();
+        assert_eq!(generated.0.len(), 32);
+        assert_ne!(generated, RequestId::generate());
+    }
+
+    #[test]
+    fn test_propagate_request_id() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::GET, "/health")
+            .header(REQUEST_ID_HEADER, "test-propagate-1")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "test-propagate-1"
+        );
+
+        let request = srv
+            .client(Method::GET, "/health")
+            .header(REQUEST_ID_HEADER, "not a valid id")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        let generated = response.headers().get(REQUEST_ID_HEADER).unwrap();
+        assert_eq!(generated.len(), 32);
+    }
+
+    #[test]
+    fn test_error_body_request_id() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::GET, "/api/v1/users/me")
+            .header(REQUEST_ID_HEADER, "test-error-1")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers().get(REQUEST_ID_HEADER).unwrap(),
+            "test-error-1"
+        );
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "unauthorized");
+        assert_eq!(error_data.request_id, Some("test-error-1".to_string()));
+    }
+}
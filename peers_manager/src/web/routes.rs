@@ -0,0 +1,286 @@
+//! Routes of the http api
+//!
+//! Every endpoint lives below `API_PREFIX`, but for the health probes and the
+//! metrics. The server and the test servers both build their app with
+//! `configure`, `routes` lists the api routes it registers so the OpenAPI
+//! document can be checked against it. The routes of bands, events and
+//! invitations need their tables in Postgres, they are left out when users are
+//! kept in SQLite.
+use actix_web::http::Method;
+use actix_web::{App, Scope, State};
+
+use super::app::AppState;
+use super::auth::Authenticate;
+use super::bands::manage::{create_band, delete_band, get_band, list_bands, update_band};
+use super::bands::members::{list_band_members, remove_band_member, set_band_member};
+use super::events::attendance::{list_attendees, set_rsvp};
+use super::events::manage::{
+    cancel_event, create_event, get_event, list_upcoming_events, update_event,
+};
+use super::health::{health, ready};
+use super::invitations::inbox::{accept_invitation, decline_invitation, list_inbox};
+use super::invitations::send::{create_invitation, revoke_invitation};
+use super::metrics::{metrics, RecordMetrics};
+use super::openapi::openapi_spec;
+use super::rate_limit::RateLimit;
+use super::request_id::{AssignRequestId, RequestId};
+use super::security::{cors, security_headers};
+use super::users::admin::{list_users, restore_user, set_user_role, suspend_user};
+use super::users::create::create_user;
+use super::users::login::login_user;
+use super::users::password_reset::{request_password_reset, reset_password, reset_password_page};
+use super::users::profile::{current_user, delete_current_user, get_user, update_current_user};
+use super::users::search::{search_users, user_search};
+use super::users::verify::verify_email;
+use config::Config;
+
+/// Path of the current version of the api
+pub const API_PREFIX: &str = "/api/v1";
+
+/// `$routes` lists the method and path, relative to `API_PREFIX`, of the
+/// routes `$register` adds to a scope
+macro_rules! api_routes {
+    ($routes:ident, $register:ident {
+        $($path:expr => { $($method:ident => $handler:expr,)+ })+
+    }) => {
+        fn $routes() -> Vec<(Method, &'static str)> {
+            vec![$($((Method::$method, $path),)+)+]
+        }
+
+        fn $register(scope: Scope<AppState>) -> Scope<AppState> {
+            scope$(.resource($path, |r| {
+                $(r.method(Method::$method).with($handler);)+
+            }))+
+        }
+    };
+}
+
+api_routes!(user_routes, register_user_routes {
+    "/openapi.json" => {
+        GET => openapi_spec,
+    }
+    "/users/create_user" => {
+        POST => create_user,
+    }
+    "/users/login" => {
+        POST => login_user,
+    }
+    "/users" => {
+        GET => search_users,
+    }
+    "/users/search" => {
+        POST => user_search,
+    }
+    "/users/verify_email" => {
+        GET => verify_email,
+    }
+    "/users/request_password_reset" => {
+        POST => request_password_reset,
+    }
+    "/users/reset_password" => {
+        GET => reset_password_page,
+        POST => reset_password,
+    }
+    "/users/me" => {
+        GET => current_user,
+        PATCH => update_current_user,
+        DELETE => delete_current_user,
+    }
+    "/users/{id}" => {
+        GET => get_user,
+    }
+    "/admin/users" => {
+        GET => list_users,
+    }
+    "/admin/users/{id}/suspend" => {
+        POST => suspend_user,
+    }
+    "/admin/users/{id}/restore" => {
+        POST => restore_user,
+    }
+    "/admin/users/{id}/role" => {
+        PUT => set_user_role,
+    }
+});
+
+api_routes!(postgres_routes, register_postgres_routes {
+    "/bands" => {
+        GET => list_bands,
+        POST => create_band,
+    }
+    "/bands/{id}" => {
+        GET => get_band,
+        PATCH => update_band,
+        DELETE => delete_band,
+    }
+    "/bands/{id}/members" => {
+        GET => list_band_members,
+    }
+    "/bands/{id}/members/{user_id}" => {
+        PUT => set_band_member,
+        DELETE => remove_band_member,
+    }
+    "/events" => {
+        GET => list_upcoming_events,
+        POST => create_event,
+    }
+    "/events/{id}" => {
+        GET => get_event,
+        PATCH => update_event,
+    }
+    "/events/{id}/cancel" => {
+        POST => cancel_event,
+    }
+    "/events/{id}/attendees" => {
+        GET => list_attendees,
+    }
+    "/events/{id}/rsvp" => {
+        PUT => set_rsvp,
+    }
+    "/invitations" => {
+        POST => create_invitation,
+    }
+    "/invitations/inbox" => {
+        GET => list_inbox,
+    }
+    "/invitations/{id}/accept" => {
+        POST => accept_invitation,
+    }
+    "/invitations/{id}/decline" => {
+        POST => decline_invitation,
+    }
+    "/invitations/{id}/revoke" => {
+        POST => revoke_invitation,
+    }
+});
+
+/// Method and path, relative to `API_PREFIX`, of every route of the api
+pub fn routes() -> Vec<(Method, &'static str)> {
+    let mut routes = user_routes();
+    routes.extend(postgres_routes());
+    routes
+}
+
+/// Adds the security headers, the metrics, the request ids, CORS, the rate
+/// limits, the authentication, the probes and the api routes to `app`
+pub fn configure(app: App<AppState>, config: &Config) -> App<AppState> {
+    let app = app
+        .middleware(security_headers(&config.security_headers))
+        .middleware(RecordMetrics)
+        .middleware(AssignRequestId);
+    // preflight requests are answered before being throttled or authenticated
+    let app = match cors(&config.cors) {
+        Some(cors) => app.middleware(cors),
+        None => app,
+    };
+
+    let with_postgres_routes = config.database.sqlite.is_none();
+    let ready_probe = move |probe: (State<AppState>, RequestId)| ready(probe, with_postgres_routes);
+
+    app.middleware(RateLimit)
+        .middleware(Authenticate)
+        .resource("/health", |r| r.method(Method::GET).with(health))
+        .resource("/ready", move |r| r.method(Method::GET).with(ready_probe))
+        .resource("/metrics", |r| r.method(Method::GET).with(metrics))
+        .scope(API_PREFIX, move |scope| {
+            let scope = register_user_routes(scope);
+            if with_postgres_routes {
+                register_postgres_routes(scope)
+            } else {
+                scope
+            }
+        })
+}
+
+#[cfg(test)]
+mod routes_tests {
+
+    use super::*;
+    use actix_web::HttpMessage;
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+    use web::openapi::document;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_spec_matches_router() {
+        let routed = routes()
+            .into_iter()
+            .map(|(method, path)| (method.as_str().to_lowercase(), path.to_string()))
+            .collect::<BTreeSet<_>>();
+
+        let document = document();
+        let documented = document["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            routed.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "routes missing from the OpenAPI document"
+        );
+        assert_eq!(
+            documented.difference(&routed).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "documented operations without a route"
+        );
+        assert_eq!(routed.len(), routes().len(), "route registered twice");
+    }
+
+    #[test]
+    fn test_serve_spec() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::GET, "/api/v1/openapi.json")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(spec, *document());
+
+        // nothing is served outside of the version prefix
+        let request = srv
+            .client(Method::GET, "/openapi.json")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[test]
+    fn test_sqlite_leaves_out_postgres_routes() {
+        use config::SqliteConfig;
+
+        let mut config = test_config();
+        config.database.sqlite = Some(SqliteConfig {
+            path: ":memory:".to_string(),
+        });
+        let mut srv = create_memory_test_server_configured(config);
+
+        let request = srv
+            .client(Method::GET, "/api/v1/bands")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+
+        let response = srv.current_user(None);
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
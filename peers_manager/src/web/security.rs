@@ -0,0 +1,237 @@
+//! Browser facing headers
+//!
+//! `cors` lets the pages of the configured origins call the api, it answers
+//! their preflight requests before the rate limits and the authentication.
+//! Requests without an `Origin`, or from other origins, get no CORS header.
+//! `security_headers` adds HSTS, `X-Content-Type-Options` and
+//! `X-Frame-Options` to the responses which don't set them.
+use actix_web::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN};
+use actix_web::middleware::cors::Cors;
+use actix_web::middleware::{DefaultHeaders, Middleware, Response, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+
+use config::{CorsConfig, SecurityHeadersConfig};
+
+/// `Cors` answering only the allowed origin of the request
+///
+/// The `Cors` of actix-web 0.7 sets `Access-Control-Allow-Origin` to the list of
+/// all the allowed origins on every response, whatever the `Origin` of the
+/// request, browsers take a single origin only.
+pub struct AllowedOrigins {
+    cors: Cors,
+    /// None allows any origin
+    origins: Option<Vec<String>>,
+}
+
+impl AllowedOrigins {
+    fn allows(&self, origin: &str) -> bool {
+        match self.origins {
+            Some(ref origins) => origins.iter().any(|allowed| allowed == origin),
+            None => true,
+        }
+    }
+}
+
+impl<S: 'static> Middleware<S> for AllowedOrigins {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        self.cors.start(req)
+    }
+
+    fn response(&self, req: &HttpRequest<S>, resp: HttpResponse) -> Result<Response> {
+        let origin = match req.headers().get(ORIGIN) {
+            Some(origin) if origin.to_str().map(|o| self.allows(o)).unwrap_or(false) => {
+                origin.clone()
+            }
+            _ => return Ok(Response::Done(resp)),
+        };
+
+        match self.cors.response(req, resp)? {
+            Response::Done(mut resp) => {
+                resp.headers_mut()
+                    .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                Ok(Response::Done(resp))
+            }
+            response => Ok(response),
+        }
+    }
+}
+
+/// The CORS middleware of `config`, none without allowed origins
+pub fn cors(config: &CorsConfig) -> Option<AllowedOrigins> {
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+
+    let mut cors = Cors::build();
+    // no allowed origin at all allows any, `validate_cors` refuses "*" with
+    // other origins
+    for origin in config
+        .allowed_origins
+        .iter()
+        .filter(|origin| *origin != "*")
+    {
+        cors.allowed_origin(origin);
+    }
+    cors.allowed_methods(config.allowed_methods.iter().map(|method| method.as_str()))
+        .allowed_headers(config.allowed_headers.iter().map(|header| header.as_str()))
+        .expose_headers(config.exposed_headers.iter().map(|header| header.as_str()))
+        .max_age(config.max_age);
+    if config.supports_credentials {
+        cors.supports_credentials();
+    }
+
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        None
+    } else {
+        Some(config.allowed_origins.clone())
+    };
+    Some(AllowedOrigins {
+        cors: cors.finish(),
+        origins,
+    })
+}
+
+pub fn security_headers(config: &SecurityHeadersConfig) -> DefaultHeaders {
+    let headers = DefaultHeaders::new()
+        .header("X-Content-Type-Options", "nosniff")
+        .header("X-Frame-Options", config.frame_options.as_str());
+
+    if config.hsts_max_age > 0 {
+        headers.header(
+            "Strict-Transport-Security",
+            format!("max-age={}; includeSubDomains", config.hsts_max_age).as_str(),
+        )
+    } else {
+        headers
+    }
+}
+
+#[cfg(test)]
+mod security_tests {
+
+    use actix_web::http::Method;
+    use actix_web::HttpMessage;
+    use std::time::Duration;
+    use web::tests_tools::*;
+
+    /// Allowed by config/test.toml
+    const FRONTEND: &str = "http://localhost:3000";
+
+    #[test]
+    fn test_security_headers() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::GET, "/health")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get("x-content-type-options").unwrap(),
+            "nosniff"
+        );
+        assert_eq!(response.headers().get("x-frame-options").unwrap(), "DENY");
+        assert_eq!(
+            response.headers().get("strict-transport-security").unwrap(),
+            "max-age=31536000; includeSubDomains"
+        );
+
+        // errors get them too
+        let request = srv
+            .client(Method::GET, "/api/v1/users/me")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers().get("x-frame-options").unwrap(), "DENY");
+    }
+
+    #[test]
+    fn test_preflight() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::OPTIONS, "/api/v1/users/me")
+            .header("Origin", FRONTEND)
+            .header("Access-Control-Request-Method", "PATCH")
+            .header("Access-Control-Request-Headers", "auth-token, content-type")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            FRONTEND
+        );
+        let methods = response
+            .headers()
+            .get("access-control-allow-methods")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(methods.contains("PATCH"), "{}", methods);
+
+        let request = srv
+            .client(Method::OPTIONS, "/api/v1/users/me")
+            .header("Origin", "https://elsewhere.example.com")
+            .header("Access-Control-Request-Method", "PATCH")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none());
+    }
+
+    #[test]
+    fn test_cross_origin_request() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(Method::GET, "/api/v1/openapi.json")
+            .header("Origin", FRONTEND)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            FRONTEND
+        );
+        let exposed = response
+            .headers()
+            .get("access-control-expose-headers")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_lowercase();
+        assert!(exposed.contains("x-request-id"), "{}", exposed);
+
+        // requests without an origin, from curl or other servers, pass
+        let request = srv
+            .client(Method::GET, "/api/v1/openapi.json")
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+        assert!(response
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none());
+    }
+}
@@ -0,0 +1,1055 @@
+extern crate mime;
+
+use actix_web::client::ClientResponse;
+use actix_web::test::TestServer;
+use actix_web::HttpMessage;
+
+use super::app::{AppState, Reloadable, VerificationSettings};
+use super::auth::AUTH_TOKEN_HEADER;
+use super::bands::manage::{NewBandInput, UpdateBandInput};
+use super::bands::members::SetBandMemberInput;
+use super::email_validator::{load_list, EmailRules, SharedList, ValidateExecutor};
+use super::events::attendance::RsvpInput;
+use super::events::manage::{NewEventInput, UpdateEventInput};
+use super::invitations::send::NewInvitationInput;
+use super::metrics::{MeteredAddr, Metrics};
+use super::rate_limit::memory::MemoryStore;
+use super::rate_limit::RateLimiter;
+use super::routes::{configure, API_PREFIX};
+use super::users::admin::SetRoleInput;
+use super::users::create::NewUserInput;
+use super::users::profile::UpdateUserInput;
+use actix_web::App;
+use chrono::{Duration, Utc};
+use config::{Config, RateLimitConfig, RateLimitRule};
+use db::memory::MemoryUserRepository;
+use db::migrations::run_pending_migrations;
+use db::password_reset::{RequestPasswordReset, ResetPassword};
+use db::repository::UsersExecutor;
+use db::search::SearchWithKeyword;
+use db::users::{DbExecutor, LoginResponse, LoginWithEmail};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool};
+use mail::memory::{MemoryMailer, Outbox};
+use mail::MailExecutor;
+use models;
+use std::ops::{Deref, DerefMut};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::Arc;
+
+lazy_static! {
+    static ref TEST_CONFIG: Config =
+        Config::load_environment("test").expect("Invalid test configuration");
+    /// Parsed once, shared by the validators of every test server
+    static ref TEST_SUFFIX_LIST: SharedList = load_list(
+        TEST_CONFIG
+            .email_validation
+            .public_suffix_list
+            .as_ref()
+            .map(|path| path.as_str())
+    )
+    .expect("Invalid public suffix list");
+}
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// Tells apart the schemas of the tests running in this process
+static NEXT_SCHEMA: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Points every connection of a pool at the schema of its test
+#[derive(Debug)]
+struct SearchPath(String);
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for SearchPath {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute(&format!("SET search_path TO {}", self.0))
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Schema of the test database owned by one test, created with all the
+/// migrations applied and dropped with the value, so tests running in
+/// parallel never see each other's rows.
+pub struct TestDatabase {
+    schema: String,
+    pool: PgPool,
+}
+
+impl TestDatabase {
+    pub fn new() -> TestDatabase {
+        let schema = format!(
+            "test_{}_{}",
+            process::id(),
+            NEXT_SCHEMA.fetch_add(1, Ordering::SeqCst)
+        );
+
+        let conn = PgConnection::establish(&TEST_CONFIG.database.url)
+            .expect("Failed to connect to the test database.");
+        conn.batch_execute(&format!(
+            "CREATE SCHEMA {0}; SET search_path TO {0}",
+            schema
+        ))
+        .expect("Failed to create the test schema.");
+        run_pending_migrations(&conn, &mut ::std::io::sink())
+            .expect("Failed to migrate the test schema.");
+
+        let manager = ConnectionManager::<PgConnection>::new(TEST_CONFIG.database.url.as_str());
+        let pool = Pool::builder()
+            .max_size(TEST_CONFIG.database.pool_size)
+            .connection_customizer(Box::new(SearchPath(schema.clone())))
+            .build(manager)
+            .expect("Failed to create pool.");
+
+        TestDatabase { schema, pool }
+    }
+
+    pub fn pool(&self) -> PgPool {
+        self.pool.clone()
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let dropped = PgConnection::establish(&TEST_CONFIG.database.url).and_then(|conn| {
+            conn.batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", self.schema))
+                .map_err(|error| ConnectionError::BadConnection(error.to_string()))
+        });
+
+        if let Err(error) = dropped {
+            eprintln!("Failed to drop test schema {}: {}", self.schema, error);
+        }
+    }
+}
+
+/// Test server storing into a `TestDatabase` of its own, both are torn down
+/// when it is dropped. Derefs to the `TestServer`.
+pub struct DbTestServer {
+    // dropped first, so no worker uses the schema when it is dropped
+    srv: TestServer,
+    _database: TestDatabase,
+}
+
+impl Deref for DbTestServer {
+    type Target = TestServer;
+
+    fn deref(&self) -> &TestServer {
+        &self.srv
+    }
+}
+
+impl DerefMut for DbTestServer {
+    fn deref_mut(&mut self) -> &mut TestServer {
+        &mut self.srv
+    }
+}
+
+/// Pool which never connects unless used, for servers keeping users in memory
+/// so they run without the test database
+fn create_unconnected_pool() -> PgPool {
+    let manager = ConnectionManager::<PgConnection>::new(TEST_CONFIG.database.url.as_str());
+    Pool::builder()
+        .max_size(TEST_CONFIG.database.pool_size)
+        .min_idle(Some(0))
+        .build_unchecked(manager)
+}
+
+/// Where a test server stores users
+#[derive(Clone)]
+enum UserStorage {
+    /// The test database, bands, events and invitations refer to its users
+    Postgres,
+    /// A store of the server's own, the user endpoints need no database
+    Memory(MemoryUserRepository),
+}
+
+pub fn create_test_server() -> DbTestServer {
+    create_test_server_with(VerificationSettings::default(), Outbox::default())
+}
+
+/// Test server on a database schema of its own with the given verification
+/// settings, emails it sends end up in `outbox`.
+pub fn create_test_server_with(verification: VerificationSettings, outbox: Outbox) -> DbTestServer {
+    let database = TestDatabase::new();
+    let srv = build_test_server(
+        database.pool(),
+        UserStorage::Postgres,
+        verification,
+        outbox,
+        TEST_CONFIG.clone(),
+    );
+
+    DbTestServer {
+        srv,
+        _database: database,
+    }
+}
+
+/// Test server keeping users in memory, starting without any
+pub fn create_memory_test_server() -> TestServer {
+    create_memory_test_server_with(VerificationSettings::default(), Outbox::default())
+}
+
+/// Test server keeping users in memory with the given verification settings,
+/// emails it sends end up in `outbox`.
+pub fn create_memory_test_server_with(
+    verification: VerificationSettings,
+    outbox: Outbox,
+) -> TestServer {
+    build_test_server(
+        create_unconnected_pool(),
+        UserStorage::Memory(MemoryUserRepository::new()),
+        verification,
+        outbox,
+        TEST_CONFIG.clone(),
+    )
+}
+
+/// Test server keeping users in `repository`, so tests can change them
+/// without going through the api
+pub fn create_memory_test_server_on(repository: MemoryUserRepository) -> TestServer {
+    build_test_server(
+        create_unconnected_pool(),
+        UserStorage::Memory(repository),
+        VerificationSettings::default(),
+        Outbox::default(),
+        TEST_CONFIG.clone(),
+    )
+}
+
+/// Test server keeping users in memory, throttled by `rules` only
+pub fn create_rate_limited_test_server(rules: Vec<RateLimitRule>) -> TestServer {
+    let mut config = TEST_CONFIG.clone();
+    config.rate_limit = RateLimitConfig {
+        enabled: true,
+        trust_forwarded_for: false,
+        rules,
+    };
+
+    create_memory_test_server_configured(config)
+}
+
+/// Test server keeping users in memory, configured by `config` instead of the
+/// test configuration
+pub fn create_memory_test_server_configured(config: Config) -> TestServer {
+    build_test_server(
+        create_unconnected_pool(),
+        UserStorage::Memory(MemoryUserRepository::new()),
+        VerificationSettings::default(),
+        Outbox::default(),
+        config,
+    )
+}
+
+/// The test configuration
+pub fn test_config() -> Config {
+    TEST_CONFIG.clone()
+}
+
+fn build_test_server(
+    pool: PgPool,
+    storage: UserStorage,
+    verification: VerificationSettings,
+    outbox: Outbox,
+    config: Config,
+) -> TestServer {
+    use actix::sync::SyncArbiter;
+
+    let rate_limiter = RateLimiter::new(&config.rate_limit, Arc::new(MemoryStore::new()));
+    let metrics = Arc::new(Metrics::new());
+    let verification = Reloadable::new(verification);
+
+    TestServer::with_factory(move || {
+        let db_pool = pool.clone();
+        let addr1 = SyncArbiter::start(TEST_CONFIG.workers.db_executors, move || {
+            DbExecutor(db_pool.clone())
+        });
+        let addr4 = match storage.clone() {
+            UserStorage::Postgres => {
+                let users_pool = pool.clone();
+                SyncArbiter::start(TEST_CONFIG.workers.db_executors, move || {
+                    UsersExecutor(Box::new(DbExecutor(users_pool.clone())))
+                })
+            }
+            UserStorage::Memory(repository) => {
+                SyncArbiter::start(TEST_CONFIG.workers.db_executors, move || {
+                    UsersExecutor(Box::new(repository.clone()))
+                })
+            }
+        };
+        let addr2 = SyncArbiter::start(TEST_CONFIG.workers.email_validators, || ValidateExecutor {
+            list: TEST_SUFFIX_LIST.clone(),
+            rules: EmailRules::from_config(&TEST_CONFIG.email_validation),
+            resolver: None,
+        });
+        let outbox = outbox.clone();
+        let addr3 = SyncArbiter::start(1, move || {
+            MailExecutor(Box::new(MemoryMailer::new(outbox.clone())))
+        });
+        configure(
+            App::with_state(AppState {
+                db: MeteredAddr::new(addr1),
+                db_pool: pool.clone(),
+                users: MeteredAddr::new(addr4),
+                email_validator: MeteredAddr::new(addr2),
+                canonicalization: EmailRules::from_config(&TEST_CONFIG.email_validation)
+                    .canonicalization,
+                mailer: addr3,
+                verification: verification.clone(),
+                rate_limiter: rate_limiter.clone(),
+                metrics: metrics.clone(),
+            }),
+            &config,
+        )
+    })
+}
+
+/// Token of the latest verification link mailed to `email`
+pub fn verification_token(outbox: &Outbox, email: &str) -> String {
+    let outbox = outbox.lock().unwrap();
+    let sent = outbox
+        .iter()
+        .rev()
+        .find(|sent| sent.to == email && sent.body.contains("verify_email?token="))
+        .expect("verification email was not sent");
+    let start = sent.body.find("token=").unwrap() + "token=".len();
+    sent.body[start..].trim().to_string()
+}
+
+pub fn new_user(email: &str) -> NewUserInput {
+    NewUserInput {
+        name: "name 1".to_string(),
+        email: email.to_string(),
+        about: "about 1".to_string(),
+        password: "password 1".to_string(),
+    }
+}
+
+/// Creates a user, returns its session token and id
+pub fn create_member(srv: &mut TestServer, email: &str) -> (String, i64) {
+    let token = srv.test_create_new_user(new_user(email));
+
+    let response = srv.current_user(Some(&token));
+    let bytes = srv.execute(response.body()).unwrap();
+    let user: models::User = serde_json::from_slice(&bytes).unwrap();
+
+    (token, user.id)
+}
+
+pub fn new_band(name: &str) -> NewBandInput {
+    NewBandInput {
+        name: name.to_string(),
+        description: "description 1".to_string(),
+        genre: "jazz".to_string(),
+    }
+}
+
+/// Event starting in `in_days` and lasting three hours
+pub fn new_event(title: &str, in_days: i64) -> NewEventInput {
+    let starts_at = (Utc::now() + Duration::days(in_days)).naive_utc();
+
+    NewEventInput {
+        title: title.to_string(),
+        description: "description 1".to_string(),
+        starts_at,
+        ends_at: starts_at + Duration::hours(3),
+        timezone: "Europe/Kiev".to_string(),
+        venue: "venue 1".to_string(),
+        band_id: None,
+    }
+}
+
+/// Url of `path` in the current version of the api
+fn api(path: &str) -> String {
+    format!("{}{}", API_PREFIX, path)
+}
+
+pub trait UsersWebMethods {
+    fn create_user(&mut self, new_user: NewUserInput) -> ClientResponse;
+    fn test_create_new_user(&mut self, new_user: NewUserInput) -> String;
+    fn login_user(&mut self, email: &str, password: &str) -> ClientResponse;
+    fn current_user(&mut self, token: Option<&str>) -> ClientResponse;
+    fn get_user(&mut self, id: i64) -> ClientResponse;
+    fn update_current_user(&mut self, token: &str, update: UpdateUserInput) -> ClientResponse;
+    fn delete_current_user(&mut self, token: &str) -> ClientResponse;
+    fn verify_email(&mut self, token: &str) -> ClientResponse;
+    fn request_password_reset(&mut self, email: &str) -> ClientResponse;
+    fn reset_password(&mut self, token: &str, password: &str) -> ClientResponse;
+    fn search_users(&mut self, query: &str) -> ClientResponse;
+    fn search_users_as(&mut self, token: Option<&str>, query: &str) -> ClientResponse;
+    fn legacy_search_users(&mut self, search: SearchWithKeyword) -> ClientResponse;
+}
+
+impl UsersWebMethods for TestServer {
+    fn create_user(&mut self, new_user: NewUserInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api("/users/create_user"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .json(new_user)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn test_create_new_user(&mut self, new_user: NewUserInput) -> String {
+        let response = self.create_user(new_user);
+        let bytes = self.execute(response.body()).unwrap();
+        let token_data: LoginResponse = serde_json::from_slice(&bytes).unwrap();
+        let token = token_data.token;
+
+        assert!(!token.is_empty());
+        assert!(response.status().is_success());
+
+        token
+    }
+
+    fn login_user(&mut self, email: &str, password: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let login = LoginWithEmail {
+            email: email.to_string(),
+            password: password.to_string(),
+            require_verified_email: false,
+            typed_email: None,
+        };
+
+        let request = self
+            .client(http::Method::POST, &api("/users/login"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .json(login)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn current_user(&mut self, token: Option<&str>) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let mut request = self.client(http::Method::GET, &api("/users/me"));
+        if let Some(token) = token {
+            request.header(AUTH_TOKEN_HEADER, token);
+        }
+        let request = request.timeout(Duration::from_secs(10)).finish().unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn get_user(&mut self, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::GET, &api(&format!("/users/{}", id)))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn update_current_user(&mut self, token: &str, update: UpdateUserInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::PATCH, &api("/users/me"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(update)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn delete_current_user(&mut self, token: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::DELETE, &api("/users/me"))
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn verify_email(&mut self, token: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let path = format!("/users/verify_email?token={}", token.replace(' ', "%20"));
+        let request = self
+            .client(http::Method::GET, &api(&path))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn request_password_reset(&mut self, email: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = RequestPasswordReset {
+            email: email.to_string(),
+            typed_email: None,
+        };
+
+        let request = self
+            .client(http::Method::POST, &api("/users/request_password_reset"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .json(request)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn reset_password(&mut self, token: &str, password: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let reset = ResetPassword {
+            token: token.to_string(),
+            password: password.to_string(),
+        };
+
+        let request = self
+            .client(http::Method::POST, &api("/users/reset_password"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .json(reset)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn search_users(&mut self, query: &str) -> ClientResponse {
+        self.search_users_as(None, query)
+    }
+
+    fn search_users_as(&mut self, token: Option<&str>, query: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let mut request = self.client(http::Method::GET, &api(&format!("/users?{}", query)));
+        if let Some(token) = token {
+            request.header(AUTH_TOKEN_HEADER, token);
+        }
+        let request = request.timeout(Duration::from_secs(10)).finish().unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn legacy_search_users(&mut self, search: SearchWithKeyword) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api("/users/search"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .timeout(Duration::from_secs(10))
+            .json(search)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+}
+
+pub trait AdminWebMethods {
+    fn admin_list_users(&mut self, token: Option<&str>, query: &str) -> ClientResponse;
+    fn admin_suspend_user(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn admin_restore_user(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn admin_set_user_role(
+        &mut self,
+        token: &str,
+        id: i64,
+        role: models::UserRole,
+    ) -> ClientResponse;
+}
+
+impl AdminWebMethods for TestServer {
+    fn admin_list_users(&mut self, token: Option<&str>, query: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let mut request = self.client(http::Method::GET, &api(&format!("/admin/users?{}", query)));
+        if let Some(token) = token {
+            request.header(AUTH_TOKEN_HEADER, token);
+        }
+        let request = request.timeout(Duration::from_secs(10)).finish().unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn admin_suspend_user(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::POST,
+                &api(&format!("/admin/users/{}/suspend", id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn admin_restore_user(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::POST,
+                &api(&format!("/admin/users/{}/restore", id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn admin_set_user_role(
+        &mut self,
+        token: &str,
+        id: i64,
+        role: models::UserRole,
+    ) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::PUT,
+                &api(&format!("/admin/users/{}/role", id)),
+            )
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(SetRoleInput { role })
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+}
+
+pub trait BandsWebMethods {
+    fn create_band(&mut self, token: &str, new_band: NewBandInput) -> ClientResponse;
+    fn test_create_new_band(&mut self, token: &str, new_band: NewBandInput) -> models::Band;
+    fn list_bands(&mut self, member: Option<i64>) -> ClientResponse;
+    fn get_band(&mut self, id: i64) -> ClientResponse;
+    fn update_band(&mut self, token: &str, id: i64, update: UpdateBandInput) -> ClientResponse;
+    fn delete_band(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn list_band_members(&mut self, id: i64) -> ClientResponse;
+    fn set_band_member(
+        &mut self,
+        token: &str,
+        id: i64,
+        user_id: i64,
+        role: models::BandRole,
+    ) -> ClientResponse;
+    fn remove_band_member(&mut self, token: &str, id: i64, user_id: i64) -> ClientResponse;
+}
+
+impl BandsWebMethods for TestServer {
+    fn create_band(&mut self, token: &str, new_band: NewBandInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api("/bands"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(new_band)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn test_create_new_band(&mut self, token: &str, new_band: NewBandInput) -> models::Band {
+        let response = self.create_band(token, new_band);
+        assert!(response.status().is_success());
+
+        let bytes = self.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn list_bands(&mut self, member: Option<i64>) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let path = match member {
+            Some(member) => format!("/bands?member={}", member),
+            None => "/bands".to_string(),
+        };
+        let request = self
+            .client(http::Method::GET, &api(&path))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn get_band(&mut self, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::GET, &api(&format!("/bands/{}", id)))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn update_band(&mut self, token: &str, id: i64, update: UpdateBandInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::PATCH, &api(&format!("/bands/{}", id)))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(update)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn delete_band(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::DELETE, &api(&format!("/bands/{}", id)))
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn list_band_members(&mut self, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::GET, &api(&format!("/bands/{}/members", id)))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn set_band_member(
+        &mut self,
+        token: &str,
+        id: i64,
+        user_id: i64,
+        role: models::BandRole,
+    ) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::PUT,
+                &api(&format!("/bands/{}/members/{}", id, user_id)),
+            )
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(SetBandMemberInput { role })
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn remove_band_member(&mut self, token: &str, id: i64, user_id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::DELETE,
+                &api(&format!("/bands/{}/members/{}", id, user_id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+}
+
+pub trait EventsWebMethods {
+    fn create_event(&mut self, token: &str, new_event: NewEventInput) -> ClientResponse;
+    fn test_create_new_event(&mut self, token: &str, new_event: NewEventInput) -> models::Event;
+    fn list_upcoming_events(&mut self, user: Option<i64>, band: Option<i64>) -> ClientResponse;
+    fn get_event(&mut self, id: i64) -> ClientResponse;
+    fn update_event(&mut self, token: &str, id: i64, update: UpdateEventInput) -> ClientResponse;
+    fn cancel_event(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn set_rsvp(&mut self, token: &str, id: i64, status: models::RsvpStatus) -> ClientResponse;
+    fn list_attendees(&mut self, id: i64) -> ClientResponse;
+}
+
+impl EventsWebMethods for TestServer {
+    fn create_event(&mut self, token: &str, new_event: NewEventInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api("/events"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(new_event)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn test_create_new_event(&mut self, token: &str, new_event: NewEventInput) -> models::Event {
+        let response = self.create_event(token, new_event);
+        assert!(response.status().is_success());
+
+        let bytes = self.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn list_upcoming_events(&mut self, user: Option<i64>, band: Option<i64>) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let mut filters = Vec::new();
+        if let Some(user) = user {
+            filters.push(format!("user={}", user));
+        }
+        if let Some(band) = band {
+            filters.push(format!("band={}", band));
+        }
+        let path = format!("/events?{}", filters.join("&"));
+
+        let request = self
+            .client(http::Method::GET, &api(&path))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn get_event(&mut self, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::GET, &api(&format!("/events/{}", id)))
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn update_event(&mut self, token: &str, id: i64, update: UpdateEventInput) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::PATCH, &api(&format!("/events/{}", id)))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(update)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn cancel_event(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api(&format!("/events/{}/cancel", id)))
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn set_rsvp(&mut self, token: &str, id: i64, status: models::RsvpStatus) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::PUT, &api(&format!("/events/{}/rsvp", id)))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(RsvpInput { status })
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn list_attendees(&mut self, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::GET,
+                &api(&format!("/events/{}/attendees", id)),
+            )
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+}
+
+pub trait InvitationsWebMethods {
+    fn create_invitation(
+        &mut self,
+        token: &str,
+        new_invitation: NewInvitationInput,
+    ) -> ClientResponse;
+    fn test_create_new_invitation(
+        &mut self,
+        token: &str,
+        new_invitation: NewInvitationInput,
+    ) -> models::Invitation;
+    fn list_inbox(&mut self, token: &str) -> ClientResponse;
+    fn accept_invitation(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn decline_invitation(&mut self, token: &str, id: i64) -> ClientResponse;
+    fn revoke_invitation(&mut self, token: &str, id: i64) -> ClientResponse;
+}
+
+impl InvitationsWebMethods for TestServer {
+    fn create_invitation(
+        &mut self,
+        token: &str,
+        new_invitation: NewInvitationInput,
+    ) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::POST, &api("/invitations"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .json(new_invitation)
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn test_create_new_invitation(
+        &mut self,
+        token: &str,
+        new_invitation: NewInvitationInput,
+    ) -> models::Invitation {
+        let response = self.create_invitation(token, new_invitation);
+        assert!(response.status().is_success());
+
+        let bytes = self.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn list_inbox(&mut self, token: &str) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(http::Method::GET, &api("/invitations/inbox"))
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn accept_invitation(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::POST,
+                &api(&format!("/invitations/{}/accept", id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn decline_invitation(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::POST,
+                &api(&format!("/invitations/{}/decline", id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+
+    fn revoke_invitation(&mut self, token: &str, id: i64) -> ClientResponse {
+        use actix_web::http;
+        use std::time::Duration;
+
+        let request = self
+            .client(
+                http::Method::POST,
+                &api(&format!("/invitations/{}/revoke", id)),
+            )
+            .header(AUTH_TOKEN_HEADER, token)
+            .timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap();
+
+        self.execute(request.send()).unwrap()
+    }
+}
@@ -0,0 +1,123 @@
+//! Native https
+//!
+//! With `[server.tls]` the server terminates TLS itself through rustls,
+//! otherwise it speaks plain http and a proxy in front of it terminates TLS.
+use std::fmt;
+use std::fs;
+use std::io;
+
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{NoClientAuth, ServerConfig, TLSError};
+
+use config::TlsConfig;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Read(String, io::Error),
+    NoCertificate(String),
+    NoPrivateKey(String),
+    /// rustls refused the certificate chain or the key
+    Rejected(TLSError),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsError::Read(path, error) => write!(f, "can't read {}: {}", path, error),
+            TlsError::NoCertificate(path) => write!(f, "no PEM certificate in {}", path),
+            TlsError::NoPrivateKey(path) => {
+                write!(f, "no PEM PKCS#8 or RSA private key in {}", path)
+            }
+            TlsError::Rejected(error) => write!(f, "invalid certificate or key: {:?}", error),
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, TlsError> {
+    fs::read(path).map_err(|error| TlsError::Read(path.to_string(), error))
+}
+
+/// Server side TLS settings with the certificate chain and the first private
+/// key of the files of `config`
+pub fn server_config(config: &TlsConfig) -> Result<ServerConfig, TlsError> {
+    let chain = read(&config.certificate)?;
+    let chain = certs(&mut chain.as_slice())
+        .ok()
+        .filter(|chain| !chain.is_empty())
+        .ok_or_else(|| TlsError::NoCertificate(config.certificate.clone()))?;
+
+    let key = read(&config.private_key)?;
+    let key = pkcs8_private_keys(&mut key.as_slice())
+        .ok()
+        .filter(|keys| !keys.is_empty())
+        .or_else(|| rsa_private_keys(&mut key.as_slice()).ok())
+        .and_then(|keys| keys.into_iter().next())
+        .ok_or_else(|| TlsError::NoPrivateKey(config.private_key.clone()))?;
+
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config
+        .set_single_cert(chain, key)
+        .map_err(TlsError::Rejected)?;
+
+    Ok(server_config)
+}
+
+#[cfg(test)]
+mod tls_tests {
+    extern crate rcgen;
+
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// A file of this process in the temporary directory
+    fn write_temp(name: &str, content: &str) -> String {
+        let path = env::temp_dir().join(format!("peers_tls_{}_{}", process::id(), name));
+        fs::write(&path, content).unwrap();
+
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_server_config() {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]);
+        let certificate = write_temp("cert.pem", &generated.serialize_pem());
+        let private_key = write_temp("key.pem", &generated.serialize_private_key_pem());
+
+        let config = TlsConfig {
+            certificate: certificate.clone(),
+            private_key: private_key.clone(),
+        };
+        assert!(server_config(&config).is_ok());
+
+        let swapped = TlsConfig {
+            certificate: private_key.clone(),
+            private_key: certificate.clone(),
+        };
+        match server_config(&swapped) {
+            Err(TlsError::NoCertificate(path)) => assert_eq!(path, private_key),
+            _ => panic!("a key was taken for a certificate"),
+        }
+
+        let no_key = TlsConfig {
+            certificate: certificate.clone(),
+            private_key: certificate.clone(),
+        };
+        match server_config(&no_key) {
+            Err(TlsError::NoPrivateKey(_)) => (),
+            _ => panic!("a certificate was taken for a key"),
+        }
+
+        let missing = TlsConfig {
+            certificate: format!("{}.missing", certificate),
+            private_key: private_key.clone(),
+        };
+        match server_config(&missing) {
+            Err(TlsError::Read(..)) => (),
+            _ => panic!("a missing file was read"),
+        }
+
+        fs::remove_file(certificate).unwrap();
+        fs::remove_file(private_key).unwrap();
+    }
+}
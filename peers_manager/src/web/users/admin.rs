@@ -0,0 +1,313 @@
+//! Account management reserved to admins
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Path, Query, State};
+
+use super::super::authorization::{Admin, UserView};
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use super::search::UserPage;
+use db::accounts::{SetRole, SetSuspended};
+use db::search::{SearchSort, SearchUsers};
+use models::UserRole;
+
+/// Filters of the admin listing, suspended accounts are listed too unless
+/// `suspended` says otherwise
+#[derive(Deserialize, Serialize, Default)]
+pub struct ListUsersQuery {
+    /// Words of the name or about, or an exact email
+    pub query: Option<String>,
+    pub role: Option<UserRole>,
+    pub suspended: Option<bool>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub with_total: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SetRoleInput {
+    pub role: UserRole,
+}
+
+/// Page of all the accounts, oldest first
+///
+/// `GET /admin/users?query=&role=&suspended=&limit=&cursor=&with_total=`
+pub fn list_users(
+    (admin, filters, state, request_id): (Admin, Query<ListUsersQuery>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    let filters = filters.into_inner();
+    let search = SearchUsers {
+        query: filters.query,
+        limit: filters.limit,
+        cursor: filters.cursor,
+        sort: SearchSort::Id,
+        with_total: filters.with_total,
+        include_suspended: filters.suspended != Some(false),
+        only_suspended: filters.suspended == Some(true),
+        role: filters.role,
+        match_email: true,
+        ..SearchUsers::default()
+    };
+
+    // send async `SearchUsers` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, search)
+        .then(api_result)
+        .map(move |results| HttpResponse::Ok().json(UserPage::new(results, Some(&admin.0))))
+        .from_err()
+        .responder()
+}
+
+/// Suspends an account and closes its sessions
+pub fn suspend_user(
+    (admin, id, state, request_id): (Admin, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    set_suspended(admin, id.into_inner(), true, state, request_id)
+}
+
+/// Lets a suspended account log in again
+pub fn restore_user(
+    (admin, id, state, request_id): (Admin, Path<i64>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    set_suspended(admin, id.into_inner(), false, state, request_id)
+}
+
+fn set_suspended(
+    admin: Admin,
+    id: i64,
+    suspended: bool,
+    state: State<AppState>,
+    request_id: RequestId,
+) -> FutureResponse<HttpResponse> {
+    // an admin locking itself out could leave the site without any
+    if id == admin.0.id {
+        return fail(ApiError::NotAllowed("admins can't suspend themselves"));
+    }
+
+    // send async `SetSuspended` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, SetSuspended { id, suspended })
+        .then(api_result)
+        .and_then(|user| user.ok_or(ApiError::UserNotFound))
+        .map(move |user| HttpResponse::Ok().json(UserView::new(user, Some(&admin.0))))
+        .from_err()
+        .responder()
+}
+
+/// Gives a user a role on the whole site
+pub fn set_user_role(
+    (admin, id, input, state, request_id): (
+        Admin,
+        Path<i64>,
+        Json<SetRoleInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let id = id.into_inner();
+    if id == admin.0.id {
+        return fail(ApiError::NotAllowed("admins can't change their own role"));
+    }
+
+    // send async `SetRole` message to a `UsersExecutor`
+    state
+        .users
+        .send(
+            &request_id,
+            SetRole {
+                id,
+                role: input.role,
+            },
+        )
+        .then(api_result)
+        .and_then(|user| user.ok_or(ApiError::UserNotFound))
+        .map(move |user| HttpResponse::Ok().json(UserView::new(user, Some(&admin.0))))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod admin_tests {
+
+    use super::*;
+    use actix_web::client::ClientResponse;
+    use actix_web::test::TestServer;
+    use actix_web::HttpMessage;
+    use db::memory::MemoryUserRepository;
+    use db::repository::UserRepository;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    fn create_admin(
+        srv: &mut TestServer,
+        repository: &mut MemoryUserRepository,
+        email: &str,
+    ) -> (String, i64) {
+        let (token, id) = create_member(srv, email);
+        repository
+            .set_role(SetRole {
+                id,
+                role: UserRole::Admin,
+            })
+            .unwrap();
+
+        (token, id)
+    }
+
+    fn body<T: serde::de::DeserializeOwned>(srv: &mut TestServer, response: ClientResponse) -> T {
+        let bytes = srv.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_admin_only() {
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+
+        let (token, id) = create_member(&mut srv, "test_admin_1@gmail.com");
+
+        let response = srv.admin_list_users(None, "");
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = srv.admin_list_users(Some(&token), "");
+        assert_eq!(response.status().as_u16(), 403);
+        let error_data: ErrorBody = body(&mut srv, response);
+        assert_eq!(error_data.code, "not_allowed");
+
+        let response = srv.admin_suspend_user(&token, id + 1);
+        assert_eq!(response.status().as_u16(), 403);
+
+        // moderators aren't admins either
+        repository
+            .set_role(SetRole {
+                id,
+                role: UserRole::Moderator,
+            })
+            .unwrap();
+        let response = srv.admin_list_users(Some(&token), "");
+        assert_eq!(response.status().as_u16(), 403);
+    }
+
+    #[test]
+    fn test_suspend_and_restore() {
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+
+        let (admin_token, admin_id) =
+            create_admin(&mut srv, &mut repository, "test_admin_2@gmail.com");
+        let (token, id) = create_member(&mut srv, "test_admin_3@gmail.com");
+
+        let response = srv.admin_suspend_user(&admin_token, id);
+        assert!(response.status().is_success());
+        let user: UserView = body(&mut srv, response);
+        assert!(user.suspended_at.is_some());
+
+        // the session is closed, logging in and the profile are refused
+        let response = srv.current_user(Some(&token));
+        assert_eq!(response.status().as_u16(), 401);
+        let response = srv.login_user("test_admin_3@gmail.com", "password 1");
+        assert_eq!(response.status().as_u16(), 403);
+        let error_data: ErrorBody = body(&mut srv, response);
+        assert_eq!(error_data.code, "account_suspended");
+        let response = srv.get_user(id);
+        assert_eq!(response.status().as_u16(), 404);
+
+        let response = srv.admin_list_users(Some(&admin_token), "suspended=true");
+        let page: UserPage = body(&mut srv, response);
+        assert_eq!(page.users.len(), 1);
+        assert_eq!(page.users[0].id, id);
+        assert_eq!(
+            page.users[0].email,
+            Some("test_admin_3@gmail.com".to_string())
+        );
+
+        let response = srv.admin_restore_user(&admin_token, id);
+        assert!(response.status().is_success());
+        let user: UserView = body(&mut srv, response);
+        assert!(user.suspended_at.is_none());
+
+        let response = srv.login_user("test_admin_3@gmail.com", "password 1");
+        assert!(response.status().is_success());
+
+        let response = srv.admin_suspend_user(&admin_token, admin_id);
+        assert_eq!(response.status().as_u16(), 403);
+
+        let response = srv.admin_suspend_user(&admin_token, id + 1_000);
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[test]
+    fn test_list_and_set_role() {
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+
+        let (admin_token, admin_id) =
+            create_admin(&mut srv, &mut repository, "test_admin_4@gmail.com");
+        let (_, id) = create_member(&mut srv, "test_admin_5@gmail.com");
+
+        let response = srv.admin_list_users(Some(&admin_token), "with_total=true");
+        let page: UserPage = body(&mut srv, response);
+        assert_eq!(page.total, Some(2));
+        let ids = page.users.iter().map(|user| user.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![admin_id, id]);
+
+        let response = srv.admin_set_user_role(&admin_token, id, UserRole::Moderator);
+        assert!(response.status().is_success());
+        let user: UserView = body(&mut srv, response);
+        assert_eq!(user.role, UserRole::Moderator);
+
+        let response = srv.admin_list_users(Some(&admin_token), "role=moderator");
+        let page: UserPage = body(&mut srv, response);
+        let ids = page.users.iter().map(|user| user.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![id]);
+
+        let response = srv.admin_set_user_role(&admin_token, admin_id, UserRole::User);
+        assert_eq!(response.status().as_u16(), 403);
+    }
+
+    #[test]
+    fn test_search_visibility() {
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+
+        let (admin_token, _) = create_admin(&mut srv, &mut repository, "test_admin_6@gmail.com");
+        let (token, _) = create_member(&mut srv, "test_admin_7@gmail.com");
+        let (moderator_token, moderator_id) = create_member(&mut srv, "test_admin_8@gmail.com");
+        repository
+            .set_role(SetRole {
+                id: moderator_id,
+                role: UserRole::Moderator,
+            })
+            .unwrap();
+
+        let emails = |srv: &mut TestServer, token: Option<&str>| {
+            let response = srv.search_users_as(token, "sort=id");
+            let page: UserPage = body(srv, response);
+            page.users
+                .into_iter()
+                .map(|user| user.email)
+                .collect::<Vec<_>>()
+        };
+
+        // users only see their own email, admins see all of them
+        assert_eq!(emails(&mut srv, None), vec![None, None, None]);
+        assert_eq!(
+            emails(&mut srv, Some(&token)),
+            vec![None, Some("test_admin_7@gmail.com".to_string()), None]
+        );
+        assert!(emails(&mut srv, Some(&admin_token))
+            .iter()
+            .all(|email| email.is_some()));
+
+        // suspended users are only found by moderators and admins
+        let (_, suspended_id) = create_member(&mut srv, "test_admin_9@gmail.com");
+        srv.admin_suspend_user(&admin_token, suspended_id);
+        assert_eq!(emails(&mut srv, Some(&token)).len(), 3);
+        assert_eq!(emails(&mut srv, Some(&moderator_token)).len(), 4);
+    }
+}
@@ -3,113 +3,147 @@ use super::super::app::AppState;
 use futures::Future;
 
 use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, State};
-use http::StatusCode;
 
-use super::super::super::db::users::{CreateUser, CreateUserError, LoginResponse};
+use super::super::super::db::users::CreateUser;
+use super::super::auth::respond_with_session;
+use super::super::authorization::UserView;
+use super::super::error::{fail, ApiError};
+use super::super::request_id::RequestId;
+use super::verify::send_verification_email;
 
 #[derive(Deserialize, Serialize)]
 pub struct NewUserInput {
     pub name: String,
     pub email: String,
     pub about: String,
+    pub password: String,
 }
 
-pub enum CreateUserErrorCode {
-    UserAlreadyExists,
-    InvalidEmail,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct CreateUserHttpError {
-    pub code: u32,
-    details: String,
-}
+/// Passwords shorter than this are rejected on registration
+pub const MIN_PASSWORD_LENGTH: usize = 8;
 
-use super::super::super::web::email_validator::{ValidateEmail, ValidateExecutor};
-use db::users::DbExecutor;
+use super::super::super::web::email_validator::{EmailVerdict, ValidateEmail, ValidateExecutor};
+use db::repository::UsersExecutor;
+use mail::MailExecutor;
+use web::metrics::MeteredAddr;
 
 pub fn create_user(
-    (new_user, state): (Json<NewUserInput>, State<AppState>),
+    (new_user, state, request_id): (Json<NewUserInput>, State<AppState>, RequestId),
 ) -> FutureResponse<HttpResponse> {
-    let db = state.db.clone();
+    let users = state.users.clone();
+    let mailer = state.mailer.clone();
+    let verification = state.verification.get();
+    let link_base_url = verification.link_base_url.clone();
+    let required_for_login = verification.required_for_login;
 
-    Box::new(
-        validate_email_request(state.email_validator.clone(), &new_user.email).and_then(
-            move |email_is_valid| {
-                if email_is_valid {
-                    return db_create_user(db, new_user);
-                }
-                let response = HttpResponse::new(StatusCode::BAD_REQUEST);
-                let mut builder = response.into_builder();
+    if new_user.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return fail(ApiError::WeakPassword);
+    }
 
-                let error = CreateUserHttpError {
-                    code: CreateUserErrorCode::InvalidEmail as u32,
-                    details: "email is not valid".to_string(),
-                };
+    let email = state.canonicalization.canonicalize(&new_user.email);
+    if let Err(error) = state.rate_limiter.check_email("/users/create_user", &email) {
+        return fail(error);
+    }
 
-                Box::new(futures::future::ok(builder.json(error)))
-            },
-        ),
+    Box::new(
+        validate_email_request(state.email_validator.clone(), &request_id, &new_user.email)
+            .and_then(move |verdict| -> FutureResponse<HttpResponse> {
+                match verdict {
+                    EmailVerdict::Valid(email) => db_create_user(
+                        users,
+                        request_id,
+                        mailer,
+                        link_base_url,
+                        required_for_login,
+                        new_user,
+                        email,
+                    ),
+                    refused => fail(ApiError::InvalidEmail(refused)),
+                }
+            }),
     )
 }
 
-fn validate_email_request(
-    validator: actix::Addr<ValidateExecutor>,
+pub fn validate_email_request(
+    validator: MeteredAddr<ValidateExecutor>,
+    request_id: &RequestId,
     email: &str,
-) -> impl Future<Item = bool, Error = actix_web::error::Error> {
+) -> impl Future<Item = EmailVerdict, Error = actix_web::error::Error> {
     let validate_email = ValidateEmail {
         email: email.to_string(),
     };
-    validator.send(validate_email).from_err()
+    validator
+        .send(request_id, validate_email)
+        .map_err(ApiError::from)
+        .from_err()
 }
 
-/// Async request handler
+/// Async request handler, `email` is the canonical form of the new user's.
+/// Users which may not log in before verifying their email get no session,
+/// only their profile with `201 Created`.
 fn db_create_user(
-    db: actix::Addr<DbExecutor>,
+    users: MeteredAddr<UsersExecutor>,
+    request_id: RequestId,
+    mailer: actix::Addr<MailExecutor>,
+    link_base_url: String,
+    required_for_login: bool,
     new_user: Json<NewUserInput>,
+    email: String,
 ) -> FutureResponse<HttpResponse> {
-    // send async `CreateUser` message to a `DbExecutor`
-    db.send(CreateUser {
-        name: new_user.name.clone(),
-        email: new_user.email.clone(),
-        about: new_user.about.clone(),
-    })
-    .and_then(|res| match res {
-        Ok(user) => {
-            let response = LoginResponse { token: user.id };
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(error) => Ok(match error {
-            CreateUserError::UserAlreadyExists => {
-                let response = HttpResponse::new(StatusCode::BAD_REQUEST);
-                let mut builder = response.into_builder();
-
-                let error = CreateUserHttpError {
-                    code: CreateUserErrorCode::UserAlreadyExists as u32,
-                    details: "user already exists".to_string(),
-                };
-
-                builder.json(error)
+    let session_users = users.clone();
+
+    // send async `CreateUser` message to a `UsersExecutor`
+    users
+        .send(
+            &request_id,
+            CreateUser {
+                name: new_user.name.clone(),
+                email,
+                about: new_user.about.clone(),
+                password: new_user.password.clone(),
+            },
+        )
+        .map_err(ApiError::from)
+        .from_err()
+        .and_then(move |res| -> FutureResponse<HttpResponse> {
+            match res {
+                Ok(user) => Box::new(
+                    send_verification_email(
+                        session_users.clone(),
+                        request_id.clone(),
+                        mailer,
+                        link_base_url,
+                        &user,
+                    )
+                    .then(move |sent| -> FutureResponse<HttpResponse> {
+                        if let Err(error) = sent {
+                            error!("Failed to send verification email: {}", error);
+                        }
+                        if required_for_login {
+                            return Box::new(futures::future::ok(
+                                HttpResponse::Created().json(UserView::own(user)),
+                            ));
+                        }
+                        respond_with_session(session_users, &request_id, user.id)
+                    }),
+                ),
+                Err(error) => fail(error.into()),
             }
-            CreateUserError::DbError(_) => HttpResponse::InternalServerError().into(),
-        }),
-    })
-    .from_err()
-    .responder()
+        })
+        .responder()
 }
 
 #[cfg(test)]
 mod create_user_tests {
 
-    use super::super::tests_tools::*;
     use super::*;
     use actix_web::HttpMessage;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
 
     #[test]
     fn test_create_user() {
-        db_clear_users();
-
-        let mut srv = create_test_server();
+        let mut srv = create_memory_test_server();
 
         let email = "test_create@gmail.com";
 
@@ -117,6 +151,7 @@ mod create_user_tests {
             name: "name 1".to_string(),
             email: email.to_string(),
             about: "about 1".to_string(),
+            password: "password 1".to_string(),
         };
         srv.test_create_new_user(new_user);
 
@@ -124,36 +159,104 @@ mod create_user_tests {
             name: "name 2".to_string(),
             email: email.to_string(),
             about: "about 2".to_string(),
+            password: "password 2".to_string(),
         };
 
         let response = srv.create_user(new_user);
         let bytes = srv.execute(response.body()).unwrap();
-        let error_data: CreateUserHttpError = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(
-            error_data.code,
-            CreateUserErrorCode::UserAlreadyExists as u32
-        );
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "user_already_exists");
 
         assert!(response.status().is_client_error());
     }
 
     #[test]
     fn test_invalid_email() {
-        db_clear_users();
-
-        let mut srv = create_test_server();
+        let mut srv = create_memory_test_server();
 
         let new_user = NewUserInput {
             name: "name 1".to_string(),
             email: "email 1".to_string(),
             about: "about 1".to_string(),
+            password: "password 1".to_string(),
         };
 
         let response = srv.create_user(new_user);
         let bytes = srv.execute(response.body()).unwrap();
-        let error_data: CreateUserHttpError = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(error_data.code, CreateUserErrorCode::InvalidEmail as u32);
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_email");
 
         assert!(response.status().is_client_error());
     }
+
+    #[test]
+    fn test_weak_password() {
+        let mut srv = create_memory_test_server();
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: "test_weak_password@gmail.com".to_string(),
+            about: "about 1".to_string(),
+            password: "short".to_string(),
+        };
+
+        let response = srv.create_user(new_user);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "weak_password");
+
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_disposable_email() {
+        let mut srv = create_memory_test_server();
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: "test_disposable@mailinator.com".to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+
+        let response = srv.create_user(new_user);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_email");
+        assert_eq!(
+            error_data.details.as_ref().map(|details| details.as_str()),
+            EmailVerdict::Disposable.reason()
+        );
+        assert_eq!(
+            error_data.message,
+            EmailVerdict::Disposable.refusal().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_canonical_email() {
+        let mut srv = create_memory_test_server();
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: "Test_Canonical@GMail.com".to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        srv.test_create_new_user(new_user);
+
+        let new_user = NewUserInput {
+            name: "name 2".to_string(),
+            email: "Test_Canonical@gmail.COM".to_string(),
+            about: "about 2".to_string(),
+            password: "password 2".to_string(),
+        };
+        let response = srv.create_user(new_user);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "user_already_exists");
+
+        let response = srv.login_user("Test_Canonical@GMAIL.com", "password 1");
+        assert!(response.status().is_success());
+    }
 }
@@ -1,47 +1,33 @@
 use super::super::app::AppState;
 
 use futures::Future;
-use http::StatusCode;
 
 use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, State};
 
-use super::super::super::db::users::{LoginError, LoginWithEmail};
-
-pub enum LoginErrorCode {
-    InvalidCredentials,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct LoginHttpError {
-    pub code: u32,
-    details: String,
-}
+use super::super::super::db::users::LoginWithEmail;
+use super::super::error::{api_result, fail};
+use super::super::request_id::RequestId;
 
 pub fn login_user(
-    (login, state): (Json<LoginWithEmail>, State<AppState>),
+    (login, state, request_id): (Json<LoginWithEmail>, State<AppState>, RequestId),
 ) -> FutureResponse<HttpResponse> {
-    // send async `LoginWithEmail` message to a `DbExecutor`
+    let mut login = login.into_inner();
+    let email = state.canonicalization.canonicalize(&login.email);
+    login.typed_email = Some(login.email.clone()).filter(|typed| *typed != email);
+    login.email = email;
+    login.require_verified_email = state.verification.get().required_for_login;
+
+    if let Err(error) = state.rate_limiter.check_email("/users/login", &login.email) {
+        return fail(error);
+    }
+
+    // send async `LoginWithEmail` message to a `UsersExecutor`
     state
-        .db
-        .send(login.into_inner())
+        .users
+        .send(&request_id, login)
+        .then(api_result)
+        .map(|token| HttpResponse::Ok().json(token))
         .from_err()
-        .and_then(|res| match res {
-            Ok(token) => Ok(HttpResponse::Ok().json(token)),
-            Err(error) => Ok(match error {
-                LoginError::InvalidCredentials => {
-                    let response = HttpResponse::new(StatusCode::BAD_REQUEST);
-                    let mut builder = response.into_builder();
-
-                    let error = LoginHttpError {
-                        code: LoginErrorCode::InvalidCredentials as u32,
-                        details: "invalid credentials".to_string(),
-                    };
-
-                    builder.json(error)
-                }
-                LoginError::DbError(_) => HttpResponse::InternalServerError().into(),
-            }),
-        })
         .responder()
 }
 
@@ -49,38 +35,15 @@ pub fn login_user(
 mod create_user_tests {
 
     use super::super::create::*;
-    use super::super::tests_tools::*;
-    use super::*;
-    use actix_web::client::ClientResponse;
-    use actix_web::test::TestServer;
-
-    fn login_user(srv: &mut TestServer, email: &str) -> ClientResponse {
-        use super::super::super::super::db::users::LoginWithEmail;
-        use actix_web::http;
-        use std::time::Duration;
-
-        let login = LoginWithEmail {
-            email: email.to_string(),
-        };
-
-        let request = srv
-            .client(http::Method::POST, "/users/login")
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .timeout(Duration::from_secs(10))
-            .json(login)
-            .unwrap();
-
-        srv.execute(request.send()).unwrap()
-    }
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
 
     #[test]
     fn test_succees_login() {
         use super::super::super::super::db::users::LoginResponse;
         use actix_web::HttpMessage;
 
-        db_clear_users();
-
-        let mut srv = create_test_server();
+        let mut srv = create_memory_test_server();
 
         let email = "test_login_1@gmail.com";
 
@@ -88,32 +51,90 @@ mod create_user_tests {
             name: "name 1".to_string(),
             email: email.to_string(),
             about: "about 1".to_string(),
+            password: "password 1".to_string(),
         };
 
         let new_user_token = srv.test_create_new_user(new_user);
 
-        let response = login_user(&mut srv, email);
+        let response = srv.login_user(email, "password 1");
         let bytes = srv.execute(response.body()).unwrap();
         let token_data: LoginResponse = serde_json::from_slice(&bytes).unwrap();
         let token = token_data.token;
-        assert_eq!(token, new_user_token);
+        assert!(!token.is_empty());
+        assert_ne!(token, new_user_token);
+
+        let response = srv.current_user(Some(&token));
+        assert!(response.status().is_success());
     }
 
     #[test]
     fn test_failed_login() {
         use actix_web::HttpMessage;
 
-        db_clear_users();
-
-        let mut srv = create_test_server();
+        let mut srv = create_memory_test_server();
 
         let email = "test_login_10@gmail.com";
 
-        let response = login_user(&mut srv, email);
+        let response = srv.login_user(email, "password 1");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_credentials");
+
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_wrong_password_login() {
+        use actix_web::HttpMessage;
+
+        let mut srv = create_memory_test_server();
+
+        let email = "test_login_20@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+
+        srv.test_create_new_user(new_user);
+
+        let response = srv.login_user(email, "password 2");
         let bytes = srv.execute(response.body()).unwrap();
-        let error_data: LoginHttpError = serde_json::from_slice(&bytes).unwrap();
-        assert_eq!(error_data.code, LoginErrorCode::InvalidCredentials as u32);
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_credentials");
 
         assert!(response.status().is_client_error());
     }
+
+    #[test]
+    fn test_login_with_email_registered_before_canonicalization() {
+        use actix_web::HttpMessage;
+        use db::memory::MemoryUserRepository;
+        use db::repository::UserRepository;
+        use db::users::CreateUser;
+
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+
+        // the domain is lower-cased now, the account kept its email as typed
+        let email = "Test_Login_30@GMAIL.com";
+        repository
+            .create_user(CreateUser {
+                name: "name 1".to_string(),
+                email: email.to_string(),
+                about: "about 1".to_string(),
+                password: "password 1".to_string(),
+            })
+            .unwrap();
+
+        let response = srv.login_user(email, "password 1");
+        assert!(response.status().is_success());
+
+        let response = srv.login_user(email, "password 2");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_credentials");
+    }
 }
@@ -1,5 +1,7 @@
+pub mod admin;
 pub mod create;
 pub mod login;
+pub mod password_reset;
+pub mod profile;
 pub mod search;
-#[cfg(test)]
-pub mod tests_tools;
+pub mod verify;
@@ -0,0 +1,264 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, State};
+
+use super::super::super::db::password_reset::{RequestPasswordReset, ResetPassword};
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use super::super::routes::API_PREFIX;
+use super::create::MIN_PASSWORD_LENGTH;
+use mail::{Email, SendMail};
+
+/// Page of the link mailed by `request_password_reset`, it posts the new
+/// password with the token of its query string to `reset_password`
+const RESET_PASSWORD_PAGE: &str = include_str!("../../../data/reset_password.html");
+
+/// Mails a password reset link, responds the same whether the email is
+/// registered or not, too many requests included.
+pub fn request_password_reset(
+    (request, state, request_id): (Json<RequestPasswordReset>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    let mailer = state.mailer.clone();
+    let link_base_url = state.verification.get().link_base_url.clone();
+    let mut request = request.into_inner();
+    let email = state.canonicalization.canonicalize(&request.email);
+    request.typed_email = Some(request.email.clone()).filter(|typed| *typed != email);
+    request.email = email;
+
+    if let Err(error) = state
+        .rate_limiter
+        .check_email("/users/request_password_reset", &request.email)
+    {
+        return fail(error);
+    }
+
+    // send async `RequestPasswordReset` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, request)
+        .then(api_result)
+        .from_err()
+        .and_then(move |reset| -> FutureResponse<HttpResponse> {
+            match reset {
+                Some(reset) => {
+                    let email = Email {
+                        to: reset.email,
+                        subject: "Reset your password".to_string(),
+                        body: format!(
+                            "Somebody asked to reset your password, if it was you open the link below:\n\n{}{}/users/reset_password?token={}",
+                            link_base_url, API_PREFIX, reset.token.token
+                        ),
+                    };
+
+                    mailer
+                        .send(SendMail(email))
+                        .then(api_result)
+                        .map(|_| HttpResponse::Ok().finish())
+                        .from_err()
+                        .responder()
+                }
+                None => Box::new(futures::future::ok(HttpResponse::Ok().finish())),
+            }
+        })
+        .responder()
+}
+
+/// Form setting the new password, opened from the reset email
+pub fn reset_password_page(_state: State<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(RESET_PASSWORD_PAGE)
+}
+
+/// Sets a new password using the token from the reset email, all sessions of
+/// the user are invalidated.
+pub fn reset_password(
+    (reset, state, request_id): (Json<ResetPassword>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    if reset.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return fail(ApiError::WeakPassword);
+    }
+
+    // send async `ResetPassword` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, reset.into_inner())
+        .then(api_result)
+        .map(|()| HttpResponse::Ok().finish())
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod password_reset_tests {
+
+    use super::super::super::app::VerificationSettings;
+    use super::super::create::*;
+    use actix_web::HttpMessage;
+    use db::password_reset::MAX_PASSWORD_RESETS_PER_HOUR;
+    use mail::memory::Outbox;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    fn reset_token(outbox: &Outbox, email: &str) -> String {
+        let outbox = outbox.lock().unwrap();
+        let sent = outbox
+            .iter()
+            .rev()
+            .find(|sent| sent.to == email && sent.body.contains("reset_password"))
+            .expect("password reset email was not sent");
+        let start = sent.body.find("token=").unwrap() + "token=".len();
+        sent.body[start..].trim().to_string()
+    }
+
+    #[test]
+    fn test_reset_password() {
+        let outbox = Outbox::default();
+        let mut srv =
+            create_memory_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let email = "test_reset_1@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        let session_token = srv.test_create_new_user(new_user);
+
+        let response = srv.request_password_reset(email);
+        assert!(response.status().is_success());
+
+        let token = reset_token(&outbox, email);
+        let response = srv.reset_password(&token, "password 2");
+        assert!(response.status().is_success());
+
+        // existing sessions are gone, the new password works, the old doesn't
+        let response = srv.current_user(Some(&session_token));
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = srv.login_user(email, "password 1");
+        assert!(response.status().is_client_error());
+
+        let response = srv.login_user(email, "password 2");
+        assert!(response.status().is_success());
+
+        // tokens are single use
+        let response = srv.reset_password(&token, "password 3");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_token");
+    }
+
+    #[test]
+    fn test_request_password_reset_unknown_email() {
+        let outbox = Outbox::default();
+        let mut srv =
+            create_memory_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let response = srv.request_password_reset("test_reset_10@gmail.com");
+        assert!(response.status().is_success());
+        assert!(outbox.lock().unwrap().is_empty());
+
+        // limited like registered emails, so they can't be told apart
+        for _ in 1..MAX_PASSWORD_RESETS_PER_HOUR {
+            let response = srv.request_password_reset("test_reset_10@gmail.com");
+            assert!(response.status().is_success());
+        }
+
+        let response = srv.request_password_reset("test_reset_10@gmail.com");
+        assert_eq!(response.status().as_u16(), 429);
+    }
+
+    #[test]
+    fn test_reset_keeps_rate_limit() {
+        let outbox = Outbox::default();
+        let mut srv =
+            create_memory_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let email = "test_reset_3@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        srv.test_create_new_user(new_user);
+
+        for round in 0..MAX_PASSWORD_RESETS_PER_HOUR {
+            let response = srv.request_password_reset(email);
+            assert!(response.status().is_success());
+
+            let token = reset_token(&outbox, email);
+            let password = format!("password {}", round + 2);
+            let response = srv.reset_password(&token, &password);
+            assert!(response.status().is_success());
+        }
+
+        // redeemed tokens still count
+        let response = srv.request_password_reset(email);
+        assert_eq!(response.status().as_u16(), 429);
+    }
+
+    #[test]
+    fn test_reset_password_page() {
+        let mut srv = create_memory_test_server();
+
+        let request = srv
+            .client(
+                actix_web::http::Method::GET,
+                "/api/v1/users/reset_password?token=abc",
+            )
+            .finish()
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.content_type(), "text/html");
+
+        let bytes = srv.execute(response.body()).unwrap();
+        assert!(String::from_utf8_lossy(&bytes).contains("<form"));
+    }
+
+    #[test]
+    fn test_request_password_reset_rate_limit() {
+        let mut srv = create_memory_test_server();
+
+        let email = "test_reset_2@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        srv.test_create_new_user(new_user);
+
+        for _ in 0..MAX_PASSWORD_RESETS_PER_HOUR {
+            let response = srv.request_password_reset(email);
+            assert!(response.status().is_success());
+        }
+
+        let response = srv.request_password_reset(email);
+        assert_eq!(response.status().as_u16(), 429);
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "too_many_requests");
+    }
+
+    #[test]
+    fn test_reset_password_weak_password() {
+        let mut srv = create_memory_test_server();
+
+        let response = srv.reset_password("not a token", "short");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "weak_password");
+
+        assert!(response.status().is_client_error());
+    }
+}
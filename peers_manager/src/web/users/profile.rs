@@ -0,0 +1,318 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, Error, FutureResponse, HttpResponse, Json, Path, State};
+
+use super::super::super::db::users::{DeleteUser, GetUser, UpdateUser};
+use super::super::auth::AuthenticatedUser;
+use super::super::authorization::UserView;
+use super::super::error::{api_result, fail, ApiError};
+use super::super::request_id::RequestId;
+use super::create::validate_email_request;
+use super::verify::send_verification_email;
+use models::UserRole;
+use web::email_validator::EmailVerdict;
+
+/// Fields of the current user to change, missing ones are kept
+#[derive(Deserialize, Serialize, Default)]
+pub struct UpdateUserInput {
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Returns the user owning the `auth-token` of the request
+pub fn current_user(user: AuthenticatedUser) -> HttpResponse {
+    HttpResponse::Ok().json(UserView::own(user.0))
+}
+
+/// Returns the profile of any user by id, suspended users are only found by
+/// moderators and admins
+pub fn get_user(
+    (id, viewer, state, request_id): (
+        Path<i64>,
+        Option<AuthenticatedUser>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let viewer = viewer.map(|viewer| viewer.0);
+    let sees_suspended = viewer
+        .as_ref()
+        .map_or(false, |viewer| viewer.role().includes(UserRole::Moderator));
+
+    // send async `GetUser` message to a `UsersExecutor`
+    state
+        .users
+        .send(
+            &request_id,
+            GetUser {
+                id: id.into_inner(),
+            },
+        )
+        .then(api_result)
+        .and_then(move |user| {
+            user.filter(|user| sees_suspended || !user.is_suspended())
+                .ok_or(ApiError::UserNotFound)
+        })
+        .map(move |user| HttpResponse::Ok().json(UserView::new(user, viewer.as_ref())))
+        .from_err()
+        .responder()
+}
+
+/// Changes the profile of the current user, a new email is validated and has
+/// to be verified again.
+pub fn update_current_user(
+    (user, update, state, request_id): (
+        AuthenticatedUser,
+        Json<UpdateUserInput>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let user = user.0;
+    let update = update.into_inner();
+
+    let users = state.users.clone();
+    let mailer = state.mailer.clone();
+    let link_base_url = state.verification.get().link_base_url.clone();
+
+    // an unchanged email needs neither validation nor verification
+    let current_email = user.email.clone();
+    let new_email: FutureResponse<Result<Option<String>, EmailVerdict>> = match update.email {
+        Some(ref new_email) if *new_email != user.email => Box::new(
+            validate_email_request(state.email_validator.clone(), &request_id, new_email).map(
+                move |verdict| match verdict {
+                    EmailVerdict::Valid(ref email) if *email == current_email => Ok(None),
+                    EmailVerdict::Valid(email) => Ok(Some(email)),
+                    refused => Err(refused),
+                },
+            ),
+        ),
+        _ => Box::new(futures::future::ok(Ok(None))),
+    };
+
+    new_email
+        .and_then(move |new_email| -> FutureResponse<HttpResponse> {
+            let new_email = match new_email {
+                Ok(new_email) => new_email,
+                Err(refused) => return fail(ApiError::InvalidEmail(refused)),
+            };
+
+            let email_changed = new_email.is_some();
+            let mail_users = users.clone();
+
+            // send async `UpdateUser` message to a `UsersExecutor`
+            users
+                .send(
+                    &request_id,
+                    UpdateUser {
+                        id: user.id,
+                        name: update.name,
+                        about: update.about,
+                        email: new_email,
+                    },
+                )
+                .then(api_result)
+                .from_err()
+                .and_then(move |user| -> FutureResponse<HttpResponse> {
+                    if !email_changed {
+                        return Box::new(futures::future::ok(
+                            HttpResponse::Ok().json(UserView::own(user)),
+                        ));
+                    }
+
+                    Box::new(
+                        send_verification_email(
+                            mail_users,
+                            request_id,
+                            mailer,
+                            link_base_url,
+                            &user,
+                        )
+                        .then(move |sent| {
+                            if let Err(error) = sent {
+                                error!("Failed to send verification email: {}", error);
+                            }
+                            Ok::<_, Error>(HttpResponse::Ok().json(UserView::own(user)))
+                        }),
+                    )
+                })
+                .responder()
+        })
+        .responder()
+}
+
+/// Deletes the account of the current user together with its sessions
+pub fn delete_current_user(
+    (user, state, request_id): (AuthenticatedUser, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    // send async `DeleteUser` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, DeleteUser { id: user.0.id })
+        .then(api_result)
+        .map(|()| HttpResponse::NoContent().finish())
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod profile_tests {
+
+    use super::super::super::app::VerificationSettings;
+    use super::*;
+    use actix_web::HttpMessage;
+    use mail::memory::Outbox;
+    use models::User;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_current_user() {
+        let mut srv = create_memory_test_server();
+
+        let email = "test_me_1@gmail.com";
+
+        let token = srv.test_create_new_user(new_user(email));
+
+        let response = srv.current_user(Some(&token));
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let user: User = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(user.email, email);
+        assert_eq!(user.name, "name 1");
+        assert_eq!(user.about, "about 1");
+    }
+
+    #[test]
+    fn test_current_user_requires_token() {
+        let mut srv = create_memory_test_server();
+
+        let response = srv.current_user(None);
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = srv.current_user(Some("not a token"));
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    #[test]
+    fn test_get_user() {
+        let mut srv = create_memory_test_server();
+
+        let email = "test_profile_1@gmail.com";
+        let token = srv.test_create_new_user(new_user(email));
+
+        let response = srv.current_user(Some(&token));
+        let bytes = srv.execute(response.body()).unwrap();
+        let me: User = serde_json::from_slice(&bytes).unwrap();
+
+        // the email is only shown to its owner and admins
+        let response = srv.get_user(me.id);
+        assert!(response.status().is_success());
+        let bytes = srv.execute(response.body()).unwrap();
+        let user: UserView = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(user.id, me.id);
+        assert_eq!(user.name, "name 1");
+        assert_eq!(user.email, None);
+        assert_eq!(user.role, UserRole::User);
+
+        let response = srv.get_user(me.id + 1_000_000);
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    #[test]
+    fn test_update_current_user() {
+        let mut srv = create_memory_test_server();
+
+        let token = srv.test_create_new_user(new_user("test_profile_2@gmail.com"));
+
+        let update = UpdateUserInput {
+            about: Some("about 2".to_string()),
+            email: Some("test_profile_3@gmail.com".to_string()),
+            ..UpdateUserInput::default()
+        };
+        let response = srv.update_current_user(&token, update);
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let user: User = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(user.name, "name 1");
+        assert_eq!(user.about, "about 2");
+        assert_eq!(user.email, "test_profile_3@gmail.com");
+        assert!(!user.email_verified);
+    }
+
+    #[test]
+    fn test_update_email_drops_verification_tokens() {
+        let outbox = Outbox::default();
+        let mut srv =
+            create_memory_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let token = srv.test_create_new_user(new_user("test_profile_7@gmail.com"));
+        let old_token = verification_token(&outbox, "test_profile_7@gmail.com");
+
+        let update = UpdateUserInput {
+            email: Some("test_profile_8@gmail.com".to_string()),
+            ..UpdateUserInput::default()
+        };
+        let response = srv.update_current_user(&token, update);
+        assert!(response.status().is_success());
+
+        // the link mailed to the previous email doesn't verify the new one
+        let response = srv.verify_email(&old_token);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_token");
+
+        let new_token = verification_token(&outbox, "test_profile_8@gmail.com");
+        let response = srv.verify_email(&new_token);
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn test_update_current_user_email_conflicts() {
+        let mut srv = create_memory_test_server();
+
+        srv.test_create_new_user(new_user("test_profile_4@gmail.com"));
+        let token = srv.test_create_new_user(new_user("test_profile_5@gmail.com"));
+
+        let update = UpdateUserInput {
+            email: Some("test_profile_4@gmail.com".to_string()),
+            ..UpdateUserInput::default()
+        };
+        let response = srv.update_current_user(&token, update);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "user_already_exists");
+        assert!(response.status().is_client_error());
+
+        let update = UpdateUserInput {
+            email: Some("email 1".to_string()),
+            ..UpdateUserInput::default()
+        };
+        let response = srv.update_current_user(&token, update);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_email");
+    }
+
+    #[test]
+    fn test_delete_current_user() {
+        let mut srv = create_memory_test_server();
+
+        let email = "test_profile_6@gmail.com";
+        let token = srv.test_create_new_user(new_user(email));
+
+        let response = srv.delete_current_user(&token);
+        assert!(response.status().is_success());
+
+        let response = srv.current_user(Some(&token));
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = srv.login_user(email, "password 1");
+        assert!(response.status().is_client_error());
+    }
+}
@@ -1,22 +1,302 @@
 use super::super::app::AppState;
 
+use actix_web::http::header::{HeaderValue, LINK};
 use futures::Future;
 
-use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, State};
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, Query, State};
 
-use super::super::super::db::users::SearchWithKeyword;
+use super::super::super::db::search::{SearchResults, SearchUsers, SearchWithKeyword};
+use super::super::auth::AuthenticatedUser;
+use super::super::authorization::UserView;
+use super::super::error::api_result;
+use super::super::metrics::MeteredAddr;
+use super::super::request_id::RequestId;
+use db::repository::UsersExecutor;
+use models::{self, UserRole};
 
+/// Page of search results as the viewer may see them
+#[derive(Serialize, Deserialize)]
+pub struct UserPage {
+    pub users: Vec<UserView>,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+    pub total: Option<i64>,
+}
+
+impl UserPage {
+    pub fn new(results: SearchResults, viewer: Option<&models::User>) -> UserPage {
+        UserPage {
+            users: results
+                .users
+                .into_iter()
+                .map(|user| UserView::new(user, viewer))
+                .collect(),
+            next_cursor: results.next_cursor,
+            total: results.total,
+        }
+    }
+}
+
+/// Page of the users matching all the given filters, pass the returned
+/// `next_cursor` as `cursor` to get the next one. Suspended users are only
+/// found by moderators and admins, and users are only found by their email
+/// by admins.
+///
+/// `GET /users?query=&name=&email=&limit=&cursor=&sort=relevance|name|id&with_total=`
+pub fn search_users(
+    (search, viewer, state, request_id): (
+        Query<SearchUsers>,
+        Option<AuthenticatedUser>,
+        State<AppState>,
+        RequestId,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let mut search = search.into_inner();
+    search.only_verified = state.verification.get().required_for_search;
+
+    db_search_users(state.users.clone(), &request_id, search, viewer)
+}
+
+/// Deprecated `POST /api/v1/users/search`, kept for existing clients. Same as
+/// `GET /api/v1/users?query=<keyword>`.
 pub fn user_search(
-    (search, state): (Json<SearchWithKeyword>, State<AppState>),
+    (search, viewer, state, request_id): (
+        Json<SearchWithKeyword>,
+        Option<AuthenticatedUser>,
+        State<AppState>,
+        RequestId,
+    ),
 ) -> FutureResponse<HttpResponse> {
-    // send async `SearchWithKeyword` message to a `DbExecutor`
-    state
-        .db
-        .send(search.into_inner())
+    let mut search = SearchUsers::from(search.into_inner());
+    search.only_verified = state.verification.get().required_for_search;
+
+    Box::new(
+        db_search_users(state.users.clone(), &request_id, search, viewer).map(|mut response| {
+            response
+                .headers_mut()
+                .insert("deprecation", HeaderValue::from_static("true"));
+            response.headers_mut().insert(
+                LINK,
+                HeaderValue::from_static("</api/v1/users>; rel=\"successor-version\""),
+            );
+            response
+        }),
+    )
+}
+
+fn db_search_users(
+    users: MeteredAddr<UsersExecutor>,
+    request_id: &RequestId,
+    mut search: SearchUsers,
+    viewer: Option<AuthenticatedUser>,
+) -> FutureResponse<HttpResponse> {
+    let viewer = viewer.map(|viewer| viewer.0);
+    search.include_suspended = viewer
+        .as_ref()
+        .map_or(false, |viewer| viewer.role().includes(UserRole::Moderator));
+    // emails are hidden from the others, searching them would reveal them
+    search.match_email = viewer
+        .as_ref()
+        .map_or(false, |viewer| viewer.role().includes(UserRole::Admin));
+
+    // send async `SearchUsers` message to a `UsersExecutor`
+    users
+        .send(request_id, search)
+        .then(api_result)
+        .map(move |results| HttpResponse::Ok().json(UserPage::new(results, viewer.as_ref())))
         .from_err()
-        .and_then(|res| match res {
-            Ok(user) => Ok(HttpResponse::Ok().json(user)),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
-        })
         .responder()
 }
+
+#[cfg(test)]
+mod search_tests {
+
+    use super::super::create::NewUserInput;
+    use super::*;
+    use actix_web::client::ClientResponse;
+    use actix_web::test::TestServer;
+    use actix_web::HttpMessage;
+    use db::accounts::SetRole;
+    use db::memory::MemoryUserRepository;
+    use db::repository::UserRepository;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    fn create_users(srv: &mut TestServer, prefix: &str) {
+        for (name, about) in &[
+            ("Bob", "plays guitar sometimes"),
+            ("Alice Guitar", "jazz"),
+            ("Carol", "singer and guitar teacher"),
+        ] {
+            srv.test_create_new_user(NewUserInput {
+                name: name.to_string(),
+                email: format!(
+                    "{}_{}@gmail.com",
+                    prefix,
+                    name.to_lowercase().replace(' ', "_")
+                ),
+                about: about.to_string(),
+                password: "password 1".to_string(),
+            });
+        }
+    }
+
+    fn results(srv: &mut TestServer, response: ClientResponse) -> UserPage {
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// Creates an admin, returns its session token
+    fn create_admin(
+        srv: &mut TestServer,
+        repository: &mut MemoryUserRepository,
+        email: &str,
+    ) -> String {
+        let token = srv.test_create_new_user(NewUserInput {
+            name: "Admin".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        });
+
+        let response = srv.current_user(Some(&token));
+        let bytes = srv.execute(response.body()).unwrap();
+        let admin: models::User = serde_json::from_slice(&bytes).unwrap();
+        repository
+            .set_role(SetRole {
+                id: admin.id,
+                role: UserRole::Admin,
+            })
+            .unwrap();
+
+        token
+    }
+
+    fn names(results: &UserPage) -> Vec<&str> {
+        results
+            .users
+            .iter()
+            .map(|user| user.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_search_ranked_pages() {
+        let mut srv = create_memory_test_server();
+        create_users(&mut srv, "test_search_1");
+
+        // a match in the name ranks above a match in the about
+        let response = srv.search_users("query=guitar&limit=2&with_total=true");
+        let page = results(&mut srv, response);
+        assert_eq!(page.total, Some(3));
+        assert_eq!(names(&page)[0], "Alice Guitar");
+        assert_eq!(page.users.len(), 2);
+
+        let cursor = page.next_cursor.unwrap();
+        let response = srv.search_users(&format!("query=guitar&limit=2&cursor={}", cursor));
+        let page = results(&mut srv, response);
+        assert_eq!(page.users.len(), 1);
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.total, None);
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let mut srv = create_memory_test_server();
+        create_users(&mut srv, "test_search_2");
+
+        // filters combine
+        let response = srv.search_users("query=guitar&name=o&sort=name");
+        let page = results(&mut srv, response);
+        assert_eq!(names(&page), vec!["Bob", "Carol"]);
+
+        let response = srv.search_users("name=GUITAR");
+        let page = results(&mut srv, response);
+        assert_eq!(names(&page), vec!["Alice Guitar"]);
+
+        // wildcards are matched literally
+        let response = srv.search_users("name=%25");
+        let page = results(&mut srv, response);
+        assert!(page.users.is_empty());
+    }
+
+    #[test]
+    fn test_email_search_admin_only() {
+        let mut repository = MemoryUserRepository::new();
+        let mut srv = create_memory_test_server_on(repository.clone());
+        create_users(&mut srv, "test_search_4");
+        let user_token = srv.test_create_new_user(NewUserInput {
+            name: "Dave".to_string(),
+            email: "test_search_4_dave@gmail.com".to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        });
+        let admin_token = create_admin(&mut srv, &mut repository, "test_search_4_admin@gmail.com");
+
+        // the others can't tell whether an email is registered
+        for token in &[None, Some(user_token.as_str())] {
+            for query in &[
+                "email=test_search_4_carol@gmail.com",
+                "query=test_search_4_carol@gmail.com",
+            ] {
+                let response = srv.search_users_as(*token, query);
+                let page = results(&mut srv, response);
+                assert!(page.users.is_empty());
+            }
+        }
+
+        let response = srv.search_users_as(
+            Some(&admin_token),
+            "email=Test_Search_4_Carol@gmail.com&query=jazz",
+        );
+        let page = results(&mut srv, response);
+        assert!(page.users.is_empty());
+
+        for query in &[
+            "email=Test_Search_4_Carol@gmail.com",
+            "query=test_search_4_carol@gmail.com",
+        ] {
+            let response = srv.search_users_as(Some(&admin_token), query);
+            let page = results(&mut srv, response);
+            assert_eq!(names(&page), vec!["Carol"]);
+        }
+    }
+
+    #[test]
+    fn test_deprecated_search() {
+        let mut srv = create_memory_test_server();
+        create_users(&mut srv, "test_search_3");
+
+        let response = srv.legacy_search_users(SearchWithKeyword {
+            keyword: "guitar teacher".to_string(),
+            limit: None,
+            cursor: None,
+            with_total: false,
+        });
+        assert!(response.headers().contains_key("deprecation"));
+
+        let page = results(&mut srv, response);
+        assert_eq!(names(&page), vec!["Carol"]);
+    }
+
+    #[test]
+    fn test_search_invalid_cursor() {
+        let mut srv = create_memory_test_server();
+
+        let response = srv.search_users("query=guitar&cursor=page");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_cursor");
+
+        assert!(response.status().is_client_error());
+
+        // no next page past the largest offset
+        let response = srv.search_users("query=guitar&cursor=9223372036854775807");
+        assert_eq!(response.status().as_u16(), 400);
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_cursor");
+    }
+}
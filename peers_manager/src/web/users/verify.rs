@@ -0,0 +1,157 @@
+use super::super::app::AppState;
+
+use futures::Future;
+
+use actix_web::{AsyncResponder, Error, FutureResponse, HttpResponse, Query, State};
+
+use super::super::super::db::verification::{IssueVerificationToken, VerifyEmail};
+use super::super::authorization::UserView;
+use super::super::error::api_result;
+use super::super::metrics::MeteredAddr;
+use super::super::request_id::RequestId;
+use super::super::routes::API_PREFIX;
+use db::repository::UsersExecutor;
+use mail::{Email, MailExecutor, SendMail};
+use models;
+
+/// Marks the email of the token's owner as verified, the link to this
+/// endpoint is sent by email on registration. Only the owner received the
+/// token, it is shown its own profile.
+pub fn verify_email(
+    (verify, state, request_id): (Query<VerifyEmail>, State<AppState>, RequestId),
+) -> FutureResponse<HttpResponse> {
+    let verify = VerifyEmail {
+        canonicalization: state.canonicalization,
+        ..verify.into_inner()
+    };
+
+    // send async `VerifyEmail` message to a `UsersExecutor`
+    state
+        .users
+        .send(&request_id, verify)
+        .then(api_result)
+        .map(|user| HttpResponse::Ok().json(UserView::own(user)))
+        .from_err()
+        .responder()
+}
+
+/// Issues a verification token for the user and mails the link redeeming it
+pub fn send_verification_email(
+    users: MeteredAddr<UsersExecutor>,
+    request_id: RequestId,
+    mailer: actix::Addr<MailExecutor>,
+    link_base_url: String,
+    user: &models::User,
+) -> impl Future<Item = (), Error = Error> {
+    let to = user.email.clone();
+
+    users
+        .send(&request_id, IssueVerificationToken { user_id: user.id })
+        .then(api_result)
+        .and_then(move |verification| {
+            let email = Email {
+                to,
+                subject: "Verify your email".to_string(),
+                body: format!(
+                    "Please confirm your email address by opening the link below:\n\n{}{}/users/verify_email?token={}",
+                    link_base_url, API_PREFIX, verification.token
+                ),
+            };
+
+            mailer.send(SendMail(email)).then(api_result)
+        })
+        .from_err()
+}
+
+#[cfg(test)]
+mod verify_email_tests {
+
+    use super::super::super::app::VerificationSettings;
+    use super::super::create::*;
+    use super::*;
+    use actix_web::HttpMessage;
+    use mail::memory::Outbox;
+    use web::error::ErrorBody;
+    use web::tests_tools::*;
+
+    #[test]
+    fn test_verify_email() {
+        let outbox = Outbox::default();
+        let mut srv =
+            create_memory_test_server_with(VerificationSettings::default(), outbox.clone());
+
+        let email = "test_verify_1@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        srv.test_create_new_user(new_user);
+
+        let token = verification_token(&outbox, email);
+        let response = srv.verify_email(&token);
+        assert!(response.status().is_success());
+
+        let bytes = srv.execute(response.body()).unwrap();
+        let user: models::User = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(user.email, email);
+        assert!(user.email_verified);
+
+        // tokens are single use
+        let response = srv.verify_email(&token);
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_verify_email_invalid_token() {
+        let mut srv = create_memory_test_server();
+
+        let response = srv.verify_email("not a token");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "invalid_token");
+
+        assert!(response.status().is_client_error());
+    }
+
+    #[test]
+    fn test_login_requires_verified_email() {
+        let outbox = Outbox::default();
+        let verification = VerificationSettings {
+            required_for_login: true,
+            ..VerificationSettings::default()
+        };
+        let mut srv = create_memory_test_server_with(verification, outbox.clone());
+
+        let email = "test_verify_2@gmail.com";
+
+        let new_user = NewUserInput {
+            name: "name 1".to_string(),
+            email: email.to_string(),
+            about: "about 1".to_string(),
+            password: "password 1".to_string(),
+        };
+        // registering doesn't log in either
+        let response = srv.create_user(new_user);
+        assert_eq!(response.status().as_u16(), 201);
+        let bytes = srv.execute(response.body()).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.get("token").is_none());
+        assert_eq!(body["email"], email);
+
+        let response = srv.login_user(email, "password 1");
+        let bytes = srv.execute(response.body()).unwrap();
+        let error_data: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error_data.code, "email_not_verified");
+        assert!(response.status().is_client_error());
+
+        let token = verification_token(&outbox, email);
+        let response = srv.verify_email(&token);
+        assert!(response.status().is_success());
+
+        let response = srv.login_user(email, "password 1");
+        assert!(response.status().is_success());
+    }
+}